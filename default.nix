@@ -4,6 +4,7 @@ stdenv.mkDerivation {
   name = "whim";
   buildInputs = [
     openssl
+    sqlite
   ];
   shellHook = ''
     export OPENSSL_DIR="${openssl.dev}"
@@ -0,0 +1,191 @@
+use chrono::Duration;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tokio_core::reactor::Core;
+
+use engine::Engine;
+use gdax::fees::FeeSchedule;
+use gdax::feed::message::{BaseCurrencyAmount, Product};
+use gdax::feed::replay::ReplayBuilder;
+use gdax::products::Products;
+use gdax::simulator::{QueueModel, Simulator};
+use report::{Report, Reporter};
+use risk::{RiskLimits, RiskViolation};
+use strategy::{MarketMaker, Strategy};
+
+use errors::*;
+
+/// Run `strategy` over the `product` messages in `capture`, sending its
+/// orders to `simulator`. Returns the report and the risk limit breach that
+/// stopped the run early, if any.
+fn run<S: Strategy>(
+  strategy:  S,
+  simulator: Simulator,
+  limits:    RiskLimits,
+  product:   Product,
+  interval:  Duration,
+  capture:   &Path,
+) -> Result<(Report, Option<RiskViolation>), Error> {
+  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
+
+  let replay = ReplayBuilder::new()
+    .product(product)
+    .open(capture, &core.handle())
+    .chain_err(|| format!("failed to open capture file: {}", capture.display()))?;
+
+  let engine = Engine::new(Reporter::new(strategy, product), simulator)
+    .timer(interval)
+    .risk_limits(limits);
+
+  let engine = core.run(engine.run(replay))?;
+
+  Ok((engine.strategy().report(engine.context()), engine.risk().breach().cloned()))
+}
+
+fn write_report(report: &Report, output: &Path) -> Result<(), Error> {
+  fs::create_dir_all(output)
+    .chain_err(|| format!("failed to create output directory: {}", output.display()))?;
+
+  let create = |name: &str| {
+    let path = output.join(name);
+    File::create(&path)
+      .map(BufWriter::new)
+      .chain_err(|| format!("failed to create report file: {}", path.display()))
+  };
+
+  report.write_json(create("report.json")?).chain_err(|| "failed to write report.json")?;
+  report.write_pnl_csv(create("pnl.csv")?).chain_err(|| "failed to write pnl.csv")?;
+  report.write_fills_csv(create("fills.csv")?).chain_err(|| "failed to write fills.csv")?;
+  Ok(())
+}
+
+pub fn backtest(
+  capture:       PathBuf,
+  products_file: PathBuf,
+  product:       Product,
+  size:          BaseCurrencyAmount,
+  interval:      Duration,
+  latency:       Duration,
+  queue:         QueueModel,
+  fee_schedule:  Option<PathBuf>,
+  fee_tier:      Option<usize>,
+  risk_limits:   Option<PathBuf>,
+  output:        Option<PathBuf>,
+) -> Result<(), Error> {
+  info!("command: backtest");
+
+  let products = Products::load(&products_file)
+    .chain_err(|| format!("failed to load products file: {}", products_file.display()))?;
+
+  let limits = match risk_limits {
+    Some(path) => RiskLimits::load(&path)
+      .chain_err(|| format!("failed to load risk limits file: {}", path.display()))?,
+    None => RiskLimits::default(),
+  };
+
+  let mut schedule = match fee_schedule {
+    Some(path) => FeeSchedule::load(&path)
+      .chain_err(|| format!("failed to load fee schedule file: {}", path.display()))?,
+    None => FeeSchedule::gdax(),
+  };
+  if let Some(index) = fee_tier {
+    let tier = *schedule.tiers().get(index)
+      .ok_or_else(|| format!("fee schedule has no tier {}", index))?;
+    schedule = FeeSchedule::flat(tier.maker, tier.taker);
+  }
+
+  let simulator = Simulator::new(products)
+    .latency(latency)
+    .queue_model(queue)
+    .fee_schedule(schedule);
+
+  let (report, breach) = run(MarketMaker::new(product, size), simulator, limits, product, interval, &capture)?;
+
+  if let Some(output) = output {
+    write_report(&report, &output)?;
+  }
+
+  println!("fills:        {}", report.fills.len());
+  println!("turnover:     {}", report.turnover);
+  println!("fees:         {}", report.fees);
+  println!("inventory:    {}", report.inventory);
+  println!("realized pnl: {}", report.position.realized_pnl);
+  println!("pnl:          {}", report.pnl);
+  println!("max drawdown: {}", report.max_drawdown);
+  match report.sharpe {
+    Some(sharpe) => println!("sharpe:       {:.4}", sharpe),
+    None         => println!("sharpe:       n/a"),
+  }
+  if let Some(breach) = breach {
+    println!("halted:       {}", breach);
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{TimeZone, Utc};
+  use std::{env, process};
+
+  use gdax::feed::capture::{CaptureWriter, Frame};
+  use gdax::fixtures::{products, snapshot_text, trade_text};
+
+  fn backtest(capture: &Path) -> (Report, Vec<u8>, Vec<u8>, Vec<u8>) {
+    let product = "BTC-USD".parse().unwrap();
+
+    let simulator = Simulator::new(products())
+      .queue_model(QueueModel::Front)
+      .fee_schedule(FeeSchedule::gdax());
+
+    let (report, breach) = run(
+      MarketMaker::new(product, "1.0".parse().unwrap()),
+      simulator,
+      RiskLimits::default(),
+      product,
+      Duration::seconds(10),
+      capture,
+    ).unwrap();
+    assert_eq!(breach, None);
+
+    let (mut json, mut pnl, mut fills) = (vec![], vec![], vec![]);
+    report.write_json(&mut json).unwrap();
+    report.write_pnl_csv(&mut pnl).unwrap();
+    report.write_fills_csv(&mut fills).unwrap();
+    (report, json, pnl, fills)
+  }
+
+  #[test]
+  fn deterministic() {
+    let path = env::temp_dir().join(format!("whim-backtest-{}.capture", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut writer = CaptureWriter::open(&path).unwrap();
+    for (seconds, text) in vec![
+      (0, snapshot_text()),
+      (5, trade_text(5, "sell", "101.00", "1.5")),
+      (10, trade_text(10, "buy", "100.00", "1.5")),
+      (15, r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["buy", "100.50", "1.0"]]}"#.to_string()),
+      (20, trade_text(20, "buy", "100.50", "0.5")),
+      (30, trade_text(30, "sell", "101.00", "1.0")),
+    ] {
+      writer.write(&Frame{received: Utc.timestamp(seconds, 0), text}).unwrap();
+    }
+
+    let first = backtest(&path);
+    let second = backtest(&path);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(first, second);
+
+    let report = first.0;
+    assert_eq!(report.fills.len(), 4);
+    assert_eq!(report.turnover, "352.25".parse().unwrap());
+    assert_eq!(report.fees, "0".parse().unwrap());
+    assert_eq!(report.samples.len(), 2);
+    assert_eq!(report.inventory, "-0.5".parse().unwrap());
+    assert_eq!(report.pnl, "1.375".parse().unwrap());
+  }
+}
@@ -1,8 +1,79 @@
+use chrono;
 use clap::{App, Arg, ArgMatches, AppSettings, SubCommand};
 
 use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::str::FromStr;
 use errors::*;
 
+use gdax::feed::message::{BaseCurrencyAmount, Channel, DateTime, Product, QuoteCurrencyPrice, Side};
+use gdax::rest::message::{NewOrder, TimeInForce};
+use gdax::simulator::QueueModel;
+use uuid::Uuid;
+use websocket::url::Url;
+
+fn validate<T: FromStr>(value: String) -> Result<(), String>
+  where T::Err: ToString
+{
+  value.parse::<T>().map(|_| ()).map_err(|error| error.to_string())
+}
+
+fn validate_speed(value: String) -> Result<(), String> {
+  let speed = value.parse::<f64>().map_err(|error| error.to_string())?;
+  if speed.is_finite() && speed > 0.0 {
+    Ok(())
+  } else {
+    Err(format!("speed must be a finite number greater than zero, got `{}`", value))
+  }
+}
+
+fn validate_interval(value: String) -> Result<(), String> {
+  match value.parse::<u32>().map_err(|error| error.to_string())? {
+    0 => Err("interval must be greater than zero".to_string()),
+    _ => Ok(()),
+  }
+}
+
+fn validate_feed_url(value: String) -> Result<(), String> {
+  let url = value.parse::<Url>().map_err(|error| error.to_string())?;
+  match url.scheme() {
+    "ws" | "wss" => Ok(()),
+    scheme       => Err(format!("unsupported feed URL scheme `{}`, expected `ws` or `wss`", scheme)),
+  }
+}
+
+fn validate_rest_url(value: String) -> Result<(), String> {
+  let url = value.parse::<Url>().map_err(|error| error.to_string())?;
+  match url.scheme() {
+    "http" | "https" => Ok(()),
+    scheme           => Err(format!("unsupported REST URL scheme `{}`, expected `http` or `https`", scheme)),
+  }
+}
+
+/// Arguments shared by subcommands that send orders
+fn order_entry_args<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+  subcommand
+    .arg(Arg::with_name("SANDBOX").long("sandbox"))
+    .arg(Arg::with_name("REST_URL")
+         .long("rest-url")
+         .takes_value(true)
+         .conflicts_with("SANDBOX")
+         .validator(validate_rest_url)
+         .help("send requests to REST API at <REST_URL> instead of GDAX, e.g. http://localhost:8080"))
+    .arg(Arg::with_name("CREDENTIALS")
+         .long("credentials")
+         .takes_value(true)
+         .help("sign requests with API key from JSON file <CREDENTIALS>, instead of from GDAX_API_KEY, GDAX_API_SECRET, and GDAX_API_PASSPHRASE"))
+    .arg(Arg::with_name("DRY_RUN")
+         .long("dry-run")
+         .help("validate and log orders without sending them"))
+}
+
+fn parse<T: FromStr>(value: &str) -> T {
+  value.parse().ok().expect("value was not validated")
+}
+
 pub fn parse_command_line<'a, I, T>(arguments: I) -> Result<ArgMatches<'a>, Error>
   where I: 'a + IntoIterator<Item = T>,
         T: Into<OsString> + Clone,
@@ -17,6 +88,237 @@ pub fn parse_command_line<'a, I, T>(arguments: I) -> Result<ArgMatches<'a>, Erro
       SubCommand::with_name("record")
         .about("connect to GDAX and record real-time market data")
         .arg(Arg::with_name("SANDBOX").long("sandbox"))
+        .arg(Arg::with_name("FEED_URL")
+             .long("feed-url")
+             .takes_value(true)
+             .conflicts_with("SANDBOX")
+             .validator(validate_feed_url)
+             .help("connect to websocket feed at <FEED_URL> instead of GDAX, e.g. ws://localhost:8080"))
+        .arg(Arg::with_name("REST_URL")
+             .long("rest-url")
+             .takes_value(true)
+             .conflicts_with("SANDBOX")
+             .validator(validate_rest_url)
+             .help("fetch the products listing from REST API at <REST_URL> instead of GDAX, e.g. http://localhost:8080"))
+        .arg(Arg::with_name("DATABASE")
+             .long("database")
+             .takes_value(true)
+             .help("store received messages in SQLite database at <DATABASE>"))
+        .arg(Arg::with_name("CAPTURE")
+             .long("capture")
+             .takes_value(true)
+             .help("append raw received frames to capture file at <CAPTURE>"))
+        .arg(Arg::with_name("RESYNC_ON_GAP")
+             .long("resync-on-gap")
+             .help("also reconnect to resync order books when a full channel sequence gap is detected, as is always done when an order book becomes invalid"))
+        .arg(Arg::with_name("PRODUCT")
+             .long("product")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .validator(validate::<Product>)
+             .help("subscribe to <PRODUCT>, e.g. BTC-USD, instead of all listed products"))
+        .arg(Arg::with_name("PRODUCTS_FILE")
+             .long("products-file")
+             .takes_value(true)
+             .help("read the products listing from JSON file <PRODUCTS_FILE> instead of fetching it from GDAX"))
+        .arg(Arg::with_name("CHANNEL")
+             .long("channel")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .validator(validate::<Channel>)
+             .help("subscribe to <CHANNEL>, e.g. level2, instead of all public channels"))
+        .arg(Arg::with_name("CREDENTIALS")
+             .long("credentials")
+             .takes_value(true)
+             .help("sign subscriptions with API key from JSON file <CREDENTIALS>, instead of from GDAX_API_KEY, GDAX_API_SECRET, and GDAX_API_PASSPHRASE"))
+    )
+    .subcommand(
+      SubCommand::with_name("replay")
+        .about("replay market data from a capture file")
+        .arg(Arg::with_name("CAPTURE")
+             .required(true)
+             .help("capture file written by `whim record --capture`"))
+        .arg(Arg::with_name("DATABASE")
+             .long("database")
+             .takes_value(true)
+             .help("store replayed messages in SQLite database at <DATABASE>"))
+        .arg(Arg::with_name("PRODUCT")
+             .long("product")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .validator(validate::<Product>)
+             .help("only replay messages for <PRODUCT>, e.g. BTC-USD"))
+        .arg(Arg::with_name("START")
+             .long("start")
+             .takes_value(true)
+             .validator(validate::<DateTime>)
+             .help("skip frames received before <START>, e.g. 2017-10-01T00:00:00Z"))
+        .arg(Arg::with_name("END")
+             .long("end")
+             .takes_value(true)
+             .validator(validate::<DateTime>)
+             .help("stop at the first frame received after <END>"))
+        .arg(Arg::with_name("SPEED")
+             .long("speed")
+             .takes_value(true)
+             .validator(validate_speed)
+             .help("replay at <SPEED> times the original pace, instead of as fast as possible"))
+    )
+    .subcommand(
+      SubCommand::with_name("mock-feed")
+        .about("serve a scripted or recorded feed on a local websocket, for testing")
+        .arg(Arg::with_name("PORT")
+             .long("port")
+             .takes_value(true)
+             .default_value("8080")
+             .validator(validate::<u16>)
+             .help("listen on 127.0.0.1:<PORT>"))
+        .arg(Arg::with_name("SCRIPT")
+             .long("script")
+             .takes_value(true)
+             .required_unless("CAPTURE")
+             .conflicts_with("CAPTURE")
+             .help("send each line of <SCRIPT>, either a JSON message or a directive like {\"mock\": \"ping\"}"))
+        .arg(Arg::with_name("CAPTURE")
+             .long("capture")
+             .takes_value(true)
+             .help("send the frames of capture file <CAPTURE>"))
+        .arg(Arg::with_name("INTERVAL")
+             .long("interval")
+             .takes_value(true)
+             .validator(validate::<u64>)
+             .help("wait <INTERVAL> milliseconds before sending each frame"))
+        .arg(Arg::with_name("CREDENTIALS")
+             .long("credentials")
+             .takes_value(true)
+             .help("verify signed subscriptions against API key from JSON file <CREDENTIALS>"))
+    )
+    .subcommand(
+      order_entry_args(SubCommand::with_name("place"))
+        .about("place a limit order, or a market order if no price is given")
+        .arg(Arg::with_name("PRODUCT")
+             .long("product")
+             .takes_value(true)
+             .required(true)
+             .validator(validate::<Product>)
+             .help("trade <PRODUCT>, e.g. BTC-USD"))
+        .arg(Arg::with_name("SIDE")
+             .long("side")
+             .takes_value(true)
+             .required(true)
+             .possible_values(&["buy", "sell"]))
+        .arg(Arg::with_name("SIZE")
+             .long("size")
+             .takes_value(true)
+             .required(true)
+             .validator(validate::<BaseCurrencyAmount>)
+             .help("buy or sell <SIZE> units of the base currency"))
+        .arg(Arg::with_name("PRICE")
+             .long("price")
+             .takes_value(true)
+             .validator(validate::<QuoteCurrencyPrice>)
+             .help("place a limit order at <PRICE>"))
+        .arg(Arg::with_name("POST_ONLY")
+             .long("post-only")
+             .requires("PRICE")
+             .help("reject the order instead of letting it take liquidity"))
+        .arg(Arg::with_name("TIME_IN_FORCE")
+             .long("time-in-force")
+             .takes_value(true)
+             .requires("PRICE")
+             .possible_values(&["GTC", "IOC", "FOK"]))
+        .arg(Arg::with_name("CLIENT_OID")
+             .long("client-oid")
+             .takes_value(true)
+             .validator(validate::<Uuid>)
+             .help("identify the order with <CLIENT_OID> instead of a random UUID"))
+        .arg(Arg::with_name("PRODUCTS_FILE")
+             .long("products-file")
+             .takes_value(true)
+             .help("validate against products listing in JSON file <PRODUCTS_FILE> instead of fetching it from GDAX"))
+        .arg(Arg::with_name("RISK_LIMITS")
+             .long("risk-limits")
+             .takes_value(true)
+             .help("check the order against risk limits in JSON file <RISK_LIMITS>, using the best bid and ask from GDAX"))
+    )
+    .subcommand(
+      order_entry_args(SubCommand::with_name("cancel"))
+        .about("cancel an order, or all open orders")
+        .arg(Arg::with_name("ORDER_ID")
+             .required_unless("ALL")
+             .conflicts_with("ALL")
+             .validator(validate::<Uuid>))
+        .arg(Arg::with_name("ALL")
+             .long("all")
+             .help("cancel all open orders"))
+        .arg(Arg::with_name("PRODUCT")
+             .long("product")
+             .takes_value(true)
+             .requires("ALL")
+             .validator(validate::<Product>)
+             .help("only cancel open orders for <PRODUCT>"))
+    )
+    .subcommand(
+      SubCommand::with_name("backtest")
+        .about("run the market maker strategy over a capture file against a simulated exchange")
+        .arg(Arg::with_name("CAPTURE")
+             .required(true)
+             .help("capture file written by `whim record --capture`"))
+        .arg(Arg::with_name("PRODUCTS_FILE")
+             .long("products-file")
+             .takes_value(true)
+             .required(true)
+             .help("validate orders against products listing in JSON file <PRODUCTS_FILE>"))
+        .arg(Arg::with_name("PRODUCT")
+             .long("product")
+             .takes_value(true)
+             .required(true)
+             .validator(validate::<Product>)
+             .help("trade <PRODUCT>, e.g. BTC-USD"))
+        .arg(Arg::with_name("SIZE")
+             .long("size")
+             .takes_value(true)
+             .required(true)
+             .validator(validate::<BaseCurrencyAmount>)
+             .help("quote <SIZE> units of the base currency on each side"))
+        .arg(Arg::with_name("INTERVAL")
+             .long("interval")
+             .takes_value(true)
+             .default_value("60")
+             .validator(validate_interval)
+             .help("sample P&L and inventory every <INTERVAL> seconds of feed time"))
+        .arg(Arg::with_name("LATENCY")
+             .long("latency")
+             .takes_value(true)
+             .default_value("0")
+             .validator(validate::<u32>)
+             .help("delay orders by <LATENCY> milliseconds before they reach the simulated exchange"))
+        .arg(Arg::with_name("QUEUE")
+             .long("queue")
+             .takes_value(true)
+             .default_value("back")
+             .validator(validate::<QueueModel>)
+             .help("join the queue at a price at the back, front, or behind a fraction of displayed size, e.g. 0.5"))
+        .arg(Arg::with_name("FEE_SCHEDULE")
+             .long("fee-schedule")
+             .takes_value(true)
+             .help("charge fees by volume tier from JSON file <FEE_SCHEDULE> instead of the exchange's schedule, e.g. [{\"min_share\": \"0\", \"maker\": \"0\", \"taker\": \"0.0025\"}]"))
+        .arg(Arg::with_name("FEE_TIER")
+             .long("fee-tier")
+             .takes_value(true)
+             .validator(validate::<usize>)
+             .help("charge the rates of tier <FEE_TIER> of the schedule, counting from 0, regardless of volume"))
+        .arg(Arg::with_name("RISK_LIMITS")
+             .long("risk-limits")
+             .takes_value(true)
+             .help("check orders against risk limits in JSON file <RISK_LIMITS>, stopping at the first breach"))
+        .arg(Arg::with_name("OUTPUT")
+             .long("output")
+             .takes_value(true)
+             .help("write report.json, pnl.csv, and fills.csv to directory <OUTPUT>"))
     )
     ;
 
@@ -38,14 +340,147 @@ impl Arguments {
 
 #[derive(Debug, PartialEq)]
 pub enum Command {
-  Record{sandbox: bool}
+  Record {
+    sandbox:       bool,
+    feed_url:      Option<Url>,
+    rest_url:      Option<Url>,
+    database:      Option<PathBuf>,
+    capture:       Option<PathBuf>,
+    resync_on_gap: bool,
+    products:      Vec<Product>,
+    products_file: Option<PathBuf>,
+    channels:      Vec<Channel>,
+    credentials:   Option<PathBuf>,
+  },
+  Replay {
+    capture:  PathBuf,
+    database: Option<PathBuf>,
+    products: Vec<Product>,
+    start:    Option<DateTime>,
+    end:      Option<DateTime>,
+    speed:    Option<f64>,
+  },
+  MockFeed {
+    port:        u16,
+    script:      Option<PathBuf>,
+    capture:     Option<PathBuf>,
+    interval:    Option<Duration>,
+    credentials: Option<PathBuf>,
+  },
+  Place {
+    sandbox:       bool,
+    rest_url:      Option<Url>,
+    credentials:   Option<PathBuf>,
+    products_file: Option<PathBuf>,
+    risk_limits:   Option<PathBuf>,
+    dry_run:       bool,
+    order:         NewOrder,
+  },
+  Cancel {
+    sandbox:     bool,
+    rest_url:    Option<Url>,
+    credentials: Option<PathBuf>,
+    dry_run:     bool,
+    order_id:    Option<Uuid>,
+    product:     Option<Product>,
+  },
+  Backtest {
+    capture:       PathBuf,
+    products_file: PathBuf,
+    product:       Product,
+    size:          BaseCurrencyAmount,
+    interval:      chrono::Duration,
+    latency:       chrono::Duration,
+    queue:         QueueModel,
+    fee_schedule:  Option<PathBuf>,
+    fee_tier:      Option<usize>,
+    risk_limits:   Option<PathBuf>,
+    output:        Option<PathBuf>,
+  },
 }
 
 impl Command {
   pub fn from_matches(matches: &ArgMatches) -> Command {
     match matches.subcommand() {
-      ("record", Some(submatches)) => Command::Record{sandbox: submatches.is_present("SANDBOX")},
+      ("record", Some(submatches)) => Command::Record {
+        sandbox:       submatches.is_present("SANDBOX"),
+        feed_url:      submatches.value_of("FEED_URL").map(parse),
+        rest_url:      submatches.value_of("REST_URL").map(parse),
+        database:      submatches.value_of_os("DATABASE").map(PathBuf::from),
+        capture:       submatches.value_of_os("CAPTURE").map(PathBuf::from),
+        resync_on_gap: submatches.is_present("RESYNC_ON_GAP"),
+        products:      submatches.values_of("PRODUCT").into_iter().flat_map(|values| values).map(parse).collect(),
+        products_file: submatches.value_of_os("PRODUCTS_FILE").map(PathBuf::from),
+        channels:      submatches.values_of("CHANNEL").into_iter().flat_map(|values| values).map(parse).collect(),
+        credentials:   submatches.value_of_os("CREDENTIALS").map(PathBuf::from),
+      },
+      ("replay", Some(submatches)) => Command::Replay {
+        capture:  PathBuf::from(submatches.value_of_os("CAPTURE").unwrap()),
+        database: submatches.value_of_os("DATABASE").map(PathBuf::from),
+        products: submatches.values_of("PRODUCT").into_iter().flat_map(|values| values).map(parse).collect(),
+        start:    submatches.value_of("START").map(parse),
+        end:      submatches.value_of("END").map(parse),
+        speed:    submatches.value_of("SPEED").map(parse),
+      },
+      ("mock-feed", Some(submatches)) => Command::MockFeed {
+        port:        parse(submatches.value_of("PORT").unwrap()),
+        script:      submatches.value_of_os("SCRIPT").map(PathBuf::from),
+        capture:     submatches.value_of_os("CAPTURE").map(PathBuf::from),
+        interval:    submatches.value_of("INTERVAL").map(parse).map(Duration::from_millis),
+        credentials: submatches.value_of_os("CREDENTIALS").map(PathBuf::from),
+      },
+      ("place", Some(submatches)) => Command::Place {
+        sandbox:       submatches.is_present("SANDBOX"),
+        rest_url:      submatches.value_of("REST_URL").map(parse),
+        credentials:   submatches.value_of_os("CREDENTIALS").map(PathBuf::from),
+        products_file: submatches.value_of_os("PRODUCTS_FILE").map(PathBuf::from),
+        risk_limits:   submatches.value_of_os("RISK_LIMITS").map(PathBuf::from),
+        dry_run:       submatches.is_present("DRY_RUN"),
+        order:         new_order(submatches),
+      },
+      ("cancel", Some(submatches)) => Command::Cancel {
+        sandbox:     submatches.is_present("SANDBOX"),
+        rest_url:    submatches.value_of("REST_URL").map(parse),
+        credentials: submatches.value_of_os("CREDENTIALS").map(PathBuf::from),
+        dry_run:     submatches.is_present("DRY_RUN"),
+        order_id:    submatches.value_of("ORDER_ID").map(parse),
+        product:     submatches.value_of("PRODUCT").map(parse),
+      },
+      ("backtest", Some(submatches)) => Command::Backtest {
+        capture:       PathBuf::from(submatches.value_of_os("CAPTURE").unwrap()),
+        products_file: PathBuf::from(submatches.value_of_os("PRODUCTS_FILE").unwrap()),
+        product:       parse(submatches.value_of("PRODUCT").unwrap()),
+        size:          parse(submatches.value_of("SIZE").unwrap()),
+        interval:      chrono::Duration::seconds(parse(submatches.value_of("INTERVAL").unwrap())),
+        latency:       chrono::Duration::milliseconds(parse(submatches.value_of("LATENCY").unwrap())),
+        queue:         parse(submatches.value_of("QUEUE").unwrap()),
+        fee_schedule:  submatches.value_of_os("FEE_SCHEDULE").map(PathBuf::from),
+        fee_tier:      submatches.value_of("FEE_TIER").map(parse),
+        risk_limits:   submatches.value_of_os("RISK_LIMITS").map(PathBuf::from),
+        output:        submatches.value_of_os("OUTPUT").map(PathBuf::from),
+      },
       (name,     submatches) => panic!("Unexpected subcommand: {} {:?}", name, submatches),
     }
   }
 }
+
+fn new_order(matches: &ArgMatches) -> NewOrder {
+  let product = parse(matches.value_of("PRODUCT").unwrap());
+  let side = parse::<Side>(matches.value_of("SIDE").unwrap());
+  let size = parse(matches.value_of("SIZE").unwrap());
+
+  let mut order = match matches.value_of("PRICE") {
+    Some(price) => NewOrder::limit(product, side, parse(price), size),
+    None        => NewOrder::market(product, side, size),
+  };
+  if matches.is_present("POST_ONLY") {
+    order = order.post_only();
+  }
+  if let Some(time_in_force) = matches.value_of("TIME_IN_FORCE") {
+    order = order.time_in_force(parse::<TimeInForce>(time_in_force));
+  }
+  if let Some(client_oid) = matches.value_of("CLIENT_OID") {
+    order = order.client_oid(parse(client_oid));
+  }
+  order
+}
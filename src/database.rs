@@ -0,0 +1,172 @@
+use diesel::prelude::*;
+use diesel::connection::SimpleConnection;
+use diesel::sqlite::SqliteConnection;
+use serde_json;
+use std::io;
+use std::path::Path;
+
+use gdax::feed::capture::{Frame, FrameSink};
+use gdax::feed::message::{DateTime, IncomingMessage};
+
+use errors::*;
+
+table! {
+  messages (id) {
+    id       -> Integer,
+    product  -> Nullable<Text>,
+    sequence -> Nullable<BigInt>,
+    time     -> Nullable<BigInt>,
+    #[sql_name = "type"]
+    type_    -> Text,
+    payload  -> Text,
+  }
+}
+
+const SCHEMA: &'static str = "
+  PRAGMA journal_mode = WAL;
+  PRAGMA synchronous  = NORMAL;
+
+  CREATE TABLE IF NOT EXISTS messages (
+    id       INTEGER PRIMARY KEY NOT NULL,
+    product  TEXT,
+    sequence BIGINT,
+    time     BIGINT,
+    type     TEXT NOT NULL,
+    payload  TEXT NOT NULL
+  );
+
+  CREATE INDEX IF NOT EXISTS messages_product  ON messages (product);
+  CREATE INDEX IF NOT EXISTS messages_sequence ON messages (sequence);
+  CREATE INDEX IF NOT EXISTS messages_time     ON messages (time);
+  CREATE INDEX IF NOT EXISTS messages_type     ON messages (type);
+";
+
+/// Type of messages that fail to deserialize
+const UNKNOWN_TYPE: &'static str = "unknown";
+
+#[derive(Insertable)]
+#[table_name = "messages"]
+struct NewMessage<'a> {
+  product:  Option<String>,
+  sequence: Option<i64>,
+  time:     Option<i64>,
+  type_:    &'a str,
+  payload:  &'a str,
+}
+
+/// Nanoseconds since the unix epoch, so that times sort correctly
+fn time_to_nanoseconds(time: DateTime) -> i64 {
+  time.timestamp() * 1_000_000_000 + time.timestamp_subsec_nanos() as i64
+}
+
+/// SQLite database of messages received from the feed, storing the text of
+/// each frame exactly as received
+pub struct Database {
+  connection: SqliteConnection,
+}
+
+impl Database {
+  pub fn open(path: &Path) -> Result<Database, Error> {
+    let url = path.to_str()
+      .ok_or_else(|| format!("database path is not valid unicode: {}", path.display()))?;
+
+    let connection = SqliteConnection::establish(url)
+      .chain_err(|| format!("failed to open database: {}", path.display()))?;
+
+    connection.batch_execute(SCHEMA)
+      .chain_err(|| format!("failed to initialize database: {}", path.display()))?;
+
+    Ok(Database{connection})
+  }
+
+  /// Insert the message in frame `text`, indexed by the fields it parses to.
+  /// Messages that fail to deserialize are stored with type `unknown` and no
+  /// indexed fields, so they can be re-parsed later.
+  pub fn insert(&mut self, text: &str) -> Result<(), Error> {
+    let new_message = match serde_json::from_str::<IncomingMessage>(text) {
+      Ok(message) => NewMessage {
+        product:  message.product_id().map(|product| product.to_string()),
+        sequence: message.sequence().map(|sequence| sequence as i64),
+        time:     message.time().map(time_to_nanoseconds),
+        type_:    message.type_name(),
+        payload:  text,
+      },
+      Err(error) => {
+        warn!("storing message that failed to deserialize as unknown: {}", error);
+        NewMessage {
+          product:  None,
+          sequence: None,
+          time:     None,
+          type_:    UNKNOWN_TYPE,
+          payload:  text,
+        }
+      }
+    };
+
+    ::diesel::insert_into(messages::table)
+      .values(&new_message)
+      .execute(&self.connection)
+      .chain_err(|| "failed to insert message into database")?;
+
+    Ok(())
+  }
+}
+
+impl FrameSink for Database {
+  fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+    self.insert(&frame.text).map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn insert() {
+    let mut database = Database::open(Path::new(":memory:")).unwrap();
+
+    let heartbeat = r#"{
+      "type":          "heartbeat",
+      "sequence":      90,
+      "last_trade_id": 20,
+      "product_id":    "BTC-USD",
+      "time":          "2014-11-07T08:19:28.464459Z",
+      "unknown":       [1, 2]
+    }"#;
+
+    database.insert(heartbeat).unwrap();
+
+    let rows = messages::table
+      .select((messages::product, messages::sequence, messages::time, messages::type_, messages::payload))
+      .load::<(Option<String>, Option<i64>, Option<i64>, String, String)>(&database.connection)
+      .unwrap();
+
+    assert_eq!(rows, vec![(
+      Some("BTC-USD".to_string()),
+      Some(90),
+      Some(1415348368464459000),
+      "heartbeat".to_string(),
+      heartbeat.to_string(),
+    )]);
+  }
+
+  #[test]
+  fn insert_unknown() {
+    let mut database = Database::open(Path::new(":memory:")).unwrap();
+
+    let unknown = r#"{"type": "novel", "product_id": "BTC-USD", "sequence": 1}"#;
+    database.write_frame(&Frame{received: ::chrono::Utc::now(), text: unknown.to_string()}).unwrap();
+    database.insert("not json").unwrap();
+
+    let rows = messages::table
+      .select((messages::product, messages::sequence, messages::type_, messages::payload))
+      .load::<(Option<String>, Option<i64>, String, String)>(&database.connection)
+      .unwrap();
+
+    assert_eq!(rows, vec![
+      (None, None, "unknown".to_string(), unknown.to_string()),
+      (None, None, "unknown".to_string(), "not json".to_string()),
+    ]);
+  }
+}
@@ -1,6 +1,9 @@
 use serde::{de, Deserialize, Serialize, Deserializer, Serializer};
 use std::fmt;
 use std::fmt::Display;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, Sub};
 use std::str::FromStr;
 use extprim::u128::u128;
 use std::u64;
@@ -24,11 +27,201 @@ fn zero() -> u128 {
   u128::zero()
 }
 
+/// How to round results that can't be represented exactly
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Rounding {
+  /// To the nearest representable value, with ties to the even one
+  HalfEven,
+  /// Toward zero, discarding excess digits
+  TowardZero,
+  /// Toward negative infinity
+  Floor,
+  /// Toward positive infinity
+  Ceiling,
+}
+
+impl Rounding {
+  /// The rounding mode to apply to the magnitude of a result with the given sign
+  fn for_magnitude(self, negative: bool) -> Rounding {
+    match (self, negative) {
+      (Rounding::Floor,   true) => Rounding::Ceiling,
+      (Rounding::Ceiling, true) => Rounding::Floor,
+      (rounding,          _)    => rounding,
+    }
+  }
+}
+
+/// 256-bit unsigned integer, as little-endian 64-bit limbs
+type Wide = [u64; 4];
+
+/// Full 256-bit product of `a` and `b`
+fn multiply_wide(a: u128, b: u128) -> Wide {
+  let a = [a.low64(), a.high64()];
+  let b = [b.low64(), b.high64()];
+  let mut product = [0; 4];
+  for i in 0..2 {
+    let mut carry = 0;
+    for j in 0..2 {
+      let partial = u128::new(a[i]) * u128::new(b[j])
+        + u128::new(product[i + j])
+        + u128::new(carry);
+      product[i + j] = partial.low64();
+      carry = partial.high64();
+    }
+    product[i + 2] = carry;
+  }
+  product
+}
+
+/// Quotient and remainder of `n` divided by `d`, or `None` if `d` is zero or
+/// the quotient doesn't fit in 128 bits
+fn divide_wide(n: Wide, d: u128) -> Option<(u128, u128)> {
+  if d == zero() {
+    return None;
+  }
+
+  let mut quotient  = zero();
+  let mut remainder = zero();
+  for bit in (0..256).rev() {
+    let carry = remainder.high64() >> 63 == 1;
+    remainder = (remainder << 1) | u128::new((n[bit / 64] >> (bit % 64)) & 1);
+    if carry || remainder >= d {
+      remainder = remainder.wrapping_sub(d);
+      if bit >= 128 {
+        return None;
+      }
+      quotient |= u128::new(1) << bit as u32;
+    }
+  }
+
+  Some((quotient, remainder))
+}
+
+/// Round the quotient `quotient` with remainder `remainder` of a division by `divisor`
+fn round(quotient: u128, remainder: u128, divisor: u128, rounding: Rounding) -> Option<u128> {
+  if remainder == zero() {
+    return Some(quotient);
+  }
+
+  let round_up = match rounding {
+    Rounding::TowardZero | Rounding::Floor => false,
+    Rounding::Ceiling                      => true,
+    Rounding::HalfEven => {
+      let excess = divisor - remainder;
+      remainder > excess || (remainder == excess && quotient.low64() & 1 == 1)
+    }
+  };
+
+  if round_up {
+    quotient.checked_add(u128::new(1))
+  } else {
+    Some(quotient)
+  }
+}
+
 impl Decimal {
   fn from_units(units: u128) -> Decimal {
     Decimal{units}
   }
 
+  pub fn zero() -> Decimal {
+    Decimal::from_units(zero())
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.units == zero()
+  }
+
+  pub fn checked_add(self, rhs: Decimal) -> Option<Decimal> {
+    self.units.checked_add(rhs.units).map(Decimal::from_units)
+  }
+
+  pub fn checked_sub(self, rhs: Decimal) -> Option<Decimal> {
+    self.units.checked_sub(rhs.units).map(Decimal::from_units)
+  }
+
+  /// Product rounded to 18 decimal places with `rounding`, or `None` on overflow
+  pub fn checked_mul(self, rhs: Decimal, rounding: Rounding) -> Option<Decimal> {
+    self.checked_mul_round(rhs, SCALING_EXPONENT, rounding)
+  }
+
+  /// Exact product rounded once to `places` decimal places with `rounding`,
+  /// or `None` on overflow
+  pub fn checked_mul_round(self, rhs: Decimal, places: u32, rounding: Rounding) -> Option<Decimal> {
+    let increment = ten().pow(SCALING_EXPONENT - places.min(SCALING_EXPONENT));
+    let divisor = scaling_factor().checked_mul(increment)?;
+    let (quotient, remainder) = divide_wide(multiply_wide(self.units, rhs.units), divisor)?;
+    round(quotient, remainder, divisor, rounding)?.checked_mul(increment).map(Decimal::from_units)
+  }
+
+  /// Quotient rounded to 18 decimal places with `rounding`, or `None` on
+  /// overflow or division by zero
+  pub fn checked_div(self, rhs: Decimal, rounding: Rounding) -> Option<Decimal> {
+    let numerator = multiply_wide(self.units, scaling_factor());
+    let (quotient, remainder) = divide_wide(numerator, rhs.units)?;
+    round(quotient, remainder, rhs.units, rounding).map(Decimal::from_units)
+  }
+
+  /// Round to `places` decimal places with `rounding`, or `None` on overflow
+  pub fn checked_round(self, places: u32, rounding: Rounding) -> Option<Decimal> {
+    if places >= SCALING_EXPONENT {
+      return Some(self);
+    }
+    let increment = ten().pow(SCALING_EXPONENT - places);
+    let quotient  = self.units / increment;
+    let remainder = self.units % increment;
+    round(quotient, remainder, increment, rounding)?.checked_mul(increment).map(Decimal::from_units)
+  }
+
+  /// Product rounded with `rounding`, panicking on overflow
+  pub fn mul_rounded(self, rhs: Decimal, rounding: Rounding) -> Decimal {
+    self.checked_mul(rhs, rounding).expect("decimal multiplication overflowed")
+  }
+
+  /// Quotient rounded with `rounding`, panicking on overflow or division by zero
+  pub fn div_rounded(self, rhs: Decimal, rounding: Rounding) -> Decimal {
+    self.checked_div(rhs, rounding).expect("decimal division overflowed or divided by zero")
+  }
+}
+
+impl From<u64> for Decimal {
+  fn from(whole: u64) -> Decimal {
+    Decimal::from_units(u128::new(whole) * scaling_factor())
+  }
+}
+
+impl Add for Decimal {
+  type Output = Decimal;
+
+  fn add(self, rhs: Decimal) -> Decimal {
+    self.checked_add(rhs).expect("decimal addition overflowed")
+  }
+}
+
+impl Sub for Decimal {
+  type Output = Decimal;
+
+  fn sub(self, rhs: Decimal) -> Decimal {
+    self.checked_sub(rhs).expect("decimal subtraction overflowed")
+  }
+}
+
+/// Multiplication rounds half to even
+impl Mul for Decimal {
+  type Output = Decimal;
+
+  fn mul(self, rhs: Decimal) -> Decimal {
+    self.mul_rounded(rhs, Rounding::HalfEven)
+  }
+}
+
+/// Division rounds half to even
+impl Div for Decimal {
+  type Output = Decimal;
+
+  fn div(self, rhs: Decimal) -> Decimal {
+    self.div_rounded(rhs, Rounding::HalfEven)
+  }
 }
 
 impl Display for Decimal {
@@ -58,14 +251,21 @@ impl FromStr for Decimal {
     let mut n                   = zero;
     let mut exponent_reduction  = 0;
 
-    for character in s.trim_right_matches('0').chars() {
+    let s = if s.contains('.') { s.trim_right_matches('0') } else { s };
+
+    for character in s.chars() {
       match character {
         '0'...'9' => {
-          let value = character as u64 - '0' as u64;
-          n =  n * ten + u128::new(value);
           if after_decimal_point {
+            if exponent_reduction == SCALING_EXPONENT {
+              return Err((character, position));
+            }
             exponent_reduction += 1;
           }
+          let value = character as u64 - '0' as u64;
+          n = n.checked_mul(ten)
+            .and_then(|n| n.checked_add(u128::new(value)))
+            .ok_or((character, position))?;
         }
         '.' if !after_decimal_point => after_decimal_point = true,
         _ => {
@@ -75,7 +275,9 @@ impl FromStr for Decimal {
       position += 1;
     }
 
-    Ok(Decimal::from_units(n * ten.pow(SCALING_EXPONENT - exponent_reduction)))
+    n.checked_mul(ten.pow(SCALING_EXPONENT - exponent_reduction))
+      .map(Decimal::from_units)
+      .ok_or((s.chars().last().unwrap_or('0'), position.saturating_sub(1)))
   }
 }
 
@@ -89,20 +291,20 @@ impl Serialize for Decimal {
 
 impl<'d> Deserialize<'d> for Decimal{
   fn deserialize<D: Deserializer<'d>>(deserializer: D) -> Result<Self, D::Error> {
-    deserializer.deserialize_str(DecimalVisitor)
+    deserializer.deserialize_str(DecimalVisitor(PhantomData))
   }
 }
 
-struct DecimalVisitor;
+struct DecimalVisitor<T>(PhantomData<T>);
 
-impl<'de> de::Visitor<'de> for DecimalVisitor {
-  type Value = Decimal;
+impl<'de, T: FromStr<Err=(char, usize)>> de::Visitor<'de> for DecimalVisitor<T> {
+  type Value = T;
 
   fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "text of the form 1234.5678")
   }
 
-  fn visit_str<E>(self, value: &str) -> Result<Decimal, E>
+  fn visit_str<E>(self, value: &str) -> Result<T, E>
     where E: de::Error
   {
     value.parse().map_err(|(character, position)| {
@@ -111,6 +313,185 @@ impl<'de> de::Visitor<'de> for DecimalVisitor {
   }
 }
 
+/// A `Decimal` with a sign, for quantities like profit and loss and position
+/// changes that can be negative
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SignedDecimal {
+  negative:  bool,
+  magnitude: Decimal,
+}
+
+impl SignedDecimal {
+  fn new(negative: bool, magnitude: Decimal) -> SignedDecimal {
+    SignedDecimal{negative: negative && !magnitude.is_zero(), magnitude}
+  }
+
+  pub fn zero() -> SignedDecimal {
+    SignedDecimal::new(false, Decimal::zero())
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.magnitude.is_zero()
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.negative
+  }
+
+  pub fn abs(self) -> Decimal {
+    self.magnitude
+  }
+
+  /// The value as an unsigned `Decimal`, or `None` if it's negative
+  pub fn to_unsigned(self) -> Option<Decimal> {
+    if self.negative {
+      None
+    } else {
+      Some(self.magnitude)
+    }
+  }
+
+  pub fn checked_add(self, rhs: SignedDecimal) -> Option<SignedDecimal> {
+    if self.negative == rhs.negative {
+      return self.magnitude.checked_add(rhs.magnitude).map(|sum| SignedDecimal::new(self.negative, sum));
+    }
+    Some(match self.magnitude.checked_sub(rhs.magnitude) {
+      Some(difference) => SignedDecimal::new(self.negative, difference),
+      None             => SignedDecimal::new(rhs.negative, rhs.magnitude - self.magnitude),
+    })
+  }
+
+  pub fn checked_sub(self, rhs: SignedDecimal) -> Option<SignedDecimal> {
+    self.checked_add(-rhs)
+  }
+
+  /// Product rounded to 18 decimal places with `rounding`, or `None` on overflow
+  pub fn checked_mul(self, rhs: SignedDecimal, rounding: Rounding) -> Option<SignedDecimal> {
+    let negative = self.negative != rhs.negative;
+    self.magnitude.checked_mul(rhs.magnitude, rounding.for_magnitude(negative))
+      .map(|product| SignedDecimal::new(negative, product))
+  }
+
+  /// Quotient rounded to 18 decimal places with `rounding`, or `None` on
+  /// overflow or division by zero
+  pub fn checked_div(self, rhs: SignedDecimal, rounding: Rounding) -> Option<SignedDecimal> {
+    let negative = self.negative != rhs.negative;
+    self.magnitude.checked_div(rhs.magnitude, rounding.for_magnitude(negative))
+      .map(|quotient| SignedDecimal::new(negative, quotient))
+  }
+
+  /// Product rounded with `rounding`, panicking on overflow
+  pub fn mul_rounded(self, rhs: SignedDecimal, rounding: Rounding) -> SignedDecimal {
+    self.checked_mul(rhs, rounding).expect("decimal multiplication overflowed")
+  }
+
+  /// Quotient rounded with `rounding`, panicking on overflow or division by zero
+  pub fn div_rounded(self, rhs: SignedDecimal, rounding: Rounding) -> SignedDecimal {
+    self.checked_div(rhs, rounding).expect("decimal division overflowed or divided by zero")
+  }
+}
+
+impl From<Decimal> for SignedDecimal {
+  fn from(magnitude: Decimal) -> SignedDecimal {
+    SignedDecimal::new(false, magnitude)
+  }
+}
+
+impl Ord for SignedDecimal {
+  fn cmp(&self, other: &SignedDecimal) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, false) => self.magnitude.cmp(&other.magnitude),
+      (true,  true)  => other.magnitude.cmp(&self.magnitude),
+      (false, true)  => Ordering::Greater,
+      (true,  false) => Ordering::Less,
+    }
+  }
+}
+
+impl PartialOrd for SignedDecimal {
+  fn partial_cmp(&self, other: &SignedDecimal) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Neg for SignedDecimal {
+  type Output = SignedDecimal;
+
+  fn neg(self) -> SignedDecimal {
+    SignedDecimal::new(!self.negative, self.magnitude)
+  }
+}
+
+impl Add for SignedDecimal {
+  type Output = SignedDecimal;
+
+  fn add(self, rhs: SignedDecimal) -> SignedDecimal {
+    self.checked_add(rhs).expect("decimal addition overflowed")
+  }
+}
+
+impl Sub for SignedDecimal {
+  type Output = SignedDecimal;
+
+  fn sub(self, rhs: SignedDecimal) -> SignedDecimal {
+    self.checked_sub(rhs).expect("decimal subtraction overflowed")
+  }
+}
+
+/// Multiplication rounds half to even
+impl Mul for SignedDecimal {
+  type Output = SignedDecimal;
+
+  fn mul(self, rhs: SignedDecimal) -> SignedDecimal {
+    self.mul_rounded(rhs, Rounding::HalfEven)
+  }
+}
+
+/// Division rounds half to even
+impl Div for SignedDecimal {
+  type Output = SignedDecimal;
+
+  fn div(self, rhs: SignedDecimal) -> SignedDecimal {
+    self.div_rounded(rhs, Rounding::HalfEven)
+  }
+}
+
+impl Display for SignedDecimal {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    if self.negative {
+      write!(f, "-")?;
+    }
+    write!(f, "{}", self.magnitude)
+  }
+}
+
+impl FromStr for SignedDecimal {
+  type Err = (char, usize);
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.starts_with('-') {
+      s[1..].parse()
+        .map(|magnitude| SignedDecimal::new(true, magnitude))
+        .map_err(|(character, position)| (character, position + 1))
+    } else {
+      s.parse::<Decimal>().map(SignedDecimal::from)
+    }
+  }
+}
+
+impl Serialize for SignedDecimal {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+      where S: Serializer
+  {
+    serializer.collect_str(&self)
+  }
+}
+
+impl<'d> Deserialize<'d> for SignedDecimal {
+  fn deserialize<D: Deserializer<'d>>(deserializer: D) -> Result<Self, D::Error> {
+    deserializer.deserialize_str(DecimalVisitor(PhantomData))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -122,7 +503,7 @@ mod tests {
   }
 
   fn test_format(digits: u64, decimal_places: u32, expected: &str) -> String {
-    let input = Decimal::from_digits_places(digits, decimal_places);
+    let input = from_digits_places(digits, decimal_places);
     let formatted = input.to_string();
     assert_eq!(formatted, expected, "formatting {:?} failed: {} != {}", input, formatted, expected);
     formatted
@@ -130,13 +511,13 @@ mod tests {
 
   fn test_parse(input: &str, digits: u64, decimal_places: u32) -> Decimal {
     let parsed: Decimal = input.parse().unwrap();
-    let expected = Decimal::from_digits_places(digits, decimal_places);
+    let expected = from_digits_places(digits, decimal_places);
     assert_eq!(parsed, expected, "deserializing {} failed: {:?} != {:?}", input, parsed, expected);
     parsed
   }
 
   fn test_round_trip(digits: u64, decimal_places: u32) {
-    let input = Decimal::from_digits_places(digits, decimal_places);
+    let input = from_digits_places(digits, decimal_places);
     let output: Decimal = input.to_string().parse().unwrap();
     assert_eq!(output, input, "round-trip failed, input {:?} != output {:?}", input, output);
   }
@@ -202,4 +583,170 @@ mod tests {
     let max = scaling_factor() * scaling_factor();
     assert!(max < u128::max_value());
   }
+
+  #[test]
+  fn integers() {
+    test("1000"  , 1000,  0, "1000.0");
+    test("10"    , 10,    0, "10.0");
+    test("100.00", 100,   0, "100.0");
+  }
+
+  #[test]
+  fn precision_and_overflow() {
+    test("0.000000000000000001", 1, 18, "0.000000000000000001");
+    test_error("0.0000000000000000001", '1', 20);
+    test_error("1000000000000000000000000000000000000000", '0', 39);
+    test_error("1000000000000000000000", '0', 21);
+  }
+
+  fn decimal(s: &str) -> Decimal {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn add_sub() {
+    assert_eq!(decimal("1.25") + decimal("2.5"), decimal("3.75"));
+    assert_eq!(decimal("3.75") - decimal("2.5"), decimal("1.25"));
+    assert_eq!(decimal("1.0").checked_sub(decimal("1.5")), None);
+    assert_eq!(Decimal::from_units(u128::max_value()).checked_add(decimal("0.000000000000000001")), None);
+  }
+
+  #[test]
+  fn mul() {
+    assert_eq!(decimal("1.5") * decimal("2.0"), decimal("3.0"));
+    assert_eq!(decimal("0.1") * decimal("0.1"), decimal("0.01"));
+    assert_eq!(decimal("4567.89") * decimal("0.0025"), decimal("11.419725"));
+    assert_eq!(decimal("0.0") * decimal("12345.6789"), decimal("0.0"));
+
+    // operands and product well beyond 64 bits of units
+    assert_eq!(
+      decimal("12345678901.234567890123456789") * decimal("9876543.21"),
+      decimal("121932631124828532.112482853211126353"),
+    );
+  }
+
+  #[test]
+  fn mul_rounding() {
+    let a = decimal("0.000000000000000001");
+    let b = decimal("0.5");
+    assert_eq!(a.mul_rounded(b, Rounding::HalfEven),   decimal("0.0"));
+    assert_eq!(a.mul_rounded(b, Rounding::TowardZero), decimal("0.0"));
+    assert_eq!(a.mul_rounded(b, Rounding::Floor),      decimal("0.0"));
+    assert_eq!(a.mul_rounded(b, Rounding::Ceiling),    decimal("0.000000000000000001"));
+
+    let a = decimal("0.000000000000000003");
+    assert_eq!(a.mul_rounded(b, Rounding::HalfEven),   decimal("0.000000000000000002"));
+    assert_eq!(a.mul_rounded(b, Rounding::TowardZero), decimal("0.000000000000000001"));
+
+    // rounding to 18 places first would give 0.00000000000000015, then 0.0000000000000002
+    let a = decimal("0.000000000000000001");
+    let b = decimal("149.9999999");
+    assert_eq!(a.checked_mul_round(b, 16, Rounding::HalfEven), Some(decimal("0.0000000000000001")));
+    assert_eq!(a.checked_mul_round(b, 18, Rounding::HalfEven), a.checked_mul(b, Rounding::HalfEven));
+    assert_eq!(decimal("2.5").checked_mul_round(decimal("1.0"), 0, Rounding::HalfEven), Some(decimal("2.0")));
+  }
+
+  #[test]
+  fn mul_overflow() {
+    let big = decimal("100000000000000000000.0");
+    assert_eq!(big.checked_mul(big, Rounding::HalfEven), None);
+    assert_eq!(big.checked_mul(decimal("2.0"), Rounding::HalfEven), Some(decimal("200000000000000000000.0")));
+  }
+
+  #[test]
+  fn div() {
+    assert_eq!(decimal("3.0") / decimal("2.0"), decimal("1.5"));
+    assert_eq!(decimal("1.0") / decimal("8.0"), decimal("0.125"));
+    assert_eq!(decimal("11.419725") / decimal("0.0025"), decimal("4567.89"));
+    assert_eq!(decimal("1.0").checked_div(decimal("0.0"), Rounding::HalfEven), None);
+    assert_eq!(
+      decimal("100000000000000000000.0").checked_div(decimal("0.000000000000000001"), Rounding::HalfEven),
+      None,
+    );
+  }
+
+  #[test]
+  fn div_rounding() {
+    let one   = decimal("1.0");
+    let three = decimal("3.0");
+    assert_eq!(one.div_rounded(three, Rounding::HalfEven),   decimal("0.333333333333333333"));
+    assert_eq!(one.div_rounded(three, Rounding::TowardZero), decimal("0.333333333333333333"));
+    assert_eq!(one.div_rounded(three, Rounding::Floor),      decimal("0.333333333333333333"));
+    assert_eq!(one.div_rounded(three, Rounding::Ceiling),    decimal("0.333333333333333334"));
+
+    let two = decimal("2.0");
+    assert_eq!(two.div_rounded(three, Rounding::HalfEven),   decimal("0.666666666666666667"));
+    assert_eq!(two.div_rounded(three, Rounding::TowardZero), decimal("0.666666666666666666"));
+
+    // ties go to the even neighbor
+    let tiny = decimal("0.000000000000000001");
+    let five = decimal("0.000000000000000005");
+    assert_eq!(tiny.div_rounded(two, Rounding::HalfEven), decimal("0.0"));
+    assert_eq!(decimal("0.000000000000000003").div_rounded(two, Rounding::HalfEven), decimal("0.000000000000000002"));
+    assert_eq!(five.div_rounded(two, Rounding::HalfEven), decimal("0.000000000000000002"));
+  }
+
+  #[test]
+  fn round_places() {
+    assert_eq!(decimal("1.005").checked_round(2, Rounding::HalfEven), Some(decimal("1.0")));
+    assert_eq!(decimal("1.015").checked_round(2, Rounding::HalfEven), Some(decimal("1.02")));
+    assert_eq!(decimal("1.011").checked_round(2, Rounding::Ceiling),  Some(decimal("1.02")));
+    assert_eq!(decimal("1.019").checked_round(2, Rounding::Floor),    Some(decimal("1.01")));
+    assert_eq!(decimal("1.5").checked_round(0, Rounding::HalfEven),   Some(decimal("2.0")));
+    assert_eq!(decimal("2.5").checked_round(0, Rounding::HalfEven),   Some(decimal("2.0")));
+  }
+
+  fn signed(s: &str) -> SignedDecimal {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn signed_parse_and_format() {
+    assert_eq!(signed("-1.5").to_string(), "-1.5");
+    assert_eq!(signed("1.5").to_string(), "1.5");
+    assert_eq!(signed("-0.0"), SignedDecimal::zero());
+    assert_eq!(signed("-0.0").to_string(), "0.0");
+    assert!(signed("-.000000000000000001").is_negative());
+    assert_eq!("--1.0".parse::<SignedDecimal>(), Err(('-', 1)));
+    assert_eq!("-1.0x".parse::<SignedDecimal>(), Err(('x', 4)));
+    assert_eq!("1-.0".parse::<SignedDecimal>(), Err(('-', 1)));
+  }
+
+  #[test]
+  fn signed_serde() {
+    use serde_json;
+    assert_eq!(serde_json::to_string(&signed("-12.25")).unwrap(), r#""-12.25""#);
+    assert_eq!(serde_json::from_str::<SignedDecimal>(r#""-12.25""#).unwrap(), signed("-12.25"));
+    assert!(serde_json::from_str::<Decimal>(r#""-12.25""#).is_err());
+  }
+
+  #[test]
+  fn signed_arithmetic() {
+    assert_eq!(signed("1.0") - signed("2.5"), signed("-1.5"));
+    assert_eq!(signed("-1.0") + signed("2.5"), signed("1.5"));
+    assert_eq!(signed("-1.0") - signed("2.5"), signed("-3.5"));
+    assert_eq!(signed("-1.5") + signed("1.5"), SignedDecimal::zero());
+    assert_eq!(signed("-1.5") * signed("-2.0"), signed("3.0"));
+    assert_eq!(signed("-3.0") / signed("2.0"), signed("-1.5"));
+    assert_eq!(-signed("2.0"), signed("-2.0"));
+    assert_eq!(signed("-2.0").abs(), decimal("2.0"));
+    assert_eq!(signed("-2.0").to_unsigned(), None);
+    assert_eq!(SignedDecimal::from(decimal("2.0")).to_unsigned(), Some(decimal("2.0")));
+  }
+
+  #[test]
+  fn signed_rounding() {
+    let third = |rounding| signed("-1.0").div_rounded(signed("3.0"), rounding);
+    assert_eq!(third(Rounding::Floor),      signed("-0.333333333333333334"));
+    assert_eq!(third(Rounding::Ceiling),    signed("-0.333333333333333333"));
+    assert_eq!(third(Rounding::TowardZero), signed("-0.333333333333333333"));
+    assert_eq!(third(Rounding::HalfEven),   signed("-0.333333333333333333"));
+  }
+
+  #[test]
+  fn signed_ordering() {
+    let mut values = vec![signed("1.0"), signed("-2.0"), signed("0.0"), signed("-1.0"), signed("2.0")];
+    values.sort();
+    assert_eq!(values, vec![signed("-2.0"), signed("-1.0"), signed("0.0"), signed("1.0"), signed("2.0")]);
+  }
 }
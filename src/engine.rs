@@ -0,0 +1,719 @@
+use chrono::Duration;
+use futures::prelude::*;
+use futures::stream::FuturesUnordered;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::time::{self, Instant};
+use tokio_core::reactor::{Handle, Timeout};
+use uuid::Uuid;
+
+use gdax::feed::FeedError;
+use gdax::feed::message::{DateTime, IncomingMessage, Product};
+use gdax::feed::reconnect::FeedEvent;
+use gdax::order_book::OrderBook;
+use gdax::order_entry::{OrderEntry, OrderError, OrderEvent, PlacedOrder};
+use gdax::rest::message::{Fill, NewOrder, Trade};
+use ledger::Ledger;
+use risk::{CheckedOrderEntry, RiskLimits, RiskManager, RiskViolation};
+use strategy::{Intent, Strategy};
+
+/// Market and order state visible to a strategy
+#[derive(Debug)]
+pub struct Context {
+  now:         Option<DateTime>,
+  books:       BTreeMap<Product, OrderBook>,
+  open_orders: BTreeMap<Uuid, NewOrder>,
+  ledger:      Ledger,
+}
+
+impl Default for Context {
+  fn default() -> Context {
+    Context {
+      now:         None,
+      books:       BTreeMap::new(),
+      open_orders: BTreeMap::new(),
+      ledger:      Ledger::new(),
+    }
+  }
+}
+
+impl Context {
+  /// Time of the latest timestamped message
+  pub fn now(&self) -> Option<DateTime> {
+    self.now
+  }
+
+  pub fn book(&self, product: Product) -> Option<&OrderBook> {
+    self.books.get(&product)
+  }
+
+  /// Orders placed by the strategy that haven't finished
+  pub fn open_orders(&self) -> &BTreeMap<Uuid, NewOrder> {
+    &self.open_orders
+  }
+
+  /// Balances and positions from the strategy's fills
+  pub fn ledger(&self) -> &Ledger {
+    &self.ledger
+  }
+
+  /// Update from a feed message, returning fills of placed orders in it. A
+  /// book that an update leaves invalid is discarded until the next snapshot.
+  pub fn observe(&mut self, message: &IncomingMessage) -> Vec<Fill> {
+    let fills = self.ledger.apply(message);
+
+    if let Some(time) = message.time() {
+      if self.now.map(|now| time > now).unwrap_or(true) {
+        self.now = Some(time);
+      }
+    }
+
+    match *message {
+      IncomingMessage::Snapshot(ref snapshot) => {
+        self.books.insert(snapshot.product_id, OrderBook::from_snapshot(snapshot));
+      }
+      IncomingMessage::L2update(ref update) => {
+        let result = self.books.get_mut(&update.product_id).map(|book| book.update(update));
+        if let Some(Err(error)) = result {
+          warn!("discarding invalid {} order book: {}", update.product_id, error);
+          self.books.remove(&update.product_id);
+        }
+      }
+      IncomingMessage::Done{order_id, ..} => {
+        self.open_orders.remove(&order_id);
+      }
+      _ => {}
+    }
+
+    fills
+  }
+
+  /// Count matches of `order` on the feed as fills from its `received`
+  /// message, before it has been acknowledged. The order needs a
+  /// `client_oid`.
+  pub fn placing(&mut self, order: &NewOrder) {
+    if let Some(client_oid) = order.client_oid {
+      self.ledger.expect(client_oid);
+    }
+  }
+
+  /// Count `order` as open, and its matches on the feed as fills, once it
+  /// has been placed as order `id`
+  pub fn placed(&mut self, id: Uuid, order: NewOrder) {
+    if let Some(client_oid) = order.client_oid {
+      self.ledger.forget(client_oid);
+    }
+    self.open_orders.insert(id, order);
+    self.ledger.track(id);
+  }
+
+  /// Stop expecting `order`, which failed to be placed
+  pub fn failed(&mut self, order: &NewOrder) {
+    if let Some(client_oid) = order.client_oid {
+      self.ledger.forget(client_oid);
+    }
+  }
+
+  /// Discard books after feed messages may have been missed, until the
+  /// next snapshots
+  pub fn discontinuity(&mut self) {
+    self.books.clear();
+  }
+
+  /// Record a fill reported by order entry, returning false if it was
+  /// already recorded
+  pub fn fill(&mut self, fill: &Fill) -> bool {
+    self.ledger.fill(fill)
+  }
+
+  /// Stop counting order `id` as open
+  pub fn finished(&mut self, id: Uuid) {
+    self.open_orders.remove(&id);
+  }
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+  Feed(FeedError),
+  Timer(io::Error),
+}
+
+impl Display for EngineError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      EngineError::Feed(ref error)  => write!(f, "feed error: {}", error),
+      EngineError::Timer(ref error) => write!(f, "stale feed timer error: {}", error),
+    }
+  }
+}
+
+impl Error for EngineError {
+  fn description(&self) -> &str {
+    match *self {
+      EngineError::Feed(_)  => "feed error",
+      EngineError::Timer(_) => "stale feed timer error",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      EngineError::Feed(ref error)  => Some(error),
+      EngineError::Timer(ref error) => Some(error),
+    }
+  }
+}
+
+/// Result of an order entry request
+enum Completion {
+  Placed(NewOrder, Result<PlacedOrder, OrderError>),
+  Canceled(Result<Vec<Uuid>, OrderError>),
+}
+
+type Pending = Box<Future<Item=Completion, Error=()>>;
+
+/// Runs a strategy over a stream of messages or feed events, from a feed or
+/// a replay, sending the orders it asks for to an order entry backend. Books
+/// are discarded after a discontinuity, until the next snapshots.
+///
+/// Fills of placed orders come from matches on the feed's user channel, or
+/// from the backend's events if it doesn't report them on the feed.
+///
+/// The timer is driven by message timestamps rather than the wall clock, so
+/// that replays behave the same at any speed.
+///
+/// Orders are checked against risk limits before they're sent. A breach
+/// trips the kill switch, which cancels all open orders and stops the run
+/// without calling the strategy again.
+pub struct Engine<S, E> {
+  strategy:    S,
+  order_entry: CheckedOrderEntry<E>,
+  context:     Context,
+  timer:       Option<Duration>,
+  next_timer:  Option<DateTime>,
+  watchdog:    Option<Handle>,
+  pending:     FuturesUnordered<Pending>,
+}
+
+impl<S: Strategy, E: OrderEntry> Engine<S, E> {
+  pub fn new(strategy: S, order_entry: E) -> Engine<S, E> {
+    Engine {
+      order_entry: CheckedOrderEntry::new(order_entry, RiskLimits::default()),
+      context:     Context::default(),
+      timer:       None,
+      next_timer:  None,
+      watchdog:    None,
+      pending:     FuturesUnordered::new(),
+      strategy,
+    }
+  }
+
+  /// Call the strategy's `on_timer` hook every `interval`. Panics unless
+  /// `interval` is greater than zero.
+  pub fn timer(mut self, interval: Duration) -> Engine<S, E> {
+    assert!(interval > Duration::zero(), "timer interval must be greater than zero: {}", interval);
+    self.timer = Some(interval);
+    self
+  }
+
+  /// Check orders against `limits`, which are unlimited by default
+  pub fn risk_limits(mut self, limits: RiskLimits) -> Engine<S, E> {
+    self.order_entry = CheckedOrderEntry::new(self.order_entry.into_inner(), limits);
+    self
+  }
+
+  /// Also check the stale feed limit against the wall clock, with a timer on
+  /// `handle`, so that a live feed that stops entirely trips the kill switch
+  pub fn watchdog(mut self, handle: &Handle) -> Engine<S, E> {
+    self.watchdog = Some(handle.clone());
+    self
+  }
+
+  pub fn strategy(&self) -> &S {
+    &self.strategy
+  }
+
+  pub fn order_entry(&self) -> &E {
+    self.order_entry.inner()
+  }
+
+  pub fn context(&self) -> &Context {
+    &self.context
+  }
+
+  pub fn risk(&self) -> &RiskManager {
+    self.order_entry.risk()
+  }
+
+  pub fn into_parts(self) -> (S, E) {
+    (self.strategy, self.order_entry.into_inner())
+  }
+
+  /// Run until `messages` ends and outstanding order requests complete,
+  /// resolving to the engine
+  pub fn run<M>(self, messages: M) -> Run<S, E, M>
+    where M:       Stream<Error=FeedError>,
+          M::Item: Into<FeedEvent>,
+  {
+    Run {
+      engine:   Some(self),
+      finished: false,
+      watchdog: None,
+      messages,
+    }
+  }
+
+  fn breached(&self) -> bool {
+    self.order_entry.risk().breach().is_some()
+  }
+
+  fn discontinuity(&mut self) {
+    warn!("discarding order books after feed discontinuity");
+    self.order_entry.inner_mut().discontinuity();
+    self.context.discontinuity();
+  }
+
+  fn handle(&mut self, message: &IncomingMessage) {
+    self.order_entry.inner_mut().observe(message);
+
+    if let Some(time) = message.time() {
+      if let Err(violation) = self.order_entry.risk_mut().check_feed(self.context.now, time) {
+        self.kill(violation);
+      }
+    }
+
+    let fills = self.context.observe(message);
+
+    if self.breached() {
+      return;
+    }
+
+    let intents = match *message {
+      IncomingMessage::Snapshot(ref snapshot) =>
+        self.strategy.on_book(&self.context, snapshot.product_id, &self.context.books[&snapshot.product_id]),
+      IncomingMessage::L2update(ref update) => match self.context.books.get(&update.product_id) {
+        Some(book) => self.strategy.on_book(&self.context, update.product_id, book),
+        None       => vec![],
+      },
+      IncomingMessage::Match{product_id, time, trade_id, price, size, side, ..} =>
+        self.strategy.on_trade(&self.context, product_id, &Trade{time, trade_id, price, size, side}),
+      IncomingMessage::Ticker(ref ticker) => self.strategy.on_ticker(&self.context, ticker),
+      _ => vec![],
+    };
+    self.execute(intents);
+
+    for fill in fills {
+      if self.breached() {
+        return;
+      }
+      let intents = self.strategy.on_fill(&self.context, &fill);
+      self.execute(intents);
+    }
+
+    if self.breached() {
+      return;
+    }
+
+    if let (Some(interval), Some(now)) = (self.timer, self.context.now) {
+      let next = *self.next_timer.get_or_insert(now + interval);
+      if now >= next {
+        let mut next = next;
+        while next <= now {
+          next = next + interval;
+        }
+        self.next_timer = Some(next);
+        let intents = self.strategy.on_timer(&self.context, now);
+        self.execute(intents);
+      }
+    }
+  }
+
+  fn execute(&mut self, intents: Vec<Intent>) {
+    for intent in intents {
+      if self.breached() {
+        return;
+      }
+
+      let pending: Pending = match intent {
+        Intent::Place(order) => {
+          let order = match order.client_oid {
+            Some(_) => order,
+            None    => order.client_oid(Uuid::new_v4()),
+          };
+          self.context.placing(&order);
+          Box::new(self.order_entry.place_in(&self.context, order.clone())
+            .then(move |result| Ok(Completion::Placed(order, result))))
+        }
+        Intent::Cancel(id) => Box::new(self.order_entry.cancel_in(&self.context, id)
+          .map(move |()| vec![id])
+          .then(|result| Ok(Completion::Canceled(result)))),
+        Intent::CancelAll(product) => Box::new(self.order_entry.cancel_all_in(&self.context, product)
+          .then(|result| Ok(Completion::Canceled(result)))),
+      };
+
+      if let Some(violation) = self.order_entry.risk().breach().cloned() {
+        self.kill(violation);
+        return;
+      }
+      self.pending.push(pending);
+    }
+  }
+
+  /// Cancel all open orders after a risk limit breach
+  fn kill(&mut self, violation: RiskViolation) {
+    error!("kill switch tripped, canceling all orders: {}", violation);
+    self.pending.push(Box::new(self.order_entry.cancel_all_in(&self.context, None)
+      .then(|result| Ok(Completion::Canceled(result)))));
+  }
+
+  fn complete(&mut self, completion: Completion) {
+    match completion {
+      Completion::Placed(order, Ok(placed)) => {
+        self.context.placed(placed.id, order);
+        if self.breached() {
+          let id = placed.id;
+          self.pending.push(Box::new(self.order_entry.cancel_in(&self.context, id)
+            .map(move |()| vec![id])
+            .then(|result| Ok(Completion::Canceled(result)))));
+        }
+      }
+      Completion::Placed(order, Err(error)) => {
+        warn!("failed to place {:?}: {}", order, error);
+        self.context.failed(&order);
+      }
+      Completion::Canceled(Ok(ids)) => for id in ids {
+        self.context.finished(id);
+      },
+      Completion::Canceled(Err(error)) => warn!("failed to cancel: {}", error),
+    }
+  }
+
+  /// Pass order events to the strategy, returning whether there were any.
+  /// Fills already seen on the feed aren't passed again.
+  fn drain_events(&mut self) -> bool {
+    let events = self.order_entry.inner_mut().events();
+    let any = !events.is_empty();
+    for event in events {
+      match event {
+        OrderEvent::Fill(fill) => {
+          if self.context.fill(&fill) && !self.breached() {
+            let intents = self.strategy.on_fill(&self.context, &fill);
+            self.execute(intents);
+          }
+        }
+        OrderEvent::Done(id) => self.context.finished(id),
+      }
+    }
+    any
+  }
+}
+
+/// Wall clock timer that fires once the feed has been silent for longer
+/// than `stale_after`
+struct Watchdog {
+  timeout:     Timeout,
+  last:        Instant,
+  stale_after: time::Duration,
+}
+
+impl Watchdog {
+  fn new(handle: &Handle, stale_after: time::Duration) -> io::Result<Watchdog> {
+    let last = Instant::now();
+    Ok(Watchdog {
+      timeout: Timeout::new_at(Watchdog::deadline(last, stale_after), handle)?,
+      last, stale_after,
+    })
+  }
+
+  /// Just after `stale_after` has passed since `last`
+  fn deadline(last: Instant, stale_after: time::Duration) -> Instant {
+    last + stale_after + time::Duration::from_millis(1)
+  }
+
+  /// Restart when a message arrives
+  fn reset(&mut self) {
+    self.last = Instant::now();
+    self.timeout.reset(Watchdog::deadline(self.last, self.stale_after));
+  }
+
+  /// Resolve to how long the feed has been silent
+  fn poll(&mut self) -> Poll<Duration, io::Error> {
+    try_ready!(self.timeout.poll());
+    let silent = Duration::from_std(self.last.elapsed()).unwrap_or_else(|_| Duration::max_value());
+    self.reset();
+    Ok(Async::Ready(silent))
+  }
+}
+
+/// Future returned by `Engine::run`
+pub struct Run<S, E, M> {
+  engine:   Option<Engine<S, E>>,
+  messages: M,
+  finished: bool,
+  watchdog: Option<Watchdog>,
+}
+
+impl<S, E, M> Future for Run<S, E, M>
+  where S: Strategy,
+        E: OrderEntry,
+        M: Stream<Error=FeedError>,
+        M::Item: Into<FeedEvent>,
+{
+  type Item  = Engine<S, E>;
+  type Error = EngineError;
+
+  fn poll(&mut self) -> Poll<Engine<S, E>, EngineError> {
+    loop {
+      let engine = self.engine.as_mut().expect("polled engine after completion");
+
+      while let Ok(Async::Ready(Some(completion))) = engine.pending.poll() {
+        engine.complete(completion);
+      }
+
+      if engine.drain_events() {
+        continue;
+      }
+
+      if self.finished || engine.breached() {
+        if engine.pending.is_empty() {
+          break;
+        }
+        return Ok(Async::NotReady);
+      }
+
+      if self.watchdog.is_none() {
+        if let (Some(handle), Some(stale_after)) = (engine.watchdog.as_ref(), engine.risk().stale_after()) {
+          let stale_after = stale_after.to_std().expect("negative stale feed limit");
+          self.watchdog = Some(Watchdog::new(handle, stale_after).map_err(EngineError::Timer)?);
+        }
+      }
+
+      if let Some(ref mut watchdog) = self.watchdog {
+        if let Async::Ready(silent) = watchdog.poll().map_err(EngineError::Timer)? {
+          if let Err(violation) = engine.order_entry.risk_mut().check_silence(silent) {
+            engine.kill(violation);
+          }
+          continue;
+        }
+      }
+
+      match self.messages.poll().map_err(EngineError::Feed)? {
+        Async::Ready(Some(event)) => {
+          if let Some(ref mut watchdog) = self.watchdog {
+            watchdog.reset();
+          }
+          match event.into() {
+            FeedEvent::Message(message) => engine.handle(&message),
+            FeedEvent::Discontinuity    => engine.discontinuity(),
+          }
+        }
+        Async::Ready(None) => self.finished = true,
+        Async::NotReady    => return Ok(Async::NotReady),
+      }
+    }
+
+    Ok(Async::Ready(self.engine.take().unwrap()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use futures::{future, stream};
+  use serde_json;
+
+  use gdax::feed::message::{Side, Ticker};
+  use gdax::fixtures::{id, match_text, products, snapshot, trade};
+  use gdax::order_entry::OrderFuture;
+  use gdax::rest::message::{Fill, Liquidity, OrderStatus};
+  use gdax::simulator::Simulator;
+  use tokio_core::reactor::Core;
+
+  /// Joins the best bid once, and counts the events it sees
+  #[derive(Default)]
+  struct Joiner {
+    books:   usize,
+    trades:  usize,
+    tickers: usize,
+    timers:  Vec<DateTime>,
+    fills:   Vec<Fill>,
+    placed:  bool,
+  }
+
+  impl Strategy for Joiner {
+    fn on_book(&mut self, context: &Context, product: Product, book: &OrderBook) -> Vec<Intent> {
+      self.books += 1;
+      if self.placed {
+        return vec![];
+      }
+      self.placed = true;
+      assert!(context.open_orders().is_empty());
+      let (price, _) = book.best_bid().unwrap();
+      vec![Intent::Place(NewOrder::limit(product, Side::Buy, price, "1.0".parse().unwrap()).post_only())]
+    }
+
+    fn on_trade(&mut self, context: &Context, _product: Product, _trade: &Trade) -> Vec<Intent> {
+      self.trades += 1;
+      assert_eq!(context.open_orders().len(), if self.fills.is_empty() { 1 } else { 0 });
+      vec![]
+    }
+
+    fn on_ticker(&mut self, _context: &Context, _ticker: &Ticker) -> Vec<Intent> {
+      self.tickers += 1;
+      vec![]
+    }
+
+    fn on_timer(&mut self, _context: &Context, now: DateTime) -> Vec<Intent> {
+      self.timers.push(now);
+      vec![]
+    }
+
+    fn on_fill(&mut self, _context: &Context, fill: &Fill) -> Vec<Intent> {
+      self.fills.push(fill.clone());
+      vec![Intent::CancelAll(None)]
+    }
+  }
+
+  fn message(text: &str) -> IncomingMessage {
+    serde_json::from_str(text).unwrap()
+  }
+
+  #[test]
+  fn simulated() {
+    let messages = vec![
+      snapshot(),
+      trade(0, "buy", "100.00", "1.5"),
+      message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["buy", "100.00", "0.5"]]}"#),
+      trade(5, "buy", "100.00", "2.0"),
+      message(r#"{"type": "ticker", "product_id": "BTC-USD", "sequence": 2, "price": "100.00", "best_bid": "100.00",
+        "best_ask": "101.00", "high_24h": "0", "open_24h": "0", "low_24h": "0", "volume_24h": "0",
+        "volume_30d": "0"}"#),
+      trade(11, "buy", "100.00", "1.0"),
+    ];
+
+    let engine = Engine::new(Joiner::default(), Simulator::new(products()))
+      .timer(Duration::seconds(5))
+      .run(stream::iter_ok(messages))
+      .wait()
+      .unwrap();
+
+    let strategy = engine.strategy();
+    assert_eq!(strategy.books, 2);
+    assert_eq!(strategy.trades, 3);
+    assert_eq!(strategy.tickers, 1);
+    assert_eq!(
+      strategy.timers.iter().map(|time| time.to_rfc3339()).collect::<Vec<_>>(),
+      vec!["2017-10-01T00:00:05+00:00", "2017-10-01T00:00:11+00:00"],
+    );
+    assert_eq!(strategy.fills.len(), 1);
+    assert_eq!(strategy.fills[0].size, "1.0".parse().unwrap());
+    assert!(engine.context().open_orders().is_empty());
+  }
+
+  /// Acknowledges orders as order 1, reporting no events, like the exchange
+  struct Acknowledger;
+
+  impl OrderEntry for Acknowledger {
+    fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder> {
+      let id = id(1);
+      Box::new(future::ok(PlacedOrder{id, client_oid: order.client_oid.unwrap_or(id)}))
+    }
+
+    fn cancel(&mut self, _id: Uuid) -> OrderFuture<()> {
+      Box::new(future::ok(()))
+    }
+
+    fn cancel_all(&mut self, _product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+      Box::new(future::ok(vec![]))
+    }
+  }
+
+  #[test]
+  fn feed_fills() {
+    let messages = vec![
+      message(r#"{"type": "l2update", "product_id": "ETH-USD", "changes": [["buy", "300.00", "1.0"]]}"#),
+      snapshot(),
+      message(&match_text(1, id(1), Uuid::nil(), "buy", "100.00", "0.5")),
+    ];
+
+    let engine = Engine::new(Joiner::default(), Acknowledger)
+      .run(stream::iter_ok(messages))
+      .wait()
+      .unwrap();
+
+    let strategy = engine.strategy();
+    assert_eq!(strategy.books, 1);
+    assert_eq!(strategy.fills.len(), 1);
+    assert_eq!(strategy.fills[0].size, "0.5".parse().unwrap());
+    assert_eq!(strategy.fills[0].liquidity, Liquidity::Maker);
+  }
+
+  #[test]
+  fn discontinuity() {
+    let update = message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["buy", "100.00", "1.0"]]}"#);
+    let events = vec![FeedEvent::Message(snapshot()), FeedEvent::Discontinuity, FeedEvent::Message(update)];
+
+    let engine = Engine::new(Joiner::default(), Simulator::new(products()))
+      .run(stream::iter_ok(events))
+      .wait()
+      .unwrap();
+
+    assert_eq!(engine.strategy().books, 1);
+    assert!(engine.context().book("BTC-USD".parse().unwrap()).is_none());
+    assert!(engine.order_entry().book("BTC-USD".parse().unwrap()).is_none());
+  }
+
+  /// Bids one more at the best bid on every book update
+  #[derive(Default)]
+  struct Stacker {
+    books: usize,
+  }
+
+  impl Strategy for Stacker {
+    fn on_book(&mut self, _context: &Context, product: Product, book: &OrderBook) -> Vec<Intent> {
+      self.books += 1;
+      let (price, _) = book.best_bid().unwrap();
+      vec![Intent::Place(NewOrder::limit(product, Side::Buy, price, "1.0".parse().unwrap()))]
+    }
+  }
+
+  #[test]
+  fn watchdog() {
+    let mut core = Core::new().unwrap();
+    let silent = stream::poll_fn(|| Ok(Async::NotReady));
+
+    let limits = serde_json::from_str(r#"{"stale_after_seconds": 1}"#).unwrap();
+    let engine = Engine::new(Stacker::default(), Simulator::new(products()))
+      .risk_limits(limits)
+      .watchdog(&core.handle());
+    let engine = core.run(engine.run(stream::iter_ok(vec![snapshot()]).chain(silent))).unwrap();
+
+    assert_eq!(engine.risk().breach(), Some(&RiskViolation::StaleFeed{seconds: 1}));
+    assert!(engine.order_entry().orders().values().all(|order| order.status != OrderStatus::Open));
+  }
+
+  #[test]
+  fn kill_switch() {
+    let messages = vec![
+      snapshot(),
+      message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["buy", "100.00", "1.0"]]}"#),
+      message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["buy", "100.00", "3.0"]]}"#),
+    ];
+
+    let limits = serde_json::from_str(r#"{"max_position": {"BTC-USD": "1.5"}}"#).unwrap();
+    let engine = Engine::new(Stacker::default(), Simulator::new(products()))
+      .risk_limits(limits)
+      .run(stream::iter_ok(messages))
+      .wait()
+      .unwrap();
+
+    assert_eq!(engine.strategy().books, 2);
+    match engine.risk().breach() {
+      Some(&RiskViolation::Position{position, ..}) => assert_eq!(position, "2.0".parse().unwrap()),
+      breach => panic!("unexpected breach: {:?}", breach),
+    }
+    assert!(engine.context().open_orders().is_empty());
+    assert!(engine.order_entry().orders().values().all(|order| order.status != OrderStatus::Open));
+  }
+}
@@ -1,4 +1,10 @@
 use clap;
+use engine::EngineError;
+use gdax::auth::AuthError;
+use gdax::feed::FeedError;
+use gdax::order_entry::OrderError;
+use gdax::products::ProductsError;
+use gdax::rest::RestError;
 
 error_chain! {
   types {
@@ -7,6 +13,12 @@ error_chain! {
 
   foreign_links {
     Clap(clap::Error);
+    Auth(AuthError);
+    Engine(EngineError);
+    Feed(FeedError);
+    Order(OrderError);
+    Products(ProductsError);
+    Rest(RestError);
   }
 
   errors {
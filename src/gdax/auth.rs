@@ -0,0 +1,228 @@
+use base64;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json;
+use sha2::Sha256;
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use gdax::feed::message::OutgoingMessage;
+
+const KEY_VARIABLE: &'static str = "GDAX_API_KEY";
+const SECRET_VARIABLE: &'static str = "GDAX_API_SECRET";
+const PASSPHRASE_VARIABLE: &'static str = "GDAX_API_PASSPHRASE";
+
+/// Request the feed signs subscribe messages as
+const FEED_METHOD: &'static str = "GET";
+const FEED_PATH: &'static str = "/users/self/verify";
+
+#[derive(Debug)]
+pub enum AuthError {
+  Config(serde_json::Error),
+  Io(io::Error),
+  MissingVariable(&'static str),
+  Secret(base64::DecodeError),
+}
+
+impl Display for AuthError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      AuthError::Config(ref error)     => write!(f, "invalid credentials file: {}", error),
+      AuthError::Io(ref error)         => write!(f, "failed to read credentials file: {}", error),
+      AuthError::MissingVariable(name) => write!(f, "environment variable {} is not set", name),
+      AuthError::Secret(ref error)     => write!(f, "API secret is not valid base64: {}", error),
+    }
+  }
+}
+
+impl Error for AuthError {
+  fn description(&self) -> &str {
+    match *self {
+      AuthError::Config(_)          => "invalid credentials file",
+      AuthError::Io(_)              => "failed to read credentials file",
+      AuthError::MissingVariable(_) => "environment variable is not set",
+      AuthError::Secret(_)          => "API secret is not valid base64",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      AuthError::Config(ref error)  => Some(error),
+      AuthError::Io(ref error)      => Some(error),
+      AuthError::Secret(ref error)  => Some(error),
+      AuthError::MissingVariable(_) => None,
+    }
+  }
+}
+
+/// Contents of a credentials file
+#[derive(Deserialize)]
+struct Config {
+  key:        String,
+  secret:     String,
+  passphrase: String,
+}
+
+/// An API key, used to sign requests
+#[derive(Clone)]
+pub struct Credentials {
+  key:        String,
+  secret:     Vec<u8>,
+  passphrase: String,
+}
+
+impl fmt::Debug for Credentials {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "Credentials {{ key: {:?}, .. }}", self.key)
+  }
+}
+
+impl Credentials {
+  /// Credentials for `key`, with its base64 encoded `secret`
+  pub fn new(key: &str, secret: &str, passphrase: &str) -> Result<Credentials, AuthError> {
+    Ok(Credentials {
+      key:        key.to_string(),
+      secret:     base64::decode(secret).map_err(AuthError::Secret)?,
+      passphrase: passphrase.to_string(),
+    })
+  }
+
+  /// Read credentials from `GDAX_API_KEY`, `GDAX_API_SECRET`, and
+  /// `GDAX_API_PASSPHRASE`, or return `None` if none of them are set
+  pub fn from_env() -> Result<Option<Credentials>, AuthError> {
+    let key        = env::var(KEY_VARIABLE).ok();
+    let secret     = env::var(SECRET_VARIABLE).ok();
+    let passphrase = env::var(PASSPHRASE_VARIABLE).ok();
+
+    match (key, secret, passphrase) {
+      (None, None, None) => Ok(None),
+      (Some(key), Some(secret), Some(passphrase))
+        => Credentials::new(&key, &secret, &passphrase).map(Some),
+      (None, _, _) => Err(AuthError::MissingVariable(KEY_VARIABLE)),
+      (_, None, _) => Err(AuthError::MissingVariable(SECRET_VARIABLE)),
+      (_, _, None) => Err(AuthError::MissingVariable(PASSPHRASE_VARIABLE)),
+    }
+  }
+
+  /// Load credentials from a JSON file with `key`, `secret`, and `passphrase`
+  /// fields
+  pub fn load(path: &Path) -> Result<Credentials, AuthError> {
+    let file = File::open(path).map_err(AuthError::Io)?;
+    let config: Config = serde_json::from_reader(file).map_err(AuthError::Config)?;
+    Credentials::new(&config.key, &config.secret, &config.passphrase)
+  }
+
+  pub fn key(&self) -> &str {
+    &self.key
+  }
+
+  pub fn passphrase(&self) -> &str {
+    &self.passphrase
+  }
+
+  fn mac(&self, timestamp: &str, method: &str, path: &str, body: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(&self.secret).expect("HMAC accepts keys of any length");
+    mac.input(timestamp.as_bytes());
+    mac.input(method.as_bytes());
+    mac.input(path.as_bytes());
+    mac.input(body.as_bytes());
+    mac
+  }
+
+  /// The base64 encoded HMAC-SHA256 signature of a request
+  pub fn sign(&self, timestamp: &str, method: &str, path: &str, body: &str) -> String {
+    base64::encode(&self.mac(timestamp, method, path, body).result().code())
+  }
+
+  /// Check the base64 encoded `signature` of a request
+  pub fn verify(&self, signature: &str, timestamp: &str, method: &str, path: &str, body: &str) -> bool {
+    match base64::decode(signature) {
+      Ok(signature) => self.mac(timestamp, method, path, body).verify(&signature).is_ok(),
+      Err(_)        => false,
+    }
+  }
+
+  /// Sign `message` with the current time, if it is a subscribe message
+  pub fn authenticate(&self, message: &mut OutgoingMessage) {
+    self.authenticate_at(message, &Utc::now().timestamp().to_string())
+  }
+
+  fn authenticate_at(&self, message: &mut OutgoingMessage, now: &str) {
+    if let OutgoingMessage::Subscribe {
+      ref mut key,
+      ref mut passphrase,
+      ref mut timestamp,
+      ref mut signature,
+      ..
+    } = *message {
+      *key        = Some(self.key.clone());
+      *passphrase = Some(self.passphrase.clone());
+      *timestamp  = Some(now.to_string());
+      *signature  = Some(self.sign(now, FEED_METHOD, FEED_PATH, ""));
+    }
+  }
+
+  /// Check that `message` is a subscribe message signed with these credentials
+  pub fn verify_subscribe(&self, message: &OutgoingMessage) -> bool {
+    match *message {
+      OutgoingMessage::Subscribe {
+        key:        Some(ref key),
+        passphrase: Some(ref passphrase),
+        timestamp:  Some(ref timestamp),
+        signature:  Some(ref signature),
+        ..
+      } => {
+        *key == self.key && *passphrase == self.passphrase
+          && self.verify(signature, timestamp, FEED_METHOD, FEED_PATH, "")
+      }
+      _ => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn credentials() -> Credentials {
+    Credentials::new("key", &base64::encode(b"secret"), "passphrase").unwrap()
+  }
+
+  #[test]
+  fn sign() {
+    // echo -n '1500000000GET/users/self/verify' | openssl dgst -sha256 -hmac secret -binary | base64
+    assert_eq!(
+      credentials().sign("1500000000", "GET", "/users/self/verify", ""),
+      "WUY/Lz4V9Jt2eonS58a6zdYr5IGzdS6cO385gN7oKxI=",
+    );
+  }
+
+  #[test]
+  fn authenticate_subscribe() {
+    let credentials = credentials();
+    let mut message = OutgoingMessage::subscribe(vec![]);
+    assert!(!credentials.verify_subscribe(&message));
+
+    credentials.authenticate_at(&mut message, "1500000000");
+    assert!(credentials.verify_subscribe(&message));
+
+    let text = serde_json::to_string(&message).unwrap();
+    assert!(text.contains(r#""key":"key""#));
+    assert!(text.contains(r#""timestamp":"1500000000""#));
+
+    let other = Credentials::new("key", &base64::encode(b"other"), "passphrase").unwrap();
+    assert!(!other.verify_subscribe(&message));
+  }
+
+  #[test]
+  fn bad_secret() {
+    match Credentials::new("key", "not base64!", "passphrase") {
+      Err(AuthError::Secret(_)) => {}
+      other => panic!("expected secret error: {:?}", other),
+    }
+  }
+}
@@ -0,0 +1,420 @@
+use futures::prelude::*;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+
+use gdax::feed::FeedError;
+use gdax::feed::message::{IncomingMessage, Product};
+use gdax::feed::reconnect::{FeedEvent, MAX_BACKOFF, MIN_BACKOFF};
+use gdax::full_order_book::{FullOrderBook, FullOrderBookError};
+use gdax::rest::{RestClient, RestError, RestFuture};
+use gdax::rest::message::FullBook;
+
+#[derive(Debug)]
+pub enum BootstrapError {
+  Book(FullOrderBookError),
+  Rest(RestError),
+  /// The snapshot is older than the first buffered message, so messages
+  /// between them were missed
+  SnapshotBehind{snapshot: u64, buffered: u64},
+}
+
+impl Display for BootstrapError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      BootstrapError::Book(ref error) => write!(f, "invalid book: {:?}", error),
+      BootstrapError::Rest(ref error) => write!(f, "failed to fetch snapshot: {}", error),
+      BootstrapError::SnapshotBehind{snapshot, buffered}
+        => write!(f, "snapshot at sequence {} is older than buffered message {}", snapshot, buffered),
+    }
+  }
+}
+
+impl Error for BootstrapError {
+  fn description(&self) -> &str {
+    match *self {
+      BootstrapError::Book(_)            => "invalid book",
+      BootstrapError::Rest(_)            => "failed to fetch snapshot",
+      BootstrapError::SnapshotBehind{..} => "snapshot is older than buffered messages",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      BootstrapError::Rest(ref error) => Some(error),
+      BootstrapError::Book(_)            |
+      BootstrapError::SnapshotBehind{..} => None,
+    }
+  }
+}
+
+/// Build a book from `snapshot`, and then apply the `buffered` messages that
+/// are newer than it
+fn build(product_id: Product, snapshot: &FullBook, buffered: &[IncomingMessage])
+  -> Result<FullOrderBook, BootstrapError>
+{
+  if let Some(first) = buffered.iter().filter_map(IncomingMessage::sequence).next() {
+    if first > snapshot.sequence + 1 {
+      return Err(BootstrapError::SnapshotBehind{snapshot: snapshot.sequence, buffered: first});
+    }
+  }
+
+  let mut book = FullOrderBook::from_snapshot(product_id, snapshot).map_err(BootstrapError::Book)?;
+  for message in buffered {
+    book.apply(message).map_err(BootstrapError::Book)?;
+  }
+  Ok(book)
+}
+
+/// Whether `message` is from the full channel and changes resting orders
+fn is_full(message: &IncomingMessage) -> bool {
+  use self::IncomingMessage::*;
+  match *message {
+    Received{..} | Open{..} | Done{..} | Match{..} | Change{..} => true,
+    _                                                          => false,
+  }
+}
+
+enum State {
+  /// Waiting for the feed to confirm its subscriptions
+  Subscribing,
+  /// Buffering messages while a snapshot is fetched, waiting `backoff`
+  /// seconds before fetching another if it fails
+  Fetching{snapshot: RestFuture<FullBook>, buffer: Vec<IncomingMessage>, backoff: u64},
+  /// Buffering messages until it's time to fetch a snapshot again
+  Waiting{timeout: Timeout, buffer: Vec<IncomingMessage>, backoff: u64},
+  Ready(FullOrderBook),
+}
+
+/// Maintains a `FullOrderBook` for each of a set of products, from a feed
+/// subscribed to the full channel for them, yielding the feed's events
+/// unchanged.
+///
+/// Once the feed confirms its subscriptions, messages are buffered while a
+/// level-3 snapshot is fetched, and buffered messages newer than the
+/// snapshot are applied to it. Books are bootstrapped again after a
+/// discontinuity, and if a message fails to apply. Snapshots that can't be
+/// fetched are retried with exponential backoff.
+pub struct FullBooks<S> {
+  feed:   S,
+  rest:   RestClient,
+  handle: Handle,
+  states: BTreeMap<Product, State>,
+}
+
+impl<S> FullBooks<S>
+  where S: Stream<Item=FeedEvent, Error=FeedError>
+{
+  pub fn new(feed: S, rest: RestClient, products: &[Product], handle: &Handle) -> FullBooks<S> {
+    FullBooks {
+      states: products.iter().map(|&product| (product, State::Subscribing)).collect(),
+      handle: handle.clone(),
+      feed, rest,
+    }
+  }
+
+  /// The book for `product`, once it has been bootstrapped
+  pub fn book(&self, product: Product) -> Option<&FullOrderBook> {
+    match self.states.get(&product) {
+      Some(&State::Ready(ref book)) => Some(book),
+      _                             => None,
+    }
+  }
+
+  fn fetch(rest: &RestClient, product: Product, buffer: Vec<IncomingMessage>, backoff: u64) -> State {
+    info!("fetching {} level 3 snapshot", product);
+    State::Fetching{snapshot: rest.full_book(product), buffer, backoff}
+  }
+
+  fn wait(handle: &Handle, product: Product, buffer: Vec<IncomingMessage>, backoff: u64)
+    -> Result<State, FeedError>
+  {
+    info!("fetching {} level 3 snapshot again in {} seconds", product, backoff);
+    let timeout = Timeout::new(Duration::from_secs(backoff), handle).map_err(FeedError::Timer)?;
+    Ok(State::Waiting{timeout, buffer, backoff: cmp::min(backoff * 2, MAX_BACKOFF)})
+  }
+
+  fn handle(&mut self, event: &FeedEvent) {
+    let message = match *event {
+      FeedEvent::Discontinuity => {
+        for state in self.states.values_mut() {
+          *state = State::Subscribing;
+        }
+        return;
+      }
+      FeedEvent::Message(ref message) => message,
+    };
+
+    if let IncomingMessage::Subscriptions{..} = *message {
+      for (&product, state) in &mut self.states {
+        if let State::Subscribing = *state {
+          *state = FullBooks::<S>::fetch(&self.rest, product, vec![], MIN_BACKOFF);
+        }
+      }
+      return;
+    }
+
+    let product = match message.product_id() {
+      Some(product) if is_full(message) => product,
+      _                                 => return,
+    };
+
+    let failed = match self.states.get_mut(&product) {
+      Some(&mut State::Fetching{ref mut buffer, ..}) |
+      Some(&mut State::Waiting{ref mut buffer, ..}) => {
+        buffer.push(message.clone());
+        None
+      }
+      Some(&mut State::Ready(ref mut book)) => book.apply(message).err(),
+      Some(&mut State::Subscribing) | None => None,
+    };
+
+    if let Some(error) = failed {
+      warn!("bootstrapping {} book again after failing to apply {}: {:?}", product, message.type_name(), error);
+      self.states.insert(product, FullBooks::<S>::fetch(&self.rest, product, vec![], MIN_BACKOFF));
+    }
+  }
+
+  /// Poll the snapshot being fetched or waited for by each product, until
+  /// none are ready, so that retries register wakeups
+  fn poll_snapshots(&mut self) -> Result<(), FeedError> {
+    for (&product, state) in &mut self.states {
+      loop {
+        let next = match *state {
+          State::Waiting{ref mut timeout, ref mut buffer, backoff} => {
+            match timeout.poll().map_err(FeedError::Timer)? {
+              Async::Ready(()) => FullBooks::<S>::fetch(&self.rest, product, buffer.split_off(0), backoff),
+              Async::NotReady  => break,
+            }
+          }
+          State::Fetching{ref mut snapshot, ref mut buffer, backoff} => {
+            let result = match snapshot.poll() {
+              Ok(Async::Ready(snapshot)) => {
+                info!(
+                  "bootstrapping {} book from snapshot at sequence {} and {} buffered messages",
+                  product, snapshot.sequence, buffer.len(),
+                );
+                build(product, &snapshot, buffer)
+              }
+              Ok(Async::NotReady) => break,
+              Err(error)          => Err(BootstrapError::Rest(error)),
+            };
+
+            match result {
+              Ok(book) => State::Ready(book),
+              Err(error) => {
+                warn!("failed to bootstrap {} book: {}", product, error);
+                // a book error means a buffered message is bad, so start over without them
+                let buffer = match error {
+                  BootstrapError::Book(_) => vec![],
+                  _                       => buffer.split_off(0),
+                };
+                FullBooks::<S>::wait(&self.handle, product, buffer, backoff)?
+              }
+            }
+          }
+          State::Subscribing | State::Ready(_) => break,
+        };
+        *state = next;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<S> Stream for FullBooks<S>
+  where S: Stream<Item=FeedEvent, Error=FeedError>
+{
+  type Item  = FeedEvent;
+  type Error = FeedError;
+
+  fn poll(&mut self) -> Poll<Option<FeedEvent>, FeedError> {
+    self.poll_snapshots()?;
+    let event = try_ready!(self.feed.poll());
+    if let Some(ref event) = event {
+      self.handle(event);
+      self.poll_snapshots()?;
+    }
+    Ok(Async::Ready(event))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use futures::future;
+  use gdax::feed::FeedBuilder;
+  use gdax::feed::message::{Channel, QuoteCurrencyPrice, Side};
+  use gdax::feed::mock::{MockEvent, MockFeed};
+  use gdax::feed::reconnect::ReconnectingFeed;
+  use gdax::fixtures::{id, open, open_text, text};
+  use gdax::rest::RestBuilder;
+  use gdax::rest::mock::{MockResponse, MockRest};
+  use serde_json;
+  use std::time::Instant;
+  use tokio_core::reactor::Core;
+
+  /// A mock feed event sending a BTC-USD open message for a bid
+  fn bid(sequence: u64, order: u8, price: &str, size: &str) -> MockEvent {
+    MockEvent::Text(open_text("BTC-USD", sequence, order, "buy", price, size))
+  }
+
+  const SNAPSHOT: &'static str = r#"{
+    "sequence": 3,
+    "bids": [
+      ["100.0", "1.0", "00000000-0000-0000-0000-000000000001"],
+      ["100.0", "2.0", "00000000-0000-0000-0000-000000000002"]
+    ],
+    "asks": [["101.0", "5.0", "00000000-0000-0000-0000-000000000005"]]
+  }"#;
+
+  fn price(price: &str) -> QuoteCurrencyPrice {
+    serde_json::from_str(&format!(r#""{}""#, price)).unwrap()
+  }
+
+  #[test]
+  fn stale_messages_are_skipped() {
+    let snapshot: FullBook = serde_json::from_str(SNAPSHOT).unwrap();
+    let buffered = vec![open(3, 3, "buy", "100.0", "9.0"), open(4, 4, "buy", "100.0", "4.0")];
+
+    let book = build("BTC-USD".parse().unwrap(), &snapshot, &buffered).unwrap();
+    assert_eq!(book.sequence(), 4);
+    let queue = book.orders_at(Side::Buy, price("100.0")).unwrap();
+    assert_eq!(queue.iter().map(|order| order.order_id).collect::<Vec<_>>(), vec![id(1), id(2), id(4)]);
+
+    assert!(build("BTC-USD".parse().unwrap(), &snapshot, &buffered[1..]).is_ok());
+
+    let late = [open(6, 6, "buy", "100.0", "1.0")];
+    match build("BTC-USD".parse().unwrap(), &snapshot, &late) {
+      Err(BootstrapError::SnapshotBehind{snapshot: 3, buffered: 6}) => {}
+      other => panic!("expected snapshot behind: {:?}", other.map(|book| book.sequence())),
+    }
+  }
+
+  /// Poll `books` until the book for `product` reaches `sequence`
+  fn run_until<S>(core: &mut Core, books: &mut FullBooks<S>, product: Product, sequence: u64)
+    where S: Stream<Item=FeedEvent, Error=FeedError>
+  {
+    core.run(future::poll_fn(|| -> Poll<(), FeedError> {
+      while let Async::Ready(Some(_)) = books.poll()? {}
+      if books.book(product).map(FullOrderBook::sequence) == Some(sequence) {
+        Ok(Async::Ready(()))
+      } else {
+        Ok(Async::NotReady)
+      }
+    })).unwrap();
+  }
+
+  #[test]
+  fn bootstrap() {
+    let mut core = Core::new().unwrap();
+    let product = "BTC-USD".parse().unwrap();
+
+    let rest = MockRest::new()
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(SNAPSHOT))
+      .bind()
+      .unwrap();
+
+    let feed = MockFeed::new()
+      .script(vec![
+        bid(2, 9, "99.0", "1.0"),
+        bid(4, 4, "100.0", "4.0"),
+        MockEvent::Text(text("BTC-USD", 5, &format!(
+          r#"{{"type": "match", "maker_order_id": "{}", "taker_order_id": "{}", "side": "buy",
+              "price": "100.0", "size": "0.25", "trade_id": 1}}"#,
+          id(1), id(8),
+        ))),
+        MockEvent::Text(text("BTC-USD", 6, &format!(
+          r#"{{"type": "done", "order_id": "{}", "side": "buy", "reason": "canceled", "price": "100.0",
+              "remaining_size": "2.0"}}"#,
+          id(2),
+        ))),
+      ])
+      .bind(&"127.0.0.1:0".parse().unwrap(), &core.handle())
+      .unwrap();
+    let url = feed.url();
+    core.handle().spawn(feed.map_err(|error| panic!("mock feed failed: {}", error)));
+
+    let feed = ReconnectingFeed::new(FeedBuilder::new().url(url).subscribe(Channel::Full, product), &core.handle())
+      .unwrap();
+    let client = RestBuilder::new().url(&rest.url()).build(&core.handle()).unwrap();
+
+    let mut books = FullBooks::new(feed, client, &[product], &core.handle());
+    run_until(&mut core, &mut books, product, 6);
+
+    let book = books.book(product).unwrap();
+    let queue = book.orders_at(Side::Buy, price("100.0")).unwrap();
+    assert_eq!(queue.iter().map(|order| order.order_id).collect::<Vec<_>>(), vec![id(1), id(4)]);
+    assert_eq!(queue[0].size, serde_json::from_str(r#""0.75""#).unwrap());
+    assert!(book.orders_at(Side::Buy, price("99.0")).is_none());
+    assert_eq!(rest.requests()[0].path, "/products/BTC-USD/book?level=3");
+  }
+
+  #[test]
+  fn rebootstrap_after_discontinuity() {
+    let mut core = Core::new().unwrap();
+    let product = "BTC-USD".parse().unwrap();
+
+    let rest = MockRest::new()
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(SNAPSHOT))
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(r#"{
+        "sequence": 10,
+        "bids": [["100.0", "1.0", "00000000-0000-0000-0000-000000000001"]],
+        "asks": []
+      }"#))
+      .bind()
+      .unwrap();
+
+    let feed = MockFeed::new()
+      .script(vec![bid(4, 4, "100.0", "4.0"), MockEvent::Close])
+      .script(vec![bid(11, 6, "100.0", "6.0")])
+      .bind(&"127.0.0.1:0".parse().unwrap(), &core.handle())
+      .unwrap();
+    let url = feed.url();
+    core.handle().spawn(feed.map_err(|error| panic!("mock feed failed: {}", error)));
+
+    let feed = ReconnectingFeed::new(FeedBuilder::new().url(url).subscribe(Channel::Full, product), &core.handle())
+      .unwrap();
+    let client = RestBuilder::new().url(&rest.url()).build(&core.handle()).unwrap();
+
+    let mut books = FullBooks::new(feed, client, &[product], &core.handle());
+    run_until(&mut core, &mut books, product, 11);
+
+    let queue = books.book(product).unwrap().orders_at(Side::Buy, price("100.0")).unwrap();
+    assert_eq!(queue.iter().map(|order| order.order_id).collect::<Vec<_>>(), vec![id(1), id(6)]);
+    assert!(rest.requests().len() >= 2);
+  }
+
+  #[test]
+  fn retry_failed_snapshot() {
+    let mut core = Core::new().unwrap();
+    let product = "BTC-USD".parse().unwrap();
+
+    let rest = MockRest::new()
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(r#"{"message": "bad request"}"#).status(400))
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(SNAPSHOT))
+      .bind()
+      .unwrap();
+
+    let feed = MockFeed::new()
+      .script(vec![bid(4, 4, "100.0", "4.0")])
+      .bind(&"127.0.0.1:0".parse().unwrap(), &core.handle())
+      .unwrap();
+    let url = feed.url();
+    core.handle().spawn(feed.map_err(|error| panic!("mock feed failed: {}", error)));
+
+    let feed = ReconnectingFeed::new(FeedBuilder::new().url(url).subscribe(Channel::Full, product), &core.handle())
+      .unwrap();
+    let client = RestBuilder::new().url(&rest.url()).build(&core.handle()).unwrap();
+
+    let start = Instant::now();
+    let mut books = FullBooks::new(feed, client, &[product], &core.handle());
+    run_until(&mut core, &mut books, product, 4);
+
+    assert!(start.elapsed() >= Duration::from_secs(MIN_BACKOFF));
+    assert_eq!(rest.requests().len(), 2);
+  }
+}
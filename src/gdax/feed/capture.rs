@@ -0,0 +1,283 @@
+//! Append-only capture files of raw feed frames
+//!
+//! A capture file starts with an eight byte magic number, followed by any
+//! number of frames. Each frame is:
+//!
+//! - text length in bytes, u32 little-endian
+//! - receive time seconds since the unix epoch, i64 little-endian
+//! - receive time nanoseconds, u32 little-endian
+//! - text, UTF-8
+//!
+//! A frame torn by a crash while it was being written is discarded when the
+//! file is next opened for appending.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{TimeZone, Utc};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use gdax::feed::message::DateTime;
+
+pub const MAGIC: &'static [u8; 8] = b"WHIMCAP1";
+
+/// Longest frame text that can be written or read, so that a corrupt length
+/// can't cause a huge allocation
+pub const MAX_FRAME_LENGTH: u32 = 64 * 1024 * 1024;
+
+/// Length of the header before each frame's text
+const HEADER_LENGTH: u64 = 16;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+  pub received: DateTime,
+  pub text:     String,
+}
+
+impl Frame {
+  pub fn now(text: String) -> Frame {
+    Frame {
+      received: Utc::now(),
+      text,
+    }
+  }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+  io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_magic<R: Read>(reader: &mut R) -> io::Result<()> {
+  let mut magic = [0; 8];
+  reader.read_exact(&mut magic)?;
+  if &magic != MAGIC {
+    return Err(invalid_data("bad capture file magic number"));
+  }
+  Ok(())
+}
+
+/// Destination for raw frames as they're received
+pub trait FrameSink {
+  fn write_frame(&mut self, frame: &Frame) -> io::Result<()>;
+}
+
+pub struct CaptureWriter<W: Write = File> {
+  inner: W,
+}
+
+impl CaptureWriter<File> {
+  /// Open a capture file for appending, creating it if it doesn't exist, and
+  /// truncating a torn frame at the end of it
+  pub fn open(path: &Path) -> io::Result<CaptureWriter<File>> {
+    let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+
+    let length = file.metadata()?.len();
+    if length == 0 {
+      return CaptureWriter::new(file);
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    let complete = complete_length(&mut file)?;
+    if complete < length {
+      warn!("truncating torn frame at end of capture file {}: {} bytes", path.display(), length - complete);
+      file.set_len(complete)?;
+    }
+
+    Ok(CaptureWriter{inner: file})
+  }
+}
+
+/// Length of the magic number and the complete frames in `file`
+fn complete_length(file: &mut File) -> io::Result<u64> {
+  let mut reader = CaptureReader::new(BufReader::new(file))?;
+  let mut complete = MAGIC.len() as u64;
+  loop {
+    match reader.read() {
+      Ok(Some(frame)) => complete += HEADER_LENGTH + frame.text.len() as u64,
+      Ok(None) => return Ok(complete),
+      Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(complete),
+      Err(error) => return Err(error),
+    }
+  }
+}
+
+impl<W: Write> CaptureWriter<W> {
+  /// Write the magic number to `inner` and return a writer that appends frames to it
+  pub fn new(mut inner: W) -> io::Result<CaptureWriter<W>> {
+    inner.write_all(MAGIC)?;
+    Ok(CaptureWriter{inner})
+  }
+
+  pub fn write(&mut self, frame: &Frame) -> io::Result<()> {
+    let text = frame.text.as_bytes();
+    if text.len() > MAX_FRAME_LENGTH as usize {
+      return Err(io::Error::new(ErrorKind::InvalidInput, "frame too long"));
+    }
+
+    let mut buffer = Vec::with_capacity(HEADER_LENGTH as usize + text.len());
+    buffer.write_u32::<LittleEndian>(text.len() as u32)?;
+    buffer.write_i64::<LittleEndian>(frame.received.timestamp())?;
+    buffer.write_u32::<LittleEndian>(frame.received.timestamp_subsec_nanos())?;
+    buffer.extend_from_slice(text);
+
+    self.inner.write_all(&buffer)?;
+    self.inner.flush()
+  }
+
+  pub fn into_inner(self) -> W {
+    self.inner
+  }
+}
+
+pub struct CaptureReader<R: Read = BufReader<File>> {
+  inner: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+  pub fn open(path: &Path) -> io::Result<CaptureReader<BufReader<File>>> {
+    CaptureReader::new(BufReader::new(File::open(path)?))
+  }
+}
+
+impl<R: Read> CaptureReader<R> {
+  /// Check the magic number at the start of `inner` and return a reader for the frames that follow
+  pub fn new(mut inner: R) -> io::Result<CaptureReader<R>> {
+    read_magic(&mut inner)?;
+    Ok(CaptureReader{inner})
+  }
+
+  /// Read the next frame, returning `None` at the end of the capture and an
+  /// `UnexpectedEof` error if the last frame is torn
+  pub fn read(&mut self) -> io::Result<Option<Frame>> {
+    let mut length = [0; 4];
+    let mut read = 0;
+    while read < length.len() {
+      match self.inner.read(&mut length[read..]) {
+        Ok(0) if read == 0 => return Ok(None),
+        Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "torn frame length")),
+        Ok(n) => read += n,
+        Err(ref error) if error.kind() == ErrorKind::Interrupted => {}
+        Err(error) => return Err(error),
+      }
+    }
+    let length = (&length[..]).read_u32::<LittleEndian>()?;
+    if length > MAX_FRAME_LENGTH {
+      return Err(invalid_data("frame length too long"));
+    }
+
+    let seconds     = self.inner.read_i64::<LittleEndian>()?;
+    let nanoseconds = self.inner.read_u32::<LittleEndian>()?;
+
+    let mut text = vec![0; length as usize];
+    self.inner.read_exact(&mut text)?;
+
+    Ok(Some(Frame {
+      received: Utc.timestamp_opt(seconds, nanoseconds).single()
+        .ok_or_else(|| invalid_data("bad frame receive time"))?,
+      text: String::from_utf8(text).map_err(|_| invalid_data("frame text is not valid UTF-8"))?,
+    }))
+  }
+}
+
+impl<W: Write> FrameSink for CaptureWriter<W> {
+  fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+    self.write(frame)
+  }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+  type Item = io::Result<Frame>;
+
+  fn next(&mut self) -> Option<io::Result<Frame>> {
+    match self.read() {
+      Ok(Some(frame)) => Some(Ok(frame)),
+      Ok(None)        => None,
+      Err(error)      => Some(Err(error)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use std::{env, fs, process};
+
+  fn frame(seconds: i64, nanoseconds: u32, text: &str) -> Frame {
+    Frame {
+      received: Utc.timestamp(seconds, nanoseconds),
+      text:     text.to_string(),
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let frames = vec![
+      frame(1415348368, 464459000, r#"{"type":"heartbeat"}"#),
+      frame(1415348369, 0,         ""),
+      frame(-1,         999999999, "not json at all"),
+    ];
+
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    for frame in &frames {
+      writer.write(frame).unwrap();
+    }
+
+    let reader = CaptureReader::new(Cursor::new(writer.into_inner())).unwrap();
+    let read = reader.collect::<io::Result<Vec<Frame>>>().unwrap();
+
+    assert_eq!(read, frames);
+  }
+
+  #[test]
+  fn bad_magic() {
+    let error = CaptureReader::new(Cursor::new(b"WHIMCAP0".to_vec())).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn truncated() {
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    writer.write(&frame(0, 0, "hello")).unwrap();
+    let mut bytes = writer.into_inner();
+    bytes.pop();
+
+    let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.read().err().unwrap().kind(), ErrorKind::UnexpectedEof);
+  }
+
+  #[test]
+  fn torn_length() {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[5, 0]);
+
+    let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.read().err().unwrap().kind(), ErrorKind::UnexpectedEof);
+  }
+
+  #[test]
+  fn corrupt_length() {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[0xff; 16]);
+
+    let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.read().err().unwrap().kind(), ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn append_after_torn_frame() {
+    let path = env::temp_dir().join(format!("whim-capture-torn-{}.capture", process::id()));
+    let _ = fs::remove_file(&path);
+
+    CaptureWriter::open(&path).unwrap().write(&frame(0, 0, "first")).unwrap();
+    CaptureWriter::open(&path).unwrap().write(&frame(1, 0, "torn")).unwrap();
+    let length = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(length - 2).unwrap();
+
+    CaptureWriter::open(&path).unwrap().write(&frame(2, 0, "second")).unwrap();
+
+    let read = CaptureReader::open(&path).unwrap().collect::<io::Result<Vec<Frame>>>().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(read, vec![frame(0, 0, "first"), frame(2, 0, "second")]);
+  }
+}
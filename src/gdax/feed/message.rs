@@ -1,25 +1,111 @@
-use decimal::Decimal;
+use decimal::{Decimal, Rounding};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::Add;
+use std::str::{self, FromStr};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
-#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
-pub enum Product {
-  BtcUsd,
-  EthUsd,
-  LtcUsd,
+const MAX_CURRENCY_CODE_LENGTH: usize = 12;
+
+/// A currency code, e.g. `BTC`, stored inline so that it can be `Copy`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency {
+  code: [u8; MAX_CURRENCY_CODE_LENGTH],
 }
 
-impl Product {
-  pub fn all() -> Vec<Product> {
-    vec![
-      Product::BtcUsd,
-      Product::EthUsd,
-      Product::LtcUsd,
-    ]
+impl Currency {
+  pub fn as_str(&self) -> &str {
+    let length = self.code.iter().position(|&byte| byte == 0).unwrap_or(MAX_CURRENCY_CODE_LENGTH);
+    str::from_utf8(&self.code[..length]).expect("currency code was not validated")
   }
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+impl Display for Currency {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl Debug for Currency {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for Currency {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Currency, String> {
+    if s.is_empty() || s.len() > MAX_CURRENCY_CODE_LENGTH {
+      return Err(format!("invalid currency code length: {}", s));
+    }
+
+    if !s.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit()) {
+      return Err(format!("invalid currency code: {}", s));
+    }
+
+    let mut code = [0; MAX_CURRENCY_CODE_LENGTH];
+    code[..s.len()].copy_from_slice(s.as_bytes());
+    Ok(Currency{code})
+  }
+}
+
+/// A product traded on the exchange, identified by base and quote currency,
+/// e.g. `BTC-USD`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Product {
+  pub base:  Currency,
+  pub quote: Currency,
+}
+
+impl Display for Product {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "{}-{}", self.base, self.quote)
+  }
+}
+
+impl Debug for Product {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(f, "{}", self)
+  }
+}
+
+impl FromStr for Product {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Product, String> {
+    let mut currencies = s.split('-');
+    match (currencies.next(), currencies.next(), currencies.next()) {
+      (Some(base), Some(quote), None) => Ok(Product {
+        base:  base.parse().map_err(|error| format!("invalid product {}: {}", s, error))?,
+        quote: quote.parse().map_err(|error| format!("invalid product {}: {}", s, error))?,
+      }),
+      _ => Err(format!("invalid product, expected BASE-QUOTE: {}", s)),
+    }
+  }
+}
+
+macro_rules! serde_via_string {
+  ($type:ident) => {
+    impl Serialize for $type {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+      }
+    }
+
+    impl<'de> Deserialize<'de> for $type {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$type, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+      }
+    }
+  }
+}
+
+serde_via_string!(Currency);
+serde_via_string!(Product);
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum Channel {
   Full,
@@ -27,9 +113,11 @@ pub enum Channel {
   Level2,
   Matches,
   Ticker,
+  User,
 }
 
 impl Channel {
+  /// Every channel that can be subscribed to without authenticating
   pub fn all() -> Vec<Channel> {
     vec![
       Channel::Full,
@@ -41,15 +129,61 @@ impl Channel {
   }
 }
 
+impl Display for Channel {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    let name = match *self {
+      Channel::Full      => "full",
+      Channel::Heartbeat => "heartbeat",
+      Channel::Level2    => "level2",
+      Channel::Matches   => "matches",
+      Channel::Ticker    => "ticker",
+      Channel::User      => "user",
+    };
+    write!(f, "{}", name)
+  }
+}
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+impl FromStr for Channel {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Channel, String> {
+    Channel::all().into_iter()
+      .chain(Some(Channel::User))
+      .find(|channel| channel.to_string() == s)
+      .ok_or_else(|| format!("unknown channel: {}", s))
+  }
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum Side {
   Buy,
   Sell,
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+impl Side {
+  pub fn opposite(self) -> Side {
+    match self {
+      Side::Buy  => Side::Sell,
+      Side::Sell => Side::Buy,
+    }
+  }
+}
+
+impl FromStr for Side {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Side, String> {
+    match s {
+      "buy"  => Ok(Side::Buy),
+      "sell" => Ok(Side::Sell),
+      _      => Err(format!("unknown side, expected buy or sell: {}", s)),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum OrderType {
   Limit,
@@ -63,7 +197,7 @@ pub enum Reason {
   Filled,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Subscription {
   pub name:        Channel,
   pub product_ids: Vec<Product>,
@@ -71,16 +205,143 @@ pub struct Subscription {
 
 pub type DateTime = ::chrono::DateTime<::chrono::offset::Utc>;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct QuoteCurrencyPrice(Decimal);
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+impl QuoteCurrencyPrice {
+  pub fn zero() -> QuoteCurrencyPrice {
+    QuoteCurrencyPrice(Decimal::zero())
+  }
+
+  pub fn checked_sub(self, rhs: QuoteCurrencyPrice) -> Option<QuoteCurrencyPrice> {
+    self.0.checked_sub(rhs.0).map(QuoteCurrencyPrice)
+  }
+
+  /// The price halfway between this price and `other`
+  pub fn midpoint(self, other: QuoteCurrencyPrice) -> QuoteCurrencyPrice {
+    QuoteCurrencyPrice((self.0 + other.0) / Decimal::from(2))
+  }
+
+  /// Whether this price is a whole number of `increment`s
+  pub fn is_multiple_of(self, increment: QuoteCurrencyPrice) -> bool {
+    if increment.0.is_zero() {
+      return true;
+    }
+    self.0.checked_div(increment.0, Rounding::TowardZero)
+      .and_then(|quotient| quotient.checked_round(0, Rounding::TowardZero))
+      .and_then(|whole| whole.checked_mul(increment.0, Rounding::TowardZero))
+      .map(|product| product == self.0)
+      .unwrap_or(false)
+  }
+
+  /// Value of `size` at this price
+  pub fn notional(self, size: BaseCurrencyAmount) -> QuoteCurrencyAmount {
+    QuoteCurrencyAmount(self.0 * size.0)
+  }
+}
+
+impl Add for QuoteCurrencyPrice {
+  type Output = QuoteCurrencyPrice;
+
+  fn add(self, rhs: QuoteCurrencyPrice) -> QuoteCurrencyPrice {
+    QuoteCurrencyPrice(self.0 + rhs.0)
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct QuoteCurrencyAmount(Decimal);
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+impl QuoteCurrencyAmount {
+  pub fn zero() -> QuoteCurrencyAmount {
+    QuoteCurrencyAmount(Decimal::zero())
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.0.is_zero()
+  }
+
+  pub fn checked_sub(self, rhs: QuoteCurrencyAmount) -> Option<QuoteCurrencyAmount> {
+    self.0.checked_sub(rhs.0).map(QuoteCurrencyAmount)
+  }
+}
+
+impl Add for QuoteCurrencyAmount {
+  type Output = QuoteCurrencyAmount;
+
+  fn add(self, rhs: QuoteCurrencyAmount) -> QuoteCurrencyAmount {
+    QuoteCurrencyAmount(self.0 + rhs.0)
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct BaseCurrencyAmount(Decimal);
 
-#[derive(Serialize, Deserialize, Debug)]
+impl BaseCurrencyAmount {
+  pub fn zero() -> BaseCurrencyAmount {
+    BaseCurrencyAmount(Decimal::zero())
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.0.is_zero()
+  }
+
+  pub fn checked_sub(self, rhs: BaseCurrencyAmount) -> Option<BaseCurrencyAmount> {
+    self.0.checked_sub(rhs.0).map(BaseCurrencyAmount)
+  }
+
+  /// This amount multiplied by `factor`
+  pub fn scale(self, factor: Decimal, rounding: Rounding) -> BaseCurrencyAmount {
+    BaseCurrencyAmount(self.0.mul_rounded(factor, rounding))
+  }
+}
+
+/// Parse and display a decimal newtype as its decimal, e.g. in command line
+/// arguments and error messages, and unwrap it for arithmetic across types
+macro_rules! decimal_newtype {
+  ($type:ident) => {
+    impl From<Decimal> for $type {
+      fn from(value: Decimal) -> $type {
+        $type(value)
+      }
+    }
+
+    impl From<$type> for Decimal {
+      fn from(value: $type) -> Decimal {
+        value.0
+      }
+    }
+
+    impl Display for $type {
+      fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.0)
+      }
+    }
+
+    impl FromStr for $type {
+      type Err = String;
+
+      fn from_str(s: &str) -> Result<$type, String> {
+        s.parse().map($type).map_err(|(character, position)| {
+          format!("bad character in decimal at position {}: {:?}", position, character)
+        })
+      }
+    }
+  }
+}
+
+decimal_newtype!(QuoteCurrencyPrice);
+decimal_newtype!(QuoteCurrencyAmount);
+decimal_newtype!(BaseCurrencyAmount);
+
+impl Add for BaseCurrencyAmount {
+  type Output = BaseCurrencyAmount;
+
+  fn add(self, rhs: BaseCurrencyAmount) -> BaseCurrencyAmount {
+    BaseCurrencyAmount(self.0 + rhs.0)
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum IncomingMessage {
   Ticker(Ticker),
@@ -95,6 +356,8 @@ pub enum IncomingMessage {
     price:          Option<QuoteCurrencyPrice>,
     time:           DateTime,
     remaining_size: Option<BaseCurrencyAmount>,
+    user_id:        Option<String>,
+    profile_id:     Option<Uuid>,
   },
   Received {
     product_id: Product,
@@ -107,6 +370,8 @@ pub enum IncomingMessage {
     price:      Option<QuoteCurrencyPrice>,
     size:       Option<BaseCurrencyAmount>,
     funds:      Option<QuoteCurrencyAmount>,
+    user_id:    Option<String>,
+    profile_id: Option<Uuid>,
   },
   Open {
     product_id:     Product,
@@ -118,15 +383,21 @@ pub enum IncomingMessage {
     remaining_size: BaseCurrencyAmount,
   },
   Match {
-    product_id:     Product,
-    maker_order_id: Uuid,
-    taker_order_id: Uuid,
-    price:          QuoteCurrencyPrice,
-    sequence:       u64,
-    side:           Side,
-    size:           BaseCurrencyAmount,
-    time:           DateTime,
-    trade_id:       u64,
+    product_id:       Product,
+    maker_order_id:   Uuid,
+    taker_order_id:   Uuid,
+    price:            QuoteCurrencyPrice,
+    sequence:         u64,
+    side:             Side,
+    size:             BaseCurrencyAmount,
+    time:             DateTime,
+    trade_id:         u64,
+    user_id:          Option<String>,
+    profile_id:       Option<Uuid>,
+    maker_user_id:    Option<String>,
+    maker_profile_id: Option<Uuid>,
+    taker_user_id:    Option<String>,
+    taker_profile_id: Option<Uuid>,
   },
   LastMatch {
     product_id:     Product,
@@ -142,6 +413,14 @@ pub enum IncomingMessage {
   Change {
     product_id: Product,
     order_id:   Uuid,
+    side:       Side,
+    sequence:   u64,
+    time:       DateTime,
+    price:      Option<QuoteCurrencyPrice>,
+    new_size:   Option<BaseCurrencyAmount>,
+    old_size:   Option<BaseCurrencyAmount>,
+    new_funds:  Option<QuoteCurrencyAmount>,
+    old_funds:  Option<QuoteCurrencyAmount>,
   },
   MarginProfileUpdate {
     product_id: Product,
@@ -161,39 +440,165 @@ pub enum IncomingMessage {
   L2update(OrderBookUpdate),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl IncomingMessage {
+  /// The value of the message's `type` tag
+  pub fn type_name(&self) -> &'static str {
+    use self::IncomingMessage::*;
+    match *self {
+      Ticker(_)                 => "ticker",
+      Error{..}                 => "error",
+      Subscriptions{..}         => "subscriptions",
+      Done{..}                  => "done",
+      Received{..}              => "received",
+      Open{..}                  => "open",
+      Match{..}                 => "match",
+      LastMatch{..}             => "last_match",
+      Change{..}                => "change",
+      MarginProfileUpdate{..}   => "margin_profile_update",
+      Activate{..}              => "activate",
+      Heartbeat{..}             => "heartbeat",
+      Snapshot(_)               => "snapshot",
+      L2update(_)               => "l2update",
+    }
+  }
+
+  pub fn product_id(&self) -> Option<Product> {
+    use self::IncomingMessage::*;
+    match *self {
+      Ticker(ref ticker)                        => Some(ticker.product_id),
+      Done{product_id, ..}                      |
+      Received{product_id, ..}                  |
+      Open{product_id, ..}                      |
+      Match{product_id, ..}                     |
+      LastMatch{product_id, ..}                 |
+      Change{product_id, ..}                    |
+      MarginProfileUpdate{product_id, ..}       |
+      Activate{product_id, ..}                  |
+      Heartbeat{product_id, ..}                 => Some(product_id),
+      Snapshot(ref snapshot)                    => Some(snapshot.product_id),
+      L2update(ref update)                      => Some(update.product_id),
+      Error{..} | Subscriptions{..}             => None,
+    }
+  }
+
+  pub fn sequence(&self) -> Option<u64> {
+    use self::IncomingMessage::*;
+    match *self {
+      Ticker(ref ticker)      => Some(ticker.sequence),
+      Change{sequence, ..}    |
+      Done{sequence, ..}      |
+      Received{sequence, ..}  |
+      Open{sequence, ..}      |
+      Match{sequence, ..}     |
+      LastMatch{sequence, ..} |
+      Heartbeat{sequence, ..} => Some(sequence),
+      _                       => None,
+    }
+  }
+
+  pub fn time(&self) -> Option<DateTime> {
+    use self::IncomingMessage::*;
+    match *self {
+      Ticker(ref ticker)  => ticker.time,
+      Change{time, ..}    |
+      Done{time, ..}      |
+      Received{time, ..}  |
+      Open{time, ..}      |
+      Match{time, ..}     |
+      LastMatch{time, ..} |
+      Heartbeat{time, ..} => Some(time),
+      _                   => None,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderBookSnapshot {
   pub product_id: Product,
   pub bids:       Vec<(QuoteCurrencyPrice, BaseCurrencyAmount)>,
   pub asks:       Vec<(QuoteCurrencyPrice, BaseCurrencyAmount)>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderBookUpdate {
   pub product_id: Product,
   pub changes:    Vec<(Side, QuoteCurrencyPrice, BaseCurrencyAmount)>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Ticker {
-  product_id: Product,
-  time:       Option<DateTime>,
-  price:      QuoteCurrencyPrice,
-  side:       Option<Side>,
-  last_size:  Option<BaseCurrencyAmount>,
-  trade_id:   Option<u64>,
-  sequence:   u64,
-  best_bid:   QuoteCurrencyPrice,
-  best_ask:   QuoteCurrencyPrice,
-  high_24h:   QuoteCurrencyPrice,
-  open_24h:   QuoteCurrencyPrice,
-  low_24h:    QuoteCurrencyPrice,
-  volume_24h: BaseCurrencyAmount,
-  volume_30d: BaseCurrencyAmount,
-}
-
-#[derive(Serialize)]
+  pub product_id: Product,
+  pub time:       Option<DateTime>,
+  pub price:      QuoteCurrencyPrice,
+  pub side:       Option<Side>,
+  pub last_size:  Option<BaseCurrencyAmount>,
+  pub trade_id:   Option<u64>,
+  pub sequence:   u64,
+  pub best_bid:   QuoteCurrencyPrice,
+  pub best_ask:   QuoteCurrencyPrice,
+  pub high_24h:   QuoteCurrencyPrice,
+  pub open_24h:   QuoteCurrencyPrice,
+  pub low_24h:    QuoteCurrencyPrice,
+  pub volume_24h: BaseCurrencyAmount,
+  pub volume_30d: BaseCurrencyAmount,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum OutgoingMessage {
-  Subscribe{channels: Vec<Subscription>}
+  Subscribe {
+    channels:   Vec<Subscription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key:        Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    passphrase: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp:  Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature:  Option<String>,
+  },
+  Unsubscribe{channels: Vec<Subscription>},
+}
+
+impl OutgoingMessage {
+  /// An unsigned subscribe message
+  pub fn subscribe(channels: Vec<Subscription>) -> OutgoingMessage {
+    OutgoingMessage::Subscribe {
+      channels,
+      key:        None,
+      passphrase: None,
+      timestamp:  None,
+      signature:  None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json;
+
+  #[test]
+  fn product() {
+    let product: Product = "BCH-EUR".parse().unwrap();
+    assert_eq!(product.base.as_str(), "BCH");
+    assert_eq!(product.quote.to_string(), "EUR");
+    assert_eq!(serde_json::to_string(&product).unwrap(), r#""BCH-EUR""#);
+    assert_eq!(serde_json::from_str::<Product>(r#""BCH-EUR""#).unwrap(), product);
+
+    for invalid in &["", "BTC", "BTC-", "BTC-USD-EUR", "btc-usd", "BTC-ABCDEFGHIJKLM"] {
+      assert!(invalid.parse::<Product>().is_err(), "{} parsed", invalid);
+    }
+  }
+
+  #[test]
+  fn is_multiple_of() {
+    let price = |s: &str| s.parse::<QuoteCurrencyPrice>().unwrap();
+    assert!(price("100.01").is_multiple_of(price("0.01")));
+    assert!(price("100.25").is_multiple_of(price("0.05")));
+    assert!(!price("100.015").is_multiple_of(price("0.01")));
+    assert!(!price("100.03").is_multiple_of(price("0.05")));
+    assert!(price("0.00001").is_multiple_of(price("0.00001")));
+    assert!("1.0.0".parse::<QuoteCurrencyPrice>().is_err());
+  }
 }
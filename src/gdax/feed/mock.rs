@@ -0,0 +1,469 @@
+use futures::prelude::*;
+use futures::stream;
+use futures::unsync::mpsc;
+use serde_json;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::iter;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::{Handle, Timeout};
+use websocket::{OwnedMessage, WebSocketError};
+use websocket::async::server::IntoWs;
+use websocket::url::Url;
+
+use super::capture::CaptureReader;
+use super::message::{Channel, IncomingMessage, OutgoingMessage};
+use super::subscriptions::{pairs, subscriptions, Pair};
+use gdax::auth::Credentials;
+
+/// Something for a `MockFeed` to do after a client subscribes
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockEvent {
+  /// Send `text` in a text frame
+  Text(String),
+  Ping,
+  Binary,
+  /// Send a text frame that isn't valid JSON
+  Malformed,
+  /// Drop the next `count` messages that have sequence numbers
+  Gap{count: u64},
+  /// Send a close frame, and nothing after it
+  Close,
+}
+
+/// Script file lines of the form `{"mock": "ping"}`
+#[derive(Deserialize)]
+#[serde(tag = "mock", rename_all = "snake_case")]
+enum Directive {
+  Ping,
+  Binary,
+  Malformed,
+  Gap{count: u64},
+  Close,
+}
+
+impl MockEvent {
+  pub fn message(message: &IncomingMessage) -> MockEvent {
+    MockEvent::Text(serde_json::to_string(message).expect("failed to serialize message"))
+  }
+
+  /// Parse a script file line, which is either a directive or a message to
+  /// send verbatim
+  fn parse(line: &str) -> Result<MockEvent, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(line)?;
+    if value.get("mock").is_none() {
+      return Ok(MockEvent::Text(line.to_string()));
+    }
+    Ok(match serde_json::from_value(value)? {
+      Directive::Ping        => MockEvent::Ping,
+      Directive::Binary      => MockEvent::Binary,
+      Directive::Malformed   => MockEvent::Malformed,
+      Directive::Gap{count}  => MockEvent::Gap{count},
+      Directive::Close       => MockEvent::Close,
+    })
+  }
+}
+
+/// Read a script file, with one message or directive per line
+pub fn read_script(path: &Path) -> io::Result<Vec<MockEvent>> {
+  let mut events = vec![];
+  for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let event = MockEvent::parse(&line).map_err(|error| {
+      io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, error))
+    })?;
+    events.push(event);
+  }
+  Ok(events)
+}
+
+/// Read the text frames of a capture file written by `whim record --capture`
+pub fn read_capture(path: &Path) -> io::Result<Vec<MockEvent>> {
+  CaptureReader::open(path)?
+    .map(|frame| frame.map(|frame| MockEvent::Text(frame.text)))
+    .collect()
+}
+
+fn has_sequence(text: &str) -> bool {
+  serde_json::from_str::<IncomingMessage>(text).ok().and_then(|message| message.sequence()).is_some()
+}
+
+fn frames(events: &[MockEvent]) -> Vec<OwnedMessage> {
+  let mut frames = vec![];
+  let mut skip = 0;
+  for event in events {
+    let frame = match *event {
+      MockEvent::Text(ref text) => {
+        if skip > 0 && has_sequence(text) {
+          skip -= 1;
+          continue;
+        }
+        OwnedMessage::Text(text.clone())
+      }
+      MockEvent::Ping       => OwnedMessage::Ping(b"whim".to_vec()),
+      MockEvent::Binary     => OwnedMessage::Binary(vec![0xde, 0xad, 0xbe, 0xef]),
+      MockEvent::Malformed  => OwnedMessage::Text(r#"{"type": "#.to_string()),
+      MockEvent::Gap{count} => {
+        skip += count;
+        continue;
+      }
+      MockEvent::Close => {
+        frames.push(OwnedMessage::Close(None));
+        break;
+      }
+    };
+    frames.push(frame);
+  }
+  frames
+}
+
+/// Apply a subscription change to `subscribed`, returning the reply to send.
+/// Signed subscribe messages, and those that include the user channel, must
+/// be signed with `credentials`.
+fn change_subscriptions(
+  subscribed:  &mut BTreeSet<Pair>,
+  credentials: Option<&Credentials>,
+  text:        &str,
+) -> IncomingMessage {
+  match serde_json::from_str(text) {
+    Ok(message @ OutgoingMessage::Subscribe{..}) => {
+      if let OutgoingMessage::Subscribe{ref channels, ref signature, ..} = message {
+        let user = channels.iter().any(|subscription| subscription.name == Channel::User);
+        let verified = credentials.map_or(false, |credentials| credentials.verify_subscribe(&message));
+        if (user || signature.is_some()) && !verified {
+          return IncomingMessage::Error{message: "Authentication Failed".to_string()};
+        }
+        subscribed.extend(pairs(channels));
+      }
+    }
+    Ok(OutgoingMessage::Unsubscribe{channels}) => {
+      for pair in pairs(&channels) {
+        subscribed.remove(&pair);
+      }
+    }
+    Err(error) => return IncomingMessage::Error{message: format!("Failed to subscribe: {}", error)},
+  }
+  IncomingMessage::Subscriptions{channels: subscriptions(subscribed)}
+}
+
+fn text(message: &IncomingMessage) -> OwnedMessage {
+  OwnedMessage::Text(serde_json::to_string(message).expect("failed to serialize message"))
+}
+
+/// A local websocket server that stands in for the GDAX feed. It answers each
+/// client's subscription request, and then sends it a script of messages and
+/// faults.
+pub struct MockFeed {
+  scripts:     Vec<Vec<MockEvent>>,
+  interval:    Option<Duration>,
+  credentials: Option<Credentials>,
+}
+
+impl MockFeed {
+  pub fn new() -> MockFeed {
+    MockFeed {
+      scripts:     vec![],
+      interval:    None,
+      credentials: None,
+    }
+  }
+
+  /// Send `events` to the next client to connect. Clients connecting after
+  /// the last script has been used receive the last script again.
+  pub fn script(mut self, events: Vec<MockEvent>) -> MockFeed {
+    self.scripts.push(events);
+    self
+  }
+
+  /// Wait `interval` before sending each frame
+  pub fn interval(mut self, interval: Duration) -> MockFeed {
+    self.interval = Some(interval);
+    self
+  }
+
+  /// Accept subscribe messages signed with `credentials`, which are required
+  /// to subscribe to the user channel
+  pub fn credentials(mut self, credentials: Credentials) -> MockFeed {
+    self.credentials = Some(credentials);
+    self
+  }
+
+  /// Listen on `address`, which may have port 0 to pick an unused port
+  pub fn bind(self, address: &SocketAddr, handle: &Handle) -> io::Result<MockServer> {
+    let listener = TcpListener::bind(address, handle)?;
+    let url = Url::parse(&format!("ws://{}", listener.local_addr()?))
+      .expect("socket address was not a valid URL host");
+    let received = Rc::new(RefCell::new(vec![]));
+
+    let scripts     = self.scripts;
+    let interval    = self.interval;
+    let credentials = self.credentials;
+    let handle      = handle.clone();
+    let log         = received.clone();
+    let mut connections = 0;
+
+    let incoming = listener.incoming().for_each(move |(stream, address)| {
+      let script = match scripts.len() {
+        0   => vec![],
+        len => frames(&scripts[cmp::min(connections, len - 1)]),
+      };
+      connections += 1;
+
+      info!("mock feed: connection from {}", address);
+      let connection = serve(stream, script, interval, credentials.clone(), log.clone(), handle.clone())
+        .map_err(move |error| warn!("mock feed: connection from {} failed: {}", address, error));
+      handle.spawn(connection);
+      Ok(())
+    });
+
+    Ok(MockServer {
+      incoming: Box::new(incoming),
+      url,
+      received,
+    })
+  }
+}
+
+fn serve(
+  stream:      TcpStream,
+  script:      Vec<OwnedMessage>,
+  interval:    Option<Duration>,
+  credentials: Option<Credentials>,
+  received:    Rc<RefCell<Vec<String>>>,
+  handle:      Handle,
+) -> Box<Future<Item=(), Error=WebSocketError>> {
+  let future = stream.into_ws()
+    .map_err(|(_, _, _, error)| {
+      WebSocketError::from(io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+    })
+    .and_then(|upgrade| upgrade.accept())
+    .and_then(|(client, _headers)| client.into_future().map_err(|(error, _)| error))
+    .and_then(move |(request, client)| {
+      let mut subscribed = BTreeSet::new();
+
+      // the first message must subscribe, or the connection is closed
+      let reply = match request {
+        Some(OwnedMessage::Text(text)) => {
+          let reply = change_subscriptions(&mut subscribed, credentials.as_ref(), &text);
+          received.borrow_mut().push(text);
+          reply
+        }
+        _ => IncomingMessage::Error{message: "Failed to subscribe: expected text frame".to_string()},
+      };
+      let frames = match reply {
+        IncomingMessage::Error{..} => vec![text(&reply), OwnedMessage::Close(None)],
+        _ => iter::once(text(&reply)).chain(script).collect(),
+      };
+
+      let (sink, stream) = client.split();
+
+      // later subscription changes are answered between scripted frames
+      let (replies, replying) = mpsc::unbounded();
+      let reading = stream
+        .take_while(|message| Ok(!message.is_close()))
+        .for_each(move |message| {
+          if let OwnedMessage::Text(message) = message {
+            let reply = change_subscriptions(&mut subscribed, credentials.as_ref(), &message);
+            received.borrow_mut().push(message);
+            replies.unbounded_send(text(&reply)).ok();
+          }
+          Ok(())
+        })
+        .map_err(|error| warn!("mock feed: read failed: {}", error));
+      handle.spawn(reading);
+
+      let frames: Box<Stream<Item=OwnedMessage, Error=WebSocketError>> = match interval {
+        Some(interval) => Box::new(stream::iter_ok(frames).and_then(move |frame| {
+          Timeout::new(interval, &handle).into_future().flatten()
+            .map(move |()| frame)
+            .map_err(WebSocketError::from)
+        })),
+        None => Box::new(stream::iter_ok(frames)),
+      };
+      let replying = replying.map_err(|()| -> WebSocketError { unreachable!() });
+
+      sink.send_all(frames.select(replying)).map(|_| ())
+    });
+
+  Box::new(future)
+}
+
+/// A bound `MockFeed`, which accepts connections while it is polled
+pub struct MockServer {
+  incoming: Box<Future<Item=(), Error=io::Error>>,
+  url:      Url,
+  received: Rc<RefCell<Vec<String>>>,
+}
+
+impl MockServer {
+  /// The `ws://` URL to connect to
+  pub fn url(&self) -> Url {
+    self.url.clone()
+  }
+
+  /// Text frames received from all clients, including subscription requests
+  pub fn received(&self) -> Rc<RefCell<Vec<String>>> {
+    self.received.clone()
+  }
+}
+
+impl Future for MockServer {
+  type Item  = ();
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<(), io::Error> {
+    self.incoming.poll()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use gdax::feed::{FeedBuilder, FeedError};
+  use gdax::feed::reconnect::{FeedEvent, ReconnectingFeed};
+  use gdax::fixtures::open_event;
+  use tokio_core::reactor::Core;
+
+  fn start(core: &Core, mock: MockFeed) -> (Url, Rc<RefCell<Vec<String>>>) {
+    let server = mock.bind(&"127.0.0.1:0".parse().unwrap(), &core.handle()).unwrap();
+    let url = server.url();
+    let received = server.received();
+    core.handle().spawn(server.map_err(|error| panic!("mock feed failed: {}", error)));
+    (url, received)
+  }
+
+  #[test]
+  fn feed() {
+    let mut core = Core::new().unwrap();
+    let (url, received) = start(&core, MockFeed::new()
+      .script(vec![open_event(1), MockEvent::Ping, MockEvent::Binary, open_event(2)]));
+
+    let connecting = FeedBuilder::new().url(url).subscribe_to_all(&["BTC-USD".parse().unwrap()]).connect(&core.handle()).unwrap();
+    let feed = core.run(connecting).unwrap();
+    let messages = core.run(feed.take(3).collect()).unwrap();
+    assert_eq!(
+      messages.iter().map(|message| (message.type_name(), message.sequence())).collect::<Vec<_>>(),
+      vec![("subscriptions", None), ("open", Some(1)), ("open", Some(2))],
+    );
+
+    let received = received.borrow();
+    assert_eq!(received.len(), 1);
+    assert!(received[0].contains(r#""type":"subscribe""#));
+  }
+
+  #[test]
+  fn malformed() {
+    let mut core = Core::new().unwrap();
+    let (url, _) = start(&core, MockFeed::new().script(vec![MockEvent::Malformed]));
+
+    let feed = core.run(FeedBuilder::new().url(url).connect(&core.handle()).unwrap()).unwrap();
+    match core.run(feed.skip(1).into_future()).map_err(|(error, _)| error) {
+      Err(FeedError::Deserialization(_)) => {}
+      other => panic!("expected deserialization error: {:?}", other.map(|(message, _)| message)),
+    }
+  }
+
+  #[test]
+  fn close_and_reconnect() {
+    let mut core = Core::new().unwrap();
+    let (url, received) = start(&core, MockFeed::new()
+      .script(vec![open_event(1), MockEvent::Close, open_event(2)])
+      .script(vec![MockEvent::Gap{count: 1}, open_event(3), open_event(4)]));
+
+    let feed = ReconnectingFeed::new(FeedBuilder::new().url(url), &core.handle()).unwrap();
+    let events = core.run(feed.take(5).collect()).unwrap();
+    let events = events.iter()
+      .map(|event| match *event {
+        FeedEvent::Message(ref message) => Some(message.sequence().unwrap_or(0)),
+        FeedEvent::Discontinuity        => None,
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(events, vec![Some(0), Some(1), None, Some(0), Some(4)]);
+    assert_eq!(received.borrow().len(), 2);
+  }
+
+  #[test]
+  fn reconnecting_skips_malformed() {
+    let mut core = Core::new().unwrap();
+    let (url, _) = start(&core, MockFeed::new().script(vec![open_event(1), MockEvent::Malformed, open_event(2)]));
+
+    let feed = ReconnectingFeed::new(FeedBuilder::new().url(url), &core.handle()).unwrap();
+    let events = core.run(feed.take(3).collect()).unwrap();
+    let sequences = events.iter()
+      .map(|event| match *event {
+        FeedEvent::Message(ref message) => message.sequence(),
+        FeedEvent::Discontinuity        => panic!("unexpected discontinuity"),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(sequences, vec![None, Some(1), Some(2)]);
+  }
+
+  #[test]
+  fn authenticated() {
+    let credentials = Credentials::new("key", "c2VjcmV0", "passphrase").unwrap();
+    let mut core = Core::new().unwrap();
+    let (url, _) = start(&core, MockFeed::new()
+      .credentials(credentials.clone())
+      .script(vec![MockEvent::Text(r#"{
+        "type":       "received",
+        "time":       "2017-10-01T00:00:00Z",
+        "product_id": "BTC-USD",
+        "sequence":   1,
+        "order_id":   "d50ec984-77a8-460a-b958-66f114b0de9b",
+        "order_type": "limit",
+        "side":       "buy",
+        "price":      "200.2",
+        "size":       "1.00",
+        "user_id":    "5844eceecf7e803e259d0365",
+        "profile_id": "765d1549-9660-4be2-97d4-fa2d65fa3352"
+      }"#.to_string())]));
+
+    let builder = FeedBuilder::new().url(url).subscribe(Channel::User, "BTC-USD".parse().unwrap());
+
+    let feed = core.run(builder.clone().credentials(credentials).connect(&core.handle()).unwrap()).unwrap();
+    let messages = core.run(feed.take(2).collect()).unwrap();
+    match messages[1] {
+      IncomingMessage::Received{ref user_id, profile_id, ..} => {
+        assert_eq!(user_id.as_ref().unwrap(), "5844eceecf7e803e259d0365");
+        assert_eq!(profile_id.unwrap().to_string(), "765d1549-9660-4be2-97d4-fa2d65fa3352");
+      }
+      ref other => panic!("expected received message: {:?}", other),
+    }
+
+    let wrong = Credentials::new("key", "d3Jvbmc=", "passphrase").unwrap();
+    for builder in vec![builder.clone(), builder.credentials(wrong)] {
+      let feed = core.run(builder.connect(&core.handle()).unwrap()).unwrap();
+      match core.run(feed.into_future()).map_err(|(error, _)| error).unwrap().0 {
+        Some(IncomingMessage::Error{ref message}) => assert_eq!(message, "Authentication Failed"),
+        other => panic!("expected authentication error: {:?}", other),
+      }
+    }
+  }
+
+  #[test]
+  fn script_file() {
+    let events = [
+      r#"{"mock": "ping"}"#,
+      r#"{"mock": "gap", "count": 2}"#,
+      r#"{"type": "heartbeat"}"#,
+      r#"{"mock": "close"}"#,
+    ].iter().map(|line| MockEvent::parse(line).unwrap()).collect::<Vec<_>>();
+    assert_eq!(events, vec![
+      MockEvent::Ping,
+      MockEvent::Gap{count: 2},
+      MockEvent::Text(r#"{"type": "heartbeat"}"#.to_string()),
+      MockEvent::Close,
+    ]);
+    assert!(MockEvent::parse(r#"{"mock": "explode"}"#).is_err());
+  }
+}
@@ -1,28 +1,51 @@
 use futures::prelude::*;
 use serde_json;
+use std::cell::RefCell;
 use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::rc::Rc;
 use tokio_core::reactor::Handle;
 use websocket::{ClientBuilder, WebSocketError, OwnedMessage};
+use websocket::async::{Client, Stream as AsyncStream};
+use websocket::header::Headers;
+use websocket::url::Url;
 
+pub mod capture;
 pub mod message;
+pub mod mock;
+pub mod reconnect;
+pub mod replay;
+pub mod subscriptions;
 
+use self::capture::{CaptureWriter, Frame, FrameSink};
 use self::message::{Subscription, Channel, Product, IncomingMessage, OutgoingMessage};
+use self::subscriptions::SubscriptionHandle;
+use gdax::auth::Credentials;
 
 const LIVE_URL: &'static str = "wss://ws-feed.gdax.com";
 const SANDBOX_URL: &'static str = "wss://ws-feed-public.sandbox.gdax.com";
 
 type FeedFuture = Box<Future<Item=Feed, Error=WebSocketError>>;
 
+#[derive(Clone)]
 pub struct FeedBuilder {
   sandbox:       bool,
-  subscriptions: Vec<Subscription>,
+  url:           Option<Url>,
+  subscriptions: SubscriptionHandle,
+  sinks:         Vec<Rc<RefCell<FrameSink>>>,
+  credentials:   Option<Credentials>,
 }
 
 impl FeedBuilder {
   pub fn new() -> FeedBuilder {
     FeedBuilder {
       sandbox:       false,
-      subscriptions: vec![],
+      url:           None,
+      subscriptions: SubscriptionHandle::new(),
+      sinks:         vec![],
+      credentials:   None,
     }
   }
 
@@ -31,59 +54,126 @@ impl FeedBuilder {
     self
   }
 
-  pub fn _subscribe(mut self, channel: Channel, product: Product) -> FeedBuilder {
-    self.subscriptions.push(Subscription {
+  /// Connect to `url` instead of GDAX, which may use either the `ws` or `wss`
+  /// scheme. Overrides `sandbox`.
+  pub fn url(mut self, url: Url) -> FeedBuilder {
+    self.url = Some(url);
+    self
+  }
+
+  /// Write every text frame received to `capture`, shared by all feeds
+  /// connected with clones of this builder
+  pub fn capture(self, capture: CaptureWriter) -> FeedBuilder {
+    self.frame_sink(capture)
+  }
+
+  /// Write every text frame received to `sink`, before it's deserialized,
+  /// shared by all feeds connected with clones of this builder
+  pub fn frame_sink<F: FrameSink + 'static>(mut self, sink: F) -> FeedBuilder {
+    self.sinks.push(Rc::new(RefCell::new(sink)));
+    self
+  }
+
+  /// Sign subscribe messages with `credentials`, which is required to
+  /// subscribe to the user channel
+  pub fn credentials(mut self, credentials: Credentials) -> FeedBuilder {
+    self.credentials = Some(credentials);
+    self
+  }
+
+  pub fn subscribe(self, channel: Channel, product: Product) -> FeedBuilder {
+    self.subscriptions.request(&[Subscription {
       name:        channel,
       product_ids: vec![product],
-    });
+    }]);
     self
   }
 
-  pub fn subscribe_to_all(mut self) -> FeedBuilder {
+  /// Subscribe to every channel for each of `products`
+  pub fn subscribe_to_all(self, products: &[Product]) -> FeedBuilder {
     for channel in Channel::all() {
-      self.subscriptions.push(Subscription {
+      self.subscriptions.request(&[Subscription {
         name:        channel,
-        product_ids: Product::all()
-      });
+        product_ids: products.to_vec(),
+      }]);
     }
     self
   }
 
+  /// Handle for changing the subscriptions of feeds connected with this
+  /// builder or its clones
+  pub fn subscription_handle(&self) -> SubscriptionHandle {
+    self.subscriptions.clone()
+  }
+
   pub fn connect(self, handle: &Handle) -> Result<FeedFuture, WebSocketError> {
-    let url = if self.sandbox { SANDBOX_URL } else { LIVE_URL };
-
-    let unboxed = ClientBuilder::new(url)?
-      .async_connect_secure(None, &handle)
-      .map(move |(connection, headers)| {
-        info!("Connection to {} established", url);
-        trace!("received headers: {:?}", headers);
-
-        let mut buffer = VecDeque::new();
-
-        buffer.push_front(Feed::serialize(OutgoingMessage::Subscribe {
-          channels: self.subscriptions
-        }).unwrap());
-
-        let (sink, stream) = connection.split();
-        Feed {
-          buffer,
-          closed: false,
-          sink:   Box::new(sink),
-          stream: Box::new(stream),
-        }
-      });
+    let url = match self.url {
+      Some(ref url) => url.clone(),
+      None          => Url::parse(if self.sandbox { SANDBOX_URL } else { LIVE_URL })?,
+    };
+
+    let client = ClientBuilder::from_url(&url);
+    let subscriptions = self.subscriptions;
+    let sinks = self.sinks;
+    let credentials = self.credentials;
 
-    Ok(Box::new(unboxed))
+    let future: FeedFuture = if url.scheme() == "ws" {
+      Box::new(client.async_connect_insecure(&handle).map(move |(connection, headers)| {
+        Feed::new(connection, headers, &url, subscriptions, sinks, credentials)
+      }))
+    } else {
+      Box::new(client.async_connect_secure(None, &handle).map(move |(connection, headers)| {
+        Feed::new(connection, headers, &url, subscriptions, sinks, credentials)
+      }))
+    };
+
+    Ok(future)
   }
 }
 
 #[derive(Debug)]
 pub enum FeedError {
+  Capture(io::Error),
   Deserialization(serde_json::Error),
   Serialization(serde_json::Error),
+  Timer(io::Error),
   WebSocket(WebSocketError),
 }
 
+impl Display for FeedError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      FeedError::Capture(ref error)         => write!(f, "frame capture error: {}", error),
+      FeedError::Deserialization(ref error) => write!(f, "failed to deserialize message: {}", error),
+      FeedError::Serialization(ref error)   => write!(f, "failed to serialize message: {}", error),
+      FeedError::Timer(ref error)           => write!(f, "timer error: {}", error),
+      FeedError::WebSocket(ref error)       => write!(f, "websocket error: {}", error),
+    }
+  }
+}
+
+impl Error for FeedError {
+  fn description(&self) -> &str {
+    match *self {
+      FeedError::Capture(_)         => "frame capture error",
+      FeedError::Deserialization(_) => "failed to deserialize message",
+      FeedError::Serialization(_)   => "failed to serialize message",
+      FeedError::Timer(_)           => "timer error",
+      FeedError::WebSocket(_)       => "websocket error",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      FeedError::Capture(ref error)         => Some(error),
+      FeedError::Deserialization(ref error) => Some(error),
+      FeedError::Serialization(ref error)   => Some(error),
+      FeedError::Timer(ref error)           => Some(error),
+      FeedError::WebSocket(ref error)       => Some(error),
+    }
+  }
+}
+
 impl From<WebSocketError> for FeedError {
   fn from(error: WebSocketError) -> FeedError {
     FeedError::WebSocket(error)
@@ -91,14 +181,61 @@ impl From<WebSocketError> for FeedError {
 }
 
 pub struct Feed {
-  buffer: VecDeque<OwnedMessage>,
-  closed: bool,
-  sink:   Box<Sink<SinkItem=OwnedMessage, SinkError=WebSocketError>>,
-  stream: Box<Stream<Item=OwnedMessage, Error=WebSocketError>>,
+  buffer:        VecDeque<OwnedMessage>,
+  sinks:         Vec<Rc<RefCell<FrameSink>>>,
+  credentials:   Option<Credentials>,
+  subscriptions: SubscriptionHandle,
+  closed:        bool,
+  sink:          Box<Sink<SinkItem=OwnedMessage, SinkError=WebSocketError>>,
+  stream:        Box<Stream<Item=OwnedMessage, Error=WebSocketError>>,
 }
 
 impl Feed {
+  fn new<S: AsyncStream + 'static>(
+    connection:    Client<S>,
+    headers:       Headers,
+    url:           &Url,
+    subscriptions: SubscriptionHandle,
+    sinks:         Vec<Rc<RefCell<FrameSink>>>,
+    credentials:   Option<Credentials>,
+  ) -> Feed {
+    info!("Connection to {} established", url);
+    trace!("received headers: {:?}", headers);
+
+    let (sink, stream) = connection.split();
+    let mut feed = Feed {
+      buffer: VecDeque::new(),
+      sinks,
+      credentials,
+      subscriptions,
+      closed: false,
+      sink:   Box::new(sink),
+      stream: Box::new(stream),
+    };
+
+    let subscribe = OutgoingMessage::subscribe(feed.subscriptions.connected());
+    let message = feed.serialize(subscribe).unwrap();
+    feed.buffer.push_front(message);
+
+    feed
+  }
+
+  /// Handle for changing this feed's subscriptions
+  pub fn subscription_handle(&self) -> SubscriptionHandle {
+    self.subscriptions.clone()
+  }
+
+  fn queue(&mut self, message: OutgoingMessage) -> Result<(), FeedError> {
+    self.subscriptions.sent(&message);
+    let message = self.serialize(message)?;
+    self.buffer.push_back(message);
+    Ok(())
+  }
+
   fn try_empty_buffer(&mut self) -> Result<Async<()>, FeedError> {
+    while let Some(message) = self.subscriptions.take_pending() {
+      self.queue(message)?;
+    }
     while let Some(item) = self.buffer.pop_front() {
       if let AsyncSink::NotReady(item) = self.sink.start_send(item)?  {
         self.buffer.push_front(item);
@@ -109,7 +246,10 @@ impl Feed {
     Ok(Async::Ready(()))
   }
 
-  fn serialize(message: OutgoingMessage) -> Result<OwnedMessage, FeedError> {
+  fn serialize(&self, mut message: OutgoingMessage) -> Result<OwnedMessage, FeedError> {
+    if let Some(ref credentials) = self.credentials {
+      credentials.authenticate(&mut message);
+    }
     Ok(OwnedMessage::Text(serde_json::to_string(&message).map_err(FeedError::Serialization)?))
   }
 
@@ -124,10 +264,13 @@ impl Stream for Feed {
 
   fn poll(&mut self) -> Poll<Option<IncomingMessage>, FeedError> {
     loop {
-      self.try_empty_buffer()?;
+      // flush subscriptions and pongs, even if nothing is sending to the sink
+      if self.try_empty_buffer()?.is_ready() {
+        self.sink.poll_complete()?;
+      }
 
       match try_ready!(self.stream.poll()) {
-        ref message if self.closed => {
+        Some(ref message) if self.closed => {
           warn!("Received message after close: {:?}", message);
         }
         Some(OwnedMessage::Close(close_data)) => {
@@ -144,8 +287,17 @@ impl Stream for Feed {
           warn!("Got binary message from GDAX: {:?}", data);
         }
         Some(OwnedMessage::Text(data)) => {
+          if !self.sinks.is_empty() {
+            let frame = Frame::now(data.clone());
+            for sink in &self.sinks {
+              sink.borrow_mut().write_frame(&frame).map_err(FeedError::Capture)?;
+            }
+          }
           let incoming_message = Feed::deserialize(&data)?;
           warn_incomplete_deserialization(&data, &incoming_message);
+          if let IncomingMessage::Subscriptions{ref channels} = incoming_message {
+            self.subscriptions.confirm(channels);
+          }
           return Ok(Async::Ready(Some(incoming_message)));
         }
         None => return Ok(Async::Ready(None)),
@@ -160,7 +312,7 @@ impl Sink for Feed {
 
   fn start_send(&mut self, item: OutgoingMessage) -> Result<AsyncSink<OutgoingMessage>, FeedError> {
     self.try_empty_buffer()?;
-    self.buffer.push_back(Feed::serialize(item)?);
+    self.queue(item)?;
     Ok(AsyncSink::Ready)
   }
 
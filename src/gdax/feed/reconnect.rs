@@ -0,0 +1,228 @@
+use futures::prelude::*;
+use std::cell::Cell;
+use std::cmp;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+use websocket::WebSocketError;
+
+use super::{Feed, FeedBuilder, FeedError, FeedFuture};
+use super::message::{IncomingMessage, OutgoingMessage};
+use super::subscriptions::SubscriptionHandle;
+
+/// Seconds to wait before the first retry, doubling after each failure
+pub const MIN_BACKOFF: u64 = 1;
+/// Longest wait between retries, in seconds
+pub const MAX_BACKOFF: u64 = 64;
+
+#[derive(Debug)]
+pub enum FeedEvent {
+  Message(IncomingMessage),
+  /// The connection was lost, so messages may have been missed. Order books
+  /// should be discarded and rebuilt from the snapshots sent after reconnecting.
+  Discontinuity,
+}
+
+impl From<IncomingMessage> for FeedEvent {
+  fn from(message: IncomingMessage) -> FeedEvent {
+    FeedEvent::Message(message)
+  }
+}
+
+/// Requests that a `ReconnectingFeed` drop its connection and reconnect, for
+/// example after missing messages
+#[derive(Clone)]
+pub struct ResyncHandle {
+  requested: Rc<Cell<bool>>,
+}
+
+impl ResyncHandle {
+  pub fn resync(&self) {
+    self.requested.set(true);
+  }
+}
+
+enum State {
+  Connecting(FeedFuture),
+  Waiting(Timeout),
+  Connected(Feed),
+}
+
+/// A feed that reconnects with exponential backoff when its connection is
+/// lost. Messages that fail to deserialize are logged and skipped.
+pub struct ReconnectingFeed {
+  builder:       FeedBuilder,
+  handle:        Handle,
+  state:         State,
+  backoff:       u64,
+  discontinuity: bool,
+  pending:       VecDeque<OutgoingMessage>,
+  resync:        Rc<Cell<bool>>,
+}
+
+impl ReconnectingFeed {
+  pub fn new(builder: FeedBuilder, handle: &Handle) -> Result<ReconnectingFeed, WebSocketError> {
+    Ok(ReconnectingFeed {
+      state:         State::Connecting(builder.clone().connect(handle)?),
+      handle:        handle.clone(),
+      backoff:       MIN_BACKOFF,
+      discontinuity: false,
+      pending:       VecDeque::new(),
+      resync:        Rc::new(Cell::new(false)),
+      builder,
+    })
+  }
+
+  pub fn resync_handle(&self) -> ResyncHandle {
+    ResyncHandle{requested: self.resync.clone()}
+  }
+
+  pub fn subscription_handle(&self) -> SubscriptionHandle {
+    self.builder.subscription_handle()
+  }
+
+  fn wait(&mut self) -> Result<(), FeedError> {
+    info!("Reconnecting in {} seconds", self.backoff);
+    let timeout = Timeout::new(Duration::from_secs(self.backoff), &self.handle)
+      .map_err(FeedError::Timer)?;
+    self.state = State::Waiting(timeout);
+    self.backoff = cmp::min(self.backoff * 2, MAX_BACKOFF);
+    Ok(())
+  }
+
+  fn disconnect(&mut self) -> Result<(), FeedError> {
+    self.discontinuity = true;
+    self.wait()
+  }
+
+  fn connect(&mut self) -> Result<(), FeedError> {
+    self.state = State::Connecting(self.builder.clone().connect(&self.handle)?);
+    Ok(())
+  }
+
+  fn connected(&mut self, mut feed: Feed) -> Result<(), FeedError> {
+    while let Some(message) = self.pending.pop_front() {
+      match feed.start_send(message.clone()) {
+        Ok(_) => {}
+        Err(FeedError::WebSocket(error)) => {
+          warn!("Feed connection lost: {}", error);
+          self.pending.push_front(message);
+          return self.disconnect();
+        }
+        Err(error) => return Err(error),
+      }
+    }
+    self.state = State::Connected(feed);
+    Ok(())
+  }
+}
+
+impl Stream for ReconnectingFeed {
+  type Item  = FeedEvent;
+  type Error = FeedError;
+
+  fn poll(&mut self) -> Poll<Option<FeedEvent>, FeedError> {
+    if self.resync.replace(false) {
+      if let State::Connected(_) = self.state {
+        info!("Resyncing feed");
+        self.discontinuity = true;
+        self.connect()?;
+      }
+    }
+
+    loop {
+      if self.discontinuity {
+        self.discontinuity = false;
+        return Ok(Async::Ready(Some(FeedEvent::Discontinuity)));
+      }
+
+      let poll = match self.state {
+        State::Connecting(ref mut future) => match future.poll() {
+          Ok(Async::Ready(feed)) => Ok(Async::Ready(Some(feed))),
+          Ok(Async::NotReady)    => Ok(Async::NotReady),
+          Err(error)             => Err(FeedError::WebSocket(error)),
+        },
+        State::Waiting(ref mut timeout) => {
+          try_ready!(timeout.poll().map_err(FeedError::Timer));
+          Ok(Async::Ready(None))
+        }
+        State::Connected(ref mut feed) => match feed.poll() {
+          Ok(Async::Ready(Some(message))) => {
+            self.backoff = MIN_BACKOFF;
+            return Ok(Async::Ready(Some(FeedEvent::Message(message))));
+          }
+          Ok(Async::NotReady)     => return Ok(Async::NotReady),
+          Ok(Async::Ready(None))  => {
+            warn!("Feed connection closed");
+            self.disconnect()?;
+            continue;
+          }
+          Err(FeedError::WebSocket(error)) => {
+            warn!("Feed connection lost: {}", error);
+            self.disconnect()?;
+            continue;
+          }
+          Err(FeedError::Deserialization(error)) => {
+            error!("Ignoring message that failed to deserialize: {}", error);
+            continue;
+          }
+          Err(error) => return Err(error),
+        },
+      };
+
+      match poll {
+        Ok(Async::Ready(Some(feed))) => self.connected(feed)?,
+        Ok(Async::Ready(None))       => self.connect()?,
+        Ok(Async::NotReady)          => return Ok(Async::NotReady),
+        Err(error)                   => {
+          warn!("Failed to connect to feed: {}", error);
+          self.wait()?;
+        }
+      }
+    }
+  }
+}
+
+impl Sink for ReconnectingFeed {
+  type SinkItem  = OutgoingMessage;
+  type SinkError = FeedError;
+
+  /// Messages sent while disconnected, or when the connection is lost while
+  /// sending them, are sent after reconnecting
+  fn start_send(&mut self, item: OutgoingMessage) -> StartSend<OutgoingMessage, FeedError> {
+    let result = match self.state {
+      State::Connected(ref mut feed) => feed.start_send(item.clone()),
+      _ => {
+        self.pending.push_back(item);
+        return Ok(AsyncSink::Ready);
+      }
+    };
+
+    match result {
+      Err(FeedError::WebSocket(error)) => {
+        warn!("Feed connection lost: {}", error);
+        self.disconnect()?;
+        self.pending.push_back(item);
+        Ok(AsyncSink::Ready)
+      }
+      result => result,
+    }
+  }
+
+  fn poll_complete(&mut self) -> Poll<(), FeedError> {
+    let result = match self.state {
+      State::Connected(ref mut feed) => feed.poll_complete(),
+      _                              => return Ok(Async::Ready(())),
+    };
+
+    match result {
+      Err(FeedError::WebSocket(error)) => {
+        warn!("Feed connection lost: {}", error);
+        self.disconnect()?;
+        Ok(Async::Ready(()))
+      }
+      result => result,
+    }
+  }
+}
@@ -0,0 +1,260 @@
+use chrono::Duration;
+use futures::prelude::*;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+use tokio_core::reactor::{Handle, Timeout};
+
+use super::{Feed, FeedError};
+use super::capture::{CaptureReader, FrameSink};
+use super::message::{DateTime, IncomingMessage, Product};
+
+pub struct ReplayBuilder {
+  products: Vec<Product>,
+  start:    Option<DateTime>,
+  end:      Option<DateTime>,
+  speed:    Option<f64>,
+  sinks:    Vec<Box<FrameSink>>,
+}
+
+impl ReplayBuilder {
+  pub fn new() -> ReplayBuilder {
+    ReplayBuilder {
+      products: vec![],
+      start:    None,
+      end:      None,
+      speed:    None,
+      sinks:    vec![],
+    }
+  }
+
+  /// Only replay messages for `product`, may be called more than once
+  pub fn product(mut self, product: Product) -> ReplayBuilder {
+    self.products.push(product);
+    self
+  }
+
+  /// Skip frames received before `start`
+  pub fn start(mut self, start: DateTime) -> ReplayBuilder {
+    self.start = Some(start);
+    self
+  }
+
+  /// Stop at the first frame received after `end`
+  pub fn end(mut self, end: DateTime) -> ReplayBuilder {
+    self.end = Some(end);
+    self
+  }
+
+  /// Replay at `speed` times the original pace, instead of as fast as
+  /// possible. Panics unless `speed` is finite and greater than zero.
+  pub fn speed(mut self, speed: f64) -> ReplayBuilder {
+    assert!(speed.is_finite() && speed > 0.0, "replay speed must be finite and greater than zero: {}", speed);
+    self.speed = Some(speed);
+    self
+  }
+
+  /// Write the frame of every message replayed to `sink`
+  pub fn frame_sink<F: FrameSink + 'static>(mut self, sink: F) -> ReplayBuilder {
+    self.sinks.push(Box::new(sink));
+    self
+  }
+
+  pub fn open(self, path: &Path, handle: &Handle) -> io::Result<Replay> {
+    Ok(Replay {
+      reader:   CaptureReader::open(path)?,
+      products: self.products,
+      start:    self.start,
+      end:      self.end,
+      speed:    self.speed,
+      sinks:    self.sinks,
+      handle:   handle.clone(),
+      origin:   None,
+      delayed:  None,
+    })
+  }
+}
+
+/// A stream of the messages in a capture file, yielding the same items as
+/// `Feed`. Like `ReconnectingFeed`, messages that fail to deserialize are
+/// logged and skipped, and a torn final frame ends the stream.
+pub struct Replay {
+  reader:   CaptureReader,
+  products: Vec<Product>,
+  start:    Option<DateTime>,
+  end:      Option<DateTime>,
+  speed:    Option<f64>,
+  sinks:    Vec<Box<FrameSink>>,
+  handle:   Handle,
+  origin:   Option<(DateTime, Instant)>,
+  delayed:  Option<(Timeout, IncomingMessage)>,
+}
+
+impl Replay {
+  /// How long to wait before yielding a message received at `received`
+  fn delay(&mut self, received: DateTime, speed: f64) -> Option<::std::time::Duration> {
+    let (first_received, first_replayed) = *self.origin.get_or_insert((received, Instant::now()));
+
+    let offset = received.signed_duration_since(first_received).num_nanoseconds().unwrap_or(i64::max_value());
+    let target = Duration::nanoseconds((offset as f64 / speed) as i64).to_std().ok()?;
+    let elapsed = first_replayed.elapsed();
+
+    if target > elapsed {
+      Some(target - elapsed)
+    } else {
+      None
+    }
+  }
+}
+
+impl Stream for Replay {
+  type Item  = IncomingMessage;
+  type Error = FeedError;
+
+  fn poll(&mut self) -> Poll<Option<IncomingMessage>, FeedError> {
+    if let Some((mut timeout, message)) = self.delayed.take() {
+      if timeout.poll().map_err(FeedError::Timer)?.is_not_ready() {
+        self.delayed = Some((timeout, message));
+        return Ok(Async::NotReady);
+      }
+      return Ok(Async::Ready(Some(message)));
+    }
+
+    loop {
+      let frame = match self.reader.read() {
+        Ok(Some(frame)) => frame,
+        Ok(None)        => return Ok(Async::Ready(None)),
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+          warn!("Ignoring torn frame at end of capture: {}", error);
+          return Ok(Async::Ready(None));
+        }
+        Err(error) => return Err(FeedError::Capture(error)),
+      };
+
+      if self.start.map(|start| frame.received < start).unwrap_or(false) {
+        continue;
+      }
+
+      if self.end.map(|end| frame.received > end).unwrap_or(false) {
+        return Ok(Async::Ready(None));
+      }
+
+      let message = match Feed::deserialize(&frame.text) {
+        Ok(message) => message,
+        Err(FeedError::Deserialization(error)) => {
+          error!("Ignoring message that failed to deserialize: {}", error);
+          continue;
+        }
+        Err(error) => return Err(error),
+      };
+
+      if let Some(product) = message.product_id() {
+        if !self.products.is_empty() && !self.products.contains(&product) {
+          continue;
+        }
+      }
+
+      for sink in &mut self.sinks {
+        sink.write_frame(&frame).map_err(FeedError::Capture)?;
+      }
+
+      if let Some(speed) = self.speed {
+        if let Some(delay) = self.delay(frame.received, speed) {
+          let timeout = Timeout::new(delay, &self.handle).map_err(FeedError::Timer)?;
+          self.delayed = Some((timeout, message));
+          return self.poll();
+        }
+      }
+
+      return Ok(Async::Ready(Some(message)));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{TimeZone, Utc};
+  use std::{env, fs, process};
+  use std::fs::OpenOptions;
+  use tokio_core::reactor::Core;
+
+  use gdax::feed::capture::{CaptureWriter, Frame};
+
+  fn heartbeat(product: &str, sequence: u64) -> String {
+    format!(
+      r#"{{"type":"heartbeat","sequence":{},"last_trade_id":0,"product_id":"{}","time":"2017-10-01T00:00:00Z"}}"#,
+      sequence, product,
+    )
+  }
+
+  fn replay(name: &str, builder: ReplayBuilder) -> Vec<Option<u64>> {
+    let path = env::temp_dir().join(format!("whim-replay-{}-{}.capture", name, process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut writer = CaptureWriter::open(&path).unwrap();
+    for (seconds, text) in vec![
+      (0, r#"{"type":"subscriptions","channels":[]}"#.to_string()),
+      (1, heartbeat("BTC-USD", 1)),
+      (2, heartbeat("ETH-USD", 2)),
+      (3, heartbeat("BTC-USD", 3)),
+      (4, heartbeat("BTC-USD", 4)),
+    ] {
+      writer.write(&Frame{received: Utc.timestamp(seconds, 0), text}).unwrap();
+    }
+
+    let mut core = Core::new().unwrap();
+    let replay = builder.open(&path, &core.handle()).unwrap();
+    let messages = core.run(replay.collect()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    messages.iter().map(IncomingMessage::sequence).collect()
+  }
+
+  #[test]
+  fn everything() {
+    assert_eq!(replay("everything", ReplayBuilder::new()), vec![None, Some(1), Some(2), Some(3), Some(4)]);
+  }
+
+  #[test]
+  fn product() {
+    assert_eq!(
+      replay("product", ReplayBuilder::new().product("BTC-USD".parse().unwrap())),
+      vec![None, Some(1), Some(3), Some(4)],
+    );
+  }
+
+  #[test]
+  fn time_range() {
+    assert_eq!(
+      replay("time-range", ReplayBuilder::new().start(Utc.timestamp(2, 0)).end(Utc.timestamp(3, 0))),
+      vec![Some(2), Some(3)],
+    );
+  }
+
+  #[test]
+  fn skip_undeserializable_and_torn() {
+    let path = env::temp_dir().join(format!("whim-replay-skip-{}.capture", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut writer = CaptureWriter::open(&path).unwrap();
+    for (seconds, text) in vec![
+      (0, heartbeat("BTC-USD", 1)),
+      (1, r#"{"type":"novel"}"#.to_string()),
+      (2, heartbeat("BTC-USD", 2)),
+      (3, heartbeat("BTC-USD", 3)),
+    ] {
+      writer.write(&Frame{received: Utc.timestamp(seconds, 0), text}).unwrap();
+    }
+    drop(writer);
+    let length = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(length - 2).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let replay = ReplayBuilder::new().open(&path, &core.handle()).unwrap();
+    let messages = core.run(replay.collect()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(messages.iter().map(IncomingMessage::sequence).collect::<Vec<_>>(), vec![Some(1), Some(2)]);
+  }
+}
@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::rc::Rc;
+
+use super::message::{Channel, OutgoingMessage, Product, Subscription};
+
+pub type Pair = (Channel, Product);
+
+/// Flatten subscriptions into channel/product pairs
+pub fn pairs(subscriptions: &[Subscription]) -> BTreeSet<Pair> {
+  subscriptions.iter()
+    .flat_map(|subscription| {
+      subscription.product_ids.iter().map(move |product| (subscription.name, *product))
+    })
+    .collect()
+}
+
+/// Group channel/product pairs into one subscription per channel
+pub fn subscriptions(pairs: &BTreeSet<Pair>) -> Vec<Subscription> {
+  let mut channels: BTreeMap<Channel, Vec<Product>> = BTreeMap::new();
+  for &(channel, product) in pairs {
+    channels.entry(channel).or_insert_with(Vec::new).push(product);
+  }
+  channels.into_iter()
+    .map(|(name, product_ids)| Subscription{name, product_ids})
+    .collect()
+}
+
+struct State {
+  /// Pairs to subscribe to when connecting, updated as changes are sent
+  requested: BTreeSet<Pair>,
+  /// Pairs in the last subscriptions message from the server
+  confirmed: BTreeSet<Pair>,
+  /// Changes waiting to be sent
+  pending:   VecDeque<OutgoingMessage>,
+}
+
+/// Changes the subscriptions of a running feed, and reports which
+/// subscriptions the server has confirmed. Shared by all feeds connected with
+/// clones of the same `FeedBuilder`, so changes survive reconnection.
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+  state: Rc<RefCell<State>>,
+}
+
+impl SubscriptionHandle {
+  pub fn new() -> SubscriptionHandle {
+    SubscriptionHandle {
+      state: Rc::new(RefCell::new(State {
+        requested: BTreeSet::new(),
+        confirmed: BTreeSet::new(),
+        pending:   VecDeque::new(),
+      })),
+    }
+  }
+
+  /// Subscribe to `channel` for `product`, the next time the feed is polled
+  pub fn subscribe(&self, channel: Channel, product: Product) {
+    let channels = vec![Subscription{name: channel, product_ids: vec![product]}];
+    self.state.borrow_mut().pending.push_back(OutgoingMessage::subscribe(channels));
+  }
+
+  /// Unsubscribe from `channel` for `product`, the next time the feed is polled
+  pub fn unsubscribe(&self, channel: Channel, product: Product) {
+    let channels = vec![Subscription{name: channel, product_ids: vec![product]}];
+    self.state.borrow_mut().pending.push_back(OutgoingMessage::Unsubscribe{channels});
+  }
+
+  /// Pairs the server has confirmed that the feed is subscribed to
+  pub fn confirmed(&self) -> BTreeSet<Pair> {
+    self.state.borrow().confirmed.clone()
+  }
+
+  pub fn is_confirmed(&self, channel: Channel, product: Product) -> bool {
+    self.state.borrow().confirmed.contains(&(channel, product))
+  }
+
+  /// Add pairs to subscribe to when connecting
+  pub(super) fn request(&self, subscriptions: &[Subscription]) {
+    self.state.borrow_mut().requested.extend(pairs(subscriptions));
+  }
+
+  /// Subscriptions to send when a new connection is established, which
+  /// forgets previous confirmations
+  pub(super) fn connected(&self) -> Vec<Subscription> {
+    let mut state = self.state.borrow_mut();
+    state.confirmed.clear();
+    subscriptions(&state.requested)
+  }
+
+  pub(super) fn take_pending(&self) -> Option<OutgoingMessage> {
+    self.state.borrow_mut().pending.pop_front()
+  }
+
+  /// Record a change being sent, so it is requested again after reconnecting
+  pub(super) fn sent(&self, message: &OutgoingMessage) {
+    let mut state = self.state.borrow_mut();
+    match *message {
+      OutgoingMessage::Subscribe{ref channels, ..} => state.requested.extend(pairs(channels)),
+      OutgoingMessage::Unsubscribe{ref channels} => {
+        for pair in pairs(channels) {
+          state.requested.remove(&pair);
+        }
+      }
+    }
+  }
+
+  /// Record the subscriptions the server has confirmed
+  pub(super) fn confirm(&self, channels: &[Subscription]) {
+    self.state.borrow_mut().confirmed = pairs(channels);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use futures::prelude::*;
+  use gdax::feed::FeedBuilder;
+  use gdax::feed::message::IncomingMessage;
+  use gdax::feed::mock::MockFeed;
+  use tokio_core::reactor::Core;
+
+  #[test]
+  fn grouping() {
+    let (btc_usd, eth_usd) = ("BTC-USD".parse().unwrap(), "ETH-USD".parse().unwrap());
+    let pairs = [
+      (Channel::Full, eth_usd),
+      (Channel::Ticker, btc_usd),
+      (Channel::Full, btc_usd),
+    ].iter().cloned().collect();
+    let grouped = subscriptions(&pairs);
+    assert_eq!(grouped.len(), 2);
+    assert_eq!(super::pairs(&grouped), pairs);
+  }
+
+  #[test]
+  fn subscribe_and_unsubscribe() {
+    let (btc_usd, eth_usd) = ("BTC-USD".parse().unwrap(), "ETH-USD".parse().unwrap());
+    let mut core = Core::new().unwrap();
+    let server = MockFeed::new().bind(&"127.0.0.1:0".parse().unwrap(), &core.handle()).unwrap();
+    let builder = FeedBuilder::new().url(server.url()).subscribe(Channel::Full, btc_usd);
+    let received = server.received();
+    core.handle().spawn(server.map_err(|error| panic!("mock feed failed: {}", error)));
+
+    let handle = builder.subscription_handle();
+    let feed = core.run(builder.connect(&core.handle()).unwrap()).unwrap();
+
+    let (message, feed) = core.run(feed.into_future()).map_err(|(error, _)| error).unwrap();
+    match message {
+      Some(IncomingMessage::Subscriptions{..}) => {}
+      other => panic!("expected subscriptions: {:?}", other),
+    }
+    assert!(handle.is_confirmed(Channel::Full, btc_usd));
+
+    handle.subscribe(Channel::Ticker, eth_usd);
+    handle.unsubscribe(Channel::Full, btc_usd);
+
+    core.run(feed.take(2).collect()).unwrap();
+    assert_eq!(handle.confirmed(), [(Channel::Ticker, eth_usd)].iter().cloned().collect());
+    assert_eq!(received.borrow().len(), 3);
+    assert!(received.borrow()[2].contains(r#""type":"unsubscribe""#));
+
+    assert_eq!(
+      super::pairs(&handle.connected()),
+      [(Channel::Ticker, eth_usd)].iter().cloned().collect(),
+    );
+  }
+}
@@ -0,0 +1,292 @@
+use chrono::Duration;
+use serde_json;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use decimal::{Decimal, Rounding};
+use gdax::feed::message::{BaseCurrencyAmount, DateTime, IncomingMessage, Product, QuoteCurrencyAmount,
+                          QuoteCurrencyPrice};
+use gdax::rest::message::Liquidity;
+
+/// Fills report fees to this many decimal places
+const FEE_PLACES: u32 = 16;
+
+/// Fee rates that apply from a share of market volume upwards
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct FeeTier {
+  /// Smallest share of the product's trailing 30-day volume traded to
+  /// qualify, e.g. `0.01` for 1%
+  pub min_share: Decimal,
+  pub maker:     Decimal,
+  pub taker:     Decimal,
+}
+
+#[derive(Debug)]
+pub enum FeeScheduleError {
+  Deserialization(serde_json::Error),
+  Empty,
+  Io(io::Error),
+}
+
+impl Display for FeeScheduleError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      FeeScheduleError::Deserialization(ref error) => write!(f, "failed to deserialize fee schedule: {}", error),
+      FeeScheduleError::Empty                      => write!(f, "fee schedule has no tiers"),
+      FeeScheduleError::Io(ref error)              => write!(f, "I/O error: {}", error),
+    }
+  }
+}
+
+impl Error for FeeScheduleError {
+  fn description(&self) -> &str {
+    match *self {
+      FeeScheduleError::Deserialization(_) => "failed to deserialize fee schedule",
+      FeeScheduleError::Empty              => "fee schedule has no tiers",
+      FeeScheduleError::Io(_)              => "I/O error",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      FeeScheduleError::Deserialization(ref error) => Some(error),
+      FeeScheduleError::Io(ref error)              => Some(error),
+      FeeScheduleError::Empty                      => None,
+    }
+  }
+}
+
+/// Maker and taker fee rates by volume tier
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeSchedule {
+  tiers: Vec<FeeTier>,
+}
+
+impl FeeSchedule {
+  /// A schedule from `tiers`, in any order. Volume below the lowest tier's
+  /// minimum share pays the lowest tier's rates.
+  pub fn new(mut tiers: Vec<FeeTier>) -> Result<FeeSchedule, FeeScheduleError> {
+    if tiers.is_empty() {
+      return Err(FeeScheduleError::Empty);
+    }
+    tiers.sort_by_key(|tier| tier.min_share);
+    Ok(FeeSchedule{tiers})
+  }
+
+  /// The same rates at any volume
+  pub fn flat(maker: Decimal, taker: Decimal) -> FeeSchedule {
+    FeeSchedule{tiers: vec![FeeTier{min_share: Decimal::zero(), maker, taker}]}
+  }
+
+  /// The exchange's schedule, with free maker fills and taker fees falling
+  /// from 0.25% to 0.10%
+  pub fn gdax() -> FeeSchedule {
+    let tier = |min_share: &str, taker: &str| FeeTier {
+      min_share: min_share.parse().unwrap(),
+      maker:     Decimal::zero(),
+      taker:     taker.parse().unwrap(),
+    };
+
+    FeeSchedule {
+      tiers: vec![
+        tier("0",     "0.0025"),
+        tier("0.01",  "0.0024"),
+        tier("0.025", "0.0022"),
+        tier("0.05",  "0.0019"),
+        tier("0.1",   "0.0015"),
+        tier("0.2",   "0.0010"),
+      ],
+    }
+  }
+
+  /// Parse a JSON array of tiers
+  pub fn from_json(json: &[u8]) -> Result<FeeSchedule, FeeScheduleError> {
+    FeeSchedule::new(serde_json::from_slice(json).map_err(FeeScheduleError::Deserialization)?)
+  }
+
+  /// Load a JSON array of tiers from the file at `path`
+  pub fn load(path: &Path) -> Result<FeeSchedule, FeeScheduleError> {
+    let mut json = Vec::new();
+    File::open(path)
+      .and_then(|mut file| file.read_to_end(&mut json))
+      .map_err(FeeScheduleError::Io)?;
+    FeeSchedule::from_json(&json)
+  }
+
+  /// Tiers from lowest to highest minimum share
+  pub fn tiers(&self) -> &[FeeTier] {
+    &self.tiers
+  }
+
+  /// The highest tier that `share` of market volume qualifies for
+  pub fn tier(&self, share: Decimal) -> FeeTier {
+    self.tiers.iter()
+      .rev()
+      .find(|tier| tier.min_share <= share)
+      .cloned()
+      .unwrap_or(self.tiers[0])
+  }
+}
+
+/// Computes fees from a `FeeSchedule`, tracking our trailing 30-day volume
+/// from fills and market volume from tickers to pick the tier for each
+/// product
+#[derive(Debug, Clone)]
+pub struct FeeModel {
+  schedule: FeeSchedule,
+  /// Fills in the trailing 30 days, oldest first
+  fills:    VecDeque<(DateTime, Product, BaseCurrencyAmount)>,
+  volume:   BTreeMap<Product, BaseCurrencyAmount>,
+  market:   BTreeMap<Product, BaseCurrencyAmount>,
+}
+
+impl FeeModel {
+  pub fn new(schedule: FeeSchedule) -> FeeModel {
+    FeeModel {
+      fills:  VecDeque::new(),
+      volume: BTreeMap::new(),
+      market: BTreeMap::new(),
+      schedule,
+    }
+  }
+
+  /// Record market volume from a ticker
+  pub fn observe(&mut self, message: &IncomingMessage) {
+    if let IncomingMessage::Ticker(ref ticker) = *message {
+      self.market.insert(ticker.product_id, ticker.volume_30d);
+    }
+  }
+
+  fn expire(&mut self, now: DateTime) {
+    while let Some(&(time, product, size)) = self.fills.front() {
+      if now.signed_duration_since(time) < Duration::days(30) {
+        break;
+      }
+      self.fills.pop_front();
+      let volume = self.volume.get_mut(&product).expect("fill without volume");
+      *volume = volume.checked_sub(size).expect("volume less than fill");
+    }
+  }
+
+  /// The tier our trailing volume in `product` qualifies for at `now`.
+  /// Until a ticker reports market volume, that's the lowest tier.
+  pub fn tier(&mut self, product: Product, now: DateTime) -> FeeTier {
+    self.expire(now);
+    let volume = self.volume.get(&product).cloned().unwrap_or_else(BaseCurrencyAmount::zero);
+    let share = match self.market.get(&product) {
+      Some(&market) if !market.is_zero() =>
+        Decimal::from(volume).div_rounded(Decimal::from(market), Rounding::Floor),
+      _ => Decimal::zero(),
+    };
+    self.schedule.tier(share)
+  }
+
+  /// The fee for a fill at `now`
+  pub fn fee(
+    &mut self,
+    now:       DateTime,
+    product:   Product,
+    liquidity: Liquidity,
+    price:     QuoteCurrencyPrice,
+    size:      BaseCurrencyAmount,
+  ) -> QuoteCurrencyAmount {
+    let tier = self.tier(product, now);
+    let rate = match liquidity {
+      Liquidity::Maker => tier.maker,
+      Liquidity::Taker => tier.taker,
+    };
+    notional_fee(price.notional(size), rate)
+  }
+
+  /// Count a fill at `now` towards our volume. Fills may be recorded out of
+  /// order, e.g. from REST pages, which list the newest first.
+  pub fn record(&mut self, now: DateTime, product: Product, size: BaseCurrencyAmount) {
+    let index = self.fills.partition_point(|&(time, _, _)| time <= now);
+    self.fills.insert(index, (now, product, size));
+    let volume = self.volume.entry(product).or_insert_with(BaseCurrencyAmount::zero);
+    *volume = *volume + size;
+  }
+}
+
+/// Fee on `notional` at `rate`. The exact product is rounded once, half to
+/// even, to the `FEE_PLACES` decimal places of the `fill_fees` and `fee`
+/// fields the exchange reports on orders and fills, so a fee is never off by
+/// a second rounding.
+pub fn notional_fee(notional: QuoteCurrencyAmount, rate: Decimal) -> QuoteCurrencyAmount {
+  Decimal::from(notional)
+    .checked_mul_round(rate, FEE_PLACES, Rounding::HalfEven)
+    .expect("fee overflowed")
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{TimeZone, Utc};
+
+  fn decimal(s: &str) -> Decimal {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn tiers() {
+    let schedule = FeeSchedule::gdax();
+    assert_eq!(schedule.tier(Decimal::zero()).taker, decimal("0.0025"));
+    assert_eq!(schedule.tier(decimal("0.0099")).taker, decimal("0.0025"));
+    assert_eq!(schedule.tier(decimal("0.01")).taker, decimal("0.0024"));
+    assert_eq!(schedule.tier(decimal("0.5")).taker, decimal("0.0010"));
+    assert!(FeeSchedule::new(vec![]).is_err());
+  }
+
+  #[test]
+  fn schedule_json() {
+    let schedule = FeeSchedule::from_json(br#"[
+      {"min_share": "0.1", "maker": "0", "taker": "0.001"},
+      {"min_share": "0", "maker": "0", "taker": "0.003"}
+    ]"#).unwrap();
+    assert_eq!(schedule.tiers()[0].taker, decimal("0.003"));
+    assert_eq!(schedule.tier(decimal("0.2")).taker, decimal("0.001"));
+  }
+
+  #[test]
+  fn trailing_volume() {
+    let product = "BTC-USD".parse().unwrap();
+    let mut model = FeeModel::new(FeeSchedule::gdax());
+    let price = "100.00".parse().unwrap();
+
+    model.observe(&::serde_json::from_str(r#"{"type": "ticker", "product_id": "BTC-USD", "sequence": 1,
+      "price": "100.00", "best_bid": "100.00", "best_ask": "101.00", "high_24h": "0", "open_24h": "0",
+      "low_24h": "0", "volume_24h": "0", "volume_30d": "1000"}"#).unwrap());
+
+    let size = "20".parse().unwrap();
+    assert_eq!(model.fee(Utc.timestamp(0, 0), product, Liquidity::Taker, price, size), "5".parse().unwrap());
+    model.record(Utc.timestamp(0, 0), product, size);
+    assert_eq!(model.tier(product, Utc.timestamp(0, 0)).taker, decimal("0.0024"));
+
+    let size = "1".parse().unwrap();
+    assert_eq!(model.fee(Utc.timestamp(60, 0), product, Liquidity::Taker, price, size), "0.24".parse().unwrap());
+    model.record(Utc.timestamp(60, 0), product, size);
+
+    let later = Utc.timestamp(0, 0) + Duration::days(30);
+    assert_eq!(model.tier(product, later).taker, decimal("0.0025"));
+  }
+
+  #[test]
+  fn out_of_order_fills() {
+    let product = "BTC-USD".parse().unwrap();
+    let mut model = FeeModel::new(FeeSchedule::gdax());
+
+    model.observe(&::serde_json::from_str(r#"{"type": "ticker", "product_id": "BTC-USD", "sequence": 1,
+      "price": "100.00", "best_bid": "100.00", "best_ask": "101.00", "high_24h": "0", "open_24h": "0",
+      "low_24h": "0", "volume_24h": "0", "volume_30d": "1000"}"#).unwrap());
+
+    model.record(Utc.timestamp(0, 0) + Duration::days(10), product, "1".parse().unwrap());
+    model.record(Utc.timestamp(0, 0), product, "20".parse().unwrap());
+    assert_eq!(model.tier(product, Utc.timestamp(0, 0) + Duration::days(10)).taker, decimal("0.0024"));
+    assert_eq!(model.tier(product, Utc.timestamp(0, 0) + Duration::days(30)).taker, decimal("0.0025"));
+  }
+}
@@ -0,0 +1,93 @@
+//! Feed message and product fixtures shared by tests
+
+use serde_json;
+use uuid::Uuid;
+
+use gdax::feed::message::IncomingMessage;
+use gdax::feed::mock::MockEvent;
+use gdax::products::Products;
+
+/// A listing of BTC-USD alone
+pub fn products() -> Products {
+  Products::from_listing(br#"[
+    {
+      "id":              "BTC-USD",
+      "base_currency":   "BTC",
+      "quote_currency":  "USD",
+      "base_min_size":   "0.01",
+      "base_max_size":   "10000.00",
+      "quote_increment": "0.01"
+    }
+  ]"#).unwrap()
+}
+
+/// Order id `n`
+pub fn id(n: u8) -> Uuid {
+  format!("00000000-0000-0000-0000-0000000000{:02}", n).parse().unwrap()
+}
+
+/// The JSON object `json` with `product`, `sequence` and a fixed time added
+pub fn text(product: &str, sequence: u64, json: &str) -> String {
+  let mut value: serde_json::Value = serde_json::from_str(json).unwrap();
+  {
+    let object = value.as_object_mut().unwrap();
+    object.insert("product_id".to_string(), product.into());
+    object.insert("sequence".to_string(), sequence.into());
+    object.insert("time".to_string(), "2017-10-01T00:00:00Z".into());
+  }
+  value.to_string()
+}
+
+/// An open message for order `id(order)`
+pub fn open_text(product: &str, sequence: u64, order: u8, side: &str, price: &str, size: &str) -> String {
+  text(product, sequence, &format!(
+    r#"{{"type": "open", "order_id": "{}", "side": "{}", "price": "{}", "remaining_size": "{}"}}"#,
+    id(order), side, price, size,
+  ))
+}
+
+/// The BTC-USD message `json` with `sequence`
+pub fn message(sequence: u64, json: &str) -> IncomingMessage {
+  serde_json::from_str(&text("BTC-USD", sequence, json)).unwrap()
+}
+
+/// A BTC-USD open message for order `id(order)`
+pub fn open(sequence: u64, order: u8, side: &str, price: &str, size: &str) -> IncomingMessage {
+  serde_json::from_str(&open_text("BTC-USD", sequence, order, side, price, size)).unwrap()
+}
+
+/// A mock feed event sending a BTC-USD open message with `sequence`
+pub fn open_event(sequence: u64) -> MockEvent {
+  MockEvent::Text(open_text("BTC-USD", sequence, 1, "sell", "200.2", "1.00"))
+}
+
+/// A BTC-USD level 2 snapshot with bids at 100.00 and 99.00 and asks at
+/// 101.00 and 102.00
+pub fn snapshot_text() -> String {
+  r#"{"type": "snapshot", "product_id": "BTC-USD",
+    "bids": [["100.00", "2.0"], ["99.00", "5.0"]], "asks": [["101.00", "1.0"], ["102.00", "4.0"]]}"#.to_string()
+}
+
+pub fn snapshot() -> IncomingMessage {
+  serde_json::from_str(&snapshot_text()).unwrap()
+}
+
+/// A BTC-USD match between orders `maker` and `taker` at `second` past
+/// midnight, with that as its sequence and trade id
+pub fn match_text(second: u32, maker: Uuid, taker: Uuid, side: &str, price: &str, size: &str) -> String {
+  format!(
+    r#"{{"type": "match", "product_id": "BTC-USD", "sequence": {}, "trade_id": {},
+    "maker_order_id": "{}", "taker_order_id": "{}",
+    "time": "2017-10-01T00:00:{:02}Z", "side": "{}", "price": "{}", "size": "{}"}}"#,
+    second, second, maker, taker, second, side, price, size,
+  )
+}
+
+/// A BTC-USD match between other traders' orders
+pub fn trade_text(second: u32, side: &str, price: &str, size: &str) -> String {
+  match_text(second, Uuid::nil(), Uuid::nil(), side, price, size)
+}
+
+pub fn trade(second: u32, side: &str, price: &str, size: &str) -> IncomingMessage {
+  serde_json::from_str(&trade_text(second, side, price, size)).unwrap()
+}
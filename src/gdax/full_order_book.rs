@@ -0,0 +1,335 @@
+use gdax::feed::message::*;
+use gdax::order_book::OrderBook;
+use gdax::rest::message::{BookOrder, FullBook};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RestingOrder {
+  pub order_id: Uuid,
+  pub size:     BaseCurrencyAmount,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct QueuePosition {
+  /// Number of orders ahead at the same price
+  pub orders_ahead: usize,
+  /// Total size of the orders ahead at the same price
+  pub size_ahead:   BaseCurrencyAmount,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FullOrderBookError {
+  ProductMismatch{expected: Product, received: Product},
+  DuplicateOrder{order_id: Uuid},
+  UnknownOrder{order_id: Uuid},
+  Overfilled{order_id: Uuid, size: BaseCurrencyAmount, filled: BaseCurrencyAmount},
+  /// The order is indexed but missing from the queue at its price, which is
+  /// a bug
+  Inconsistent{order_id: Uuid},
+}
+
+type Levels = BTreeMap<QuoteCurrencyPrice, VecDeque<RestingOrder>>;
+
+/// Order-by-order book built from the full channel, with orders at each price
+/// in the order they were opened
+pub struct FullOrderBook {
+  product_id: Product,
+  sequence:   u64,
+  orders:     HashMap<Uuid, (Side, QuoteCurrencyPrice)>,
+  bids:       Levels,
+  asks:       Levels,
+}
+
+impl FullOrderBook {
+  /// An empty book, which will ignore messages with sequence numbers not
+  /// greater than `sequence`
+  pub fn new(product_id: Product, sequence: u64) -> FullOrderBook {
+    FullOrderBook {
+      product_id,
+      sequence,
+      orders: HashMap::new(),
+      bids:   BTreeMap::new(),
+      asks:   BTreeMap::new(),
+    }
+  }
+
+  /// A book with the resting orders of a level-3 REST snapshot, keeping their
+  /// order within each price
+  pub fn from_snapshot(product_id: Product, snapshot: &FullBook) -> Result<FullOrderBook, FullOrderBookError> {
+    let mut book = FullOrderBook::new(product_id, snapshot.sequence);
+    for &(side, ref orders) in &[(Side::Buy, &snapshot.bids), (Side::Sell, &snapshot.asks)] {
+      for &BookOrder(price, size, order_id) in orders.iter() {
+        book.open(order_id, side, price, size)?;
+      }
+    }
+    Ok(book)
+  }
+
+  /// Sequence number of the last message applied
+  pub fn sequence(&self) -> u64 {
+    self.sequence
+  }
+
+  fn levels(&mut self, side: Side) -> &mut Levels {
+    match side {
+      Side::Buy  => &mut self.bids,
+      Side::Sell => &mut self.asks,
+    }
+  }
+
+  fn queue(&mut self, order_id: Uuid) -> Result<&mut VecDeque<RestingOrder>, FullOrderBookError> {
+    let (side, price) = *self.orders.get(&order_id)
+      .ok_or(FullOrderBookError::UnknownOrder{order_id})?;
+    self.levels(side).get_mut(&price).ok_or(FullOrderBookError::Inconsistent{order_id})
+  }
+
+  fn resting(&mut self, order_id: Uuid) -> Result<&mut RestingOrder, FullOrderBookError> {
+    self.queue(order_id)?.iter_mut()
+      .find(|order| order.order_id == order_id)
+      .ok_or(FullOrderBookError::Inconsistent{order_id})
+  }
+
+  /// Add an order to the back of the queue at its price
+  pub fn open(&mut self, order_id: Uuid, side: Side, price: QuoteCurrencyPrice, size: BaseCurrencyAmount)
+    -> Result<(), FullOrderBookError>
+  {
+    if self.orders.contains_key(&order_id) {
+      return Err(FullOrderBookError::DuplicateOrder{order_id});
+    }
+    self.orders.insert(order_id, (side, price));
+    self.levels(side).entry(price).or_insert_with(VecDeque::new).push_back(RestingOrder{order_id, size});
+    Ok(())
+  }
+
+  /// Remove an order from the book, returning it if it was present
+  pub fn remove(&mut self, order_id: Uuid) -> Result<Option<RestingOrder>, FullOrderBookError> {
+    let (side, price) = match self.orders.get(&order_id) {
+      Some(&entry) => entry,
+      None         => return Ok(None),
+    };
+    let removed = {
+      let queue = self.queue(order_id)?;
+      let index = queue.iter().position(|order| order.order_id == order_id)
+        .ok_or(FullOrderBookError::Inconsistent{order_id})?;
+      queue.remove(index)
+    };
+    self.orders.remove(&order_id);
+    let levels = self.levels(side);
+    if levels[&price].is_empty() {
+      levels.remove(&price);
+    }
+    Ok(removed)
+  }
+
+  fn set_size(&mut self, order_id: Uuid, size: BaseCurrencyAmount) -> Result<(), FullOrderBookError> {
+    if size.is_zero() {
+      self.remove(order_id)?;
+      return Ok(());
+    }
+    self.resting(order_id)?.size = size;
+    Ok(())
+  }
+
+  fn fill(&mut self, order_id: Uuid, filled: BaseCurrencyAmount) -> Result<(), FullOrderBookError> {
+    let size = self.resting(order_id)?.size;
+    let remaining = size.checked_sub(filled)
+      .ok_or(FullOrderBookError::Overfilled{order_id, size, filled})?;
+    self.set_size(order_id, remaining)
+  }
+
+  /// Apply a full channel message. Messages that don't change resting orders,
+  /// and messages with sequence numbers not greater than the book's, are ignored.
+  ///
+  /// A message that fails to apply leaves the book unchanged, including its
+  /// sequence number. The book is then missing that message, so it should be
+  /// discarded and rebuilt from a new snapshot.
+  pub fn apply(&mut self, message: &IncomingMessage) -> Result<(), FullOrderBookError> {
+    use self::IncomingMessage::*;
+
+    match message.product_id() {
+      Some(product_id) if product_id != self.product_id => {
+        return Err(FullOrderBookError::ProductMismatch{expected: self.product_id, received: product_id});
+      }
+      _ => {}
+    }
+
+    let sequence = match *message {
+      Received{sequence, ..} |
+      Open{sequence, ..}     |
+      Done{sequence, ..}     |
+      Match{sequence, ..}    |
+      Change{sequence, ..}   => sequence,
+      _                      => return Ok(()),
+    };
+
+    if sequence <= self.sequence {
+      return Ok(());
+    }
+
+    match *message {
+      Open{order_id, side, price, remaining_size, ..} => self.open(order_id, side, price, remaining_size)?,
+      Done{order_id, ..} => {
+        self.remove(order_id)?;
+      }
+      Match{maker_order_id, size, ..} => self.fill(maker_order_id, size)?,
+      Change{order_id, new_size: Some(new_size), ..} => {
+        if self.orders.contains_key(&order_id) {
+          self.set_size(order_id, new_size)?;
+        }
+      }
+      _ => {}
+    }
+
+    self.sequence = sequence;
+
+    Ok(())
+  }
+
+  /// Position of an order in the queue at its price
+  pub fn queue_position(&self, order_id: Uuid) -> Result<QueuePosition, FullOrderBookError> {
+    let &(side, price) = self.orders.get(&order_id)
+      .ok_or(FullOrderBookError::UnknownOrder{order_id})?;
+    let queue = self.orders_at(side, price)
+      .ok_or(FullOrderBookError::Inconsistent{order_id})?;
+
+    let mut position = QueuePosition{orders_ahead: 0, size_ahead: BaseCurrencyAmount::zero()};
+    for order in queue {
+      if order.order_id == order_id {
+        return Ok(position);
+      }
+      position.orders_ahead += 1;
+      position.size_ahead = position.size_ahead + order.size;
+    }
+
+    Err(FullOrderBookError::Inconsistent{order_id})
+  }
+
+  /// Orders resting at `price` on `side`, front of the queue first
+  pub fn orders_at(&self, side: Side, price: QuoteCurrencyPrice) -> Option<&VecDeque<RestingOrder>> {
+    match side {
+      Side::Buy  => self.bids.get(&price),
+      Side::Sell => self.asks.get(&price),
+    }
+  }
+
+  /// Aggregate resting orders into a level-2 book
+  pub fn to_level2(&self) -> OrderBook {
+    fn aggregate(levels: &Levels) -> Vec<(QuoteCurrencyPrice, BaseCurrencyAmount)> {
+      levels.iter()
+        .map(|(price, orders)| {
+          (*price, orders.iter().fold(BaseCurrencyAmount::zero(), |total, order| total + order.size))
+        })
+        .collect()
+    }
+
+    OrderBook::from_snapshot(&OrderBookSnapshot {
+      product_id: self.product_id,
+      bids:       aggregate(&self.bids),
+      asks:       aggregate(&self.asks),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use gdax::fixtures::{id, message, open};
+  use serde_json;
+
+  fn level(price: &str, size: &str) -> (QuoteCurrencyPrice, BaseCurrencyAmount) {
+    serde_json::from_str(&format!(r#"["{}", "{}"]"#, price, size)).unwrap()
+  }
+
+  fn book() -> FullOrderBook {
+    let mut book = FullOrderBook::new("BTC-USD".parse().unwrap(), 0);
+    book.apply(&open(1, 1, "buy",  "100.0", "1.0")).unwrap();
+    book.apply(&open(2, 2, "buy",  "100.0", "2.0")).unwrap();
+    book.apply(&open(3, 3, "buy",  "100.0", "3.0")).unwrap();
+    book.apply(&open(4, 4, "buy",  "99.5",  "4.0")).unwrap();
+    book.apply(&open(5, 5, "sell", "101.0", "5.0")).unwrap();
+    book
+  }
+
+  #[test]
+  fn queue_position() {
+    let book = book();
+    assert_eq!(book.queue_position(id(1)).unwrap().orders_ahead, 0);
+    assert_eq!(
+      book.queue_position(id(3)).unwrap(),
+      QueuePosition{orders_ahead: 2, size_ahead: level("0", "3.0").1},
+    );
+    assert_eq!(book.queue_position(id(4)).unwrap().orders_ahead, 0);
+    assert_eq!(book.queue_position(id(9)), Err(FullOrderBookError::UnknownOrder{order_id: id(9)}));
+  }
+
+  #[test]
+  fn match_done_and_change() {
+    let mut book = book();
+
+    book.apply(&message(6, &format!(
+      r#"{{"type": "match", "maker_order_id": "{}", "taker_order_id": "{}", "side": "buy",
+          "price": "100.0", "size": "0.25", "trade_id": 1}}"#,
+      id(1), id(9),
+    ))).unwrap();
+    assert_eq!(book.orders_at(Side::Buy, level("100.0", "0").0).unwrap()[0].size, level("0", "0.75").1);
+
+    book.apply(&message(7, &format!(
+      r#"{{"type": "done", "order_id": "{}", "side": "buy", "reason": "canceled", "price": "100.0",
+          "remaining_size": "2.0"}}"#,
+      id(2),
+    ))).unwrap();
+    assert_eq!(book.queue_position(id(3)).unwrap().orders_ahead, 1);
+
+    book.apply(&message(8, &format!(
+      r#"{{"type": "change", "order_id": "{}", "side": "buy", "price": "100.0",
+          "new_size": "0.5", "old_size": "0.75"}}"#,
+      id(1),
+    ))).unwrap();
+
+    assert_eq!(book.to_level2(), OrderBook::from_snapshot(&OrderBookSnapshot {
+      product_id: "BTC-USD".parse().unwrap(),
+      bids:       vec![level("99.5", "4.0"), level("100.0", "3.5")],
+      asks:       vec![level("101.0", "5.0")],
+    }));
+    assert_eq!(book.sequence(), 8);
+  }
+
+  #[test]
+  fn fill_removes_order() {
+    let mut book = book();
+    book.apply(&message(6, &format!(
+      r#"{{"type": "match", "maker_order_id": "{}", "taker_order_id": "{}", "side": "sell",
+          "price": "101.0", "size": "5.0", "trade_id": 1}}"#,
+      id(5), id(9),
+    ))).unwrap();
+    assert_eq!(book.orders_at(Side::Sell, level("101.0", "0").0), None);
+  }
+
+  #[test]
+  fn old_messages_ignored() {
+    let mut book = book();
+    book.apply(&open(5, 6, "sell", "102.0", "1.0")).unwrap();
+    assert_eq!(book.queue_position(id(6)), Err(FullOrderBookError::UnknownOrder{order_id: id(6)}));
+    assert_eq!(book.sequence(), 5);
+  }
+
+  #[test]
+  fn errors() {
+    let mut book = book();
+    assert_eq!(
+      book.apply(&open(6, 1, "buy", "100.0", "1.0")),
+      Err(FullOrderBookError::DuplicateOrder{order_id: id(1)}),
+    );
+    assert_eq!(
+      book.apply(&message(7, &format!(
+        r#"{{"type": "match", "maker_order_id": "{}", "taker_order_id": "{}", "side": "buy",
+            "price": "100.0", "size": "1.0", "trade_id": 1}}"#,
+        id(8), id(9),
+      ))),
+      Err(FullOrderBookError::UnknownOrder{order_id: id(8)}),
+    );
+    assert_eq!(book.sequence(), 5);
+    assert_eq!(book.queue_position(id(1)).unwrap().orders_ahead, 0);
+  }
+}
@@ -1,2 +1,13 @@
+pub mod auth;
+pub mod bootstrap;
+pub mod fees;
 pub mod feed;
+#[cfg(test)]
+pub mod fixtures;
+pub mod full_order_book;
+pub mod order_entry;
 pub mod order_book;
+pub mod products;
+pub mod rest;
+pub mod sequence;
+pub mod simulator;
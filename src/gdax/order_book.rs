@@ -1,6 +1,37 @@
 use gdax::feed::message::*;
 use std::collections::BTreeMap;
+use std::collections::Bound::{Included, Unbounded};
+use std::fmt::{self, Display, Formatter};
 
+type Level = (QuoteCurrencyPrice, BaseCurrencyAmount);
+
+#[derive(Debug, PartialEq)]
+pub enum OrderBookError {
+  ProductMismatch{expected: Product, received: Product},
+  /// The best bid is higher than the best ask
+  Crossed{bid: QuoteCurrencyPrice, ask: QuoteCurrencyPrice},
+  /// The best bid is equal to the best ask
+  Locked{price: QuoteCurrencyPrice},
+  /// An update removed a level that wasn't in the book
+  UnknownLevel{side: Side, price: QuoteCurrencyPrice},
+}
+
+impl Display for OrderBookError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      OrderBookError::ProductMismatch{expected, received} =>
+        write!(f, "update for {} applied to {} order book", received, expected),
+      OrderBookError::Crossed{ref bid, ref ask} =>
+        write!(f, "order book crossed: best bid {:?} > best ask {:?}", bid, ask),
+      OrderBookError::Locked{ref price} =>
+        write!(f, "order book locked: best bid == best ask == {:?}", price),
+      OrderBookError::UnknownLevel{side, ref price} =>
+        write!(f, "update removed unknown {:?} level {:?}", side, price),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct OrderBook {
   product_id: Product,
   bids:       BTreeMap<QuoteCurrencyPrice, BaseCurrencyAmount>,
@@ -9,20 +40,254 @@ pub struct OrderBook {
 
 impl OrderBook {
   pub fn from_snapshot(snapshot: &OrderBookSnapshot) -> OrderBook {
+    let levels = |levels: &Vec<Level>| levels.iter().cloned().filter(|&(_, size)| !size.is_zero()).collect();
     OrderBook {
       product_id: snapshot.product_id,
-      bids:       snapshot.bids.iter().cloned().collect(),
-      asks:       snapshot.asks.iter().cloned().collect(),
+      bids:       levels(&snapshot.bids),
+      asks:       levels(&snapshot.asks),
+    }
+  }
+
+  fn levels_mut(&mut self, side: Side) -> &mut BTreeMap<QuoteCurrencyPrice, BaseCurrencyAmount> {
+    match side {
+      Side::Buy  => &mut self.bids,
+      Side::Sell => &mut self.asks,
     }
   }
 
-  pub fn update(&mut self, update: &OrderBookUpdate) {
-    assert_eq!(self.product_id, update.product_id, "OrderBook/update product ID mismatch: {:?} != {:?}", self.product_id, update.product_id);
-    for change in update.changes.iter().cloned() {
-      match change {
-        (Side::Buy,  price, amount) => self.bids.insert(price, amount),
-        (Side::Sell, price, amount) => self.asks.insert(price, amount),
+  /// Apply an update, removing levels whose size is zero, and then validate
+  /// the book. If the update fails, the book is left unchanged.
+  pub fn update(&mut self, update: &OrderBookUpdate) -> Result<(), OrderBookError> {
+    if self.product_id != update.product_id {
+      return Err(OrderBookError::ProductMismatch{expected: self.product_id, received: update.product_id});
+    }
+
+    // previous size of each level changed, to undo the update if it fails
+    let mut undo = Vec::with_capacity(update.changes.len());
+    let mut result = Ok(());
+
+    for &(side, price, size) in &update.changes {
+      let previous = if size.is_zero() {
+        match self.levels_mut(side).remove(&price) {
+          Some(previous) => Some(previous),
+          None => {
+            result = Err(OrderBookError::UnknownLevel{side, price});
+            break;
+          }
+        }
+      } else {
+        self.levels_mut(side).insert(price, size)
       };
+      undo.push((side, price, previous));
     }
+
+    let result = result.and_then(|()| self.validate());
+
+    if result.is_err() {
+      for (side, price, previous) in undo.into_iter().rev() {
+        let levels = self.levels_mut(side);
+        match previous {
+          Some(size) => levels.insert(price, size),
+          None       => levels.remove(&price),
+        };
+      }
+    }
+
+    result
+  }
+
+  /// Check that the best bid is below the best ask
+  pub fn validate(&self) -> Result<(), OrderBookError> {
+    match (self.best_bid(), self.best_ask()) {
+      (Some((bid, _)), Some((ask, _))) if bid > ask  => Err(OrderBookError::Crossed{bid, ask}),
+      (Some((bid, _)), Some((ask, _))) if bid == ask => Err(OrderBookError::Locked{price: bid}),
+      _                                              => Ok(()),
+    }
+  }
+
+  /// Highest bid price and the size available at it
+  pub fn best_bid(&self) -> Option<Level> {
+    self.bids.iter().next_back().map(|(price, size)| (*price, *size))
+  }
+
+  /// Lowest ask price and the size available at it
+  pub fn best_ask(&self) -> Option<Level> {
+    self.asks.iter().next().map(|(price, size)| (*price, *size))
+  }
+
+  /// Price halfway between the best bid and best ask
+  pub fn mid_price(&self) -> Option<QuoteCurrencyPrice> {
+    match (self.best_bid(), self.best_ask()) {
+      (Some((bid, _)), Some((ask, _))) => Some(bid.midpoint(ask)),
+      _                                => None,
+    }
+  }
+
+  /// Best ask minus best bid, or `None` if either side is empty or the book is crossed
+  pub fn spread(&self) -> Option<QuoteCurrencyPrice> {
+    match (self.best_bid(), self.best_ask()) {
+      (Some((bid, _)), Some((ask, _))) => ask.checked_sub(bid),
+      _                                => None,
+    }
+  }
+
+  /// The best `n` levels on `side`, best first
+  pub fn top(&self, side: Side, n: usize) -> Vec<Level> {
+    let copy = |(price, size): (&QuoteCurrencyPrice, &BaseCurrencyAmount)| (*price, *size);
+    match side {
+      Side::Buy  => self.bids.iter().rev().take(n).map(copy).collect(),
+      Side::Sell => self.asks.iter().take(n).map(copy).collect(),
+    }
+  }
+
+  /// Size on `side` at exactly `price`
+  pub fn size_at(&self, side: Side, price: QuoteCurrencyPrice) -> BaseCurrencyAmount {
+    let levels = match side {
+      Side::Buy  => &self.bids,
+      Side::Sell => &self.asks,
+    };
+    levels.get(&price).cloned().unwrap_or(BaseCurrencyAmount::zero())
+  }
+
+  /// Total size on `side` at `price` or better, which is the size a taker
+  /// could trade against with a limit order at `price`
+  pub fn size_through(&self, side: Side, price: QuoteCurrencyPrice) -> BaseCurrencyAmount {
+    let sizes = match side {
+      Side::Buy  => self.bids.range(price..),
+      Side::Sell => self.asks.range((Unbounded, Included(price))),
+    };
+    sizes.fold(BaseCurrencyAmount::zero(), |total, (_, size)| total + *size)
+  }
+
+  /// Total size on `side` within `band` of the best price on that side
+  pub fn depth_within(&self, side: Side, band: QuoteCurrencyPrice) -> BaseCurrencyAmount {
+    let limit = match side {
+      Side::Buy  => self.best_bid().map(|(bid, _)| bid.checked_sub(band).unwrap_or(QuoteCurrencyPrice::zero())),
+      Side::Sell => self.best_ask().map(|(ask, _)| ask + band),
+    };
+
+    match limit {
+      Some(limit) => self.size_through(side, limit),
+      None        => BaseCurrencyAmount::zero(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json;
+
+  fn price(price: &str) -> QuoteCurrencyPrice {
+    serde_json::from_str(&format!(r#""{}""#, price)).unwrap()
+  }
+
+  fn size(size: &str) -> BaseCurrencyAmount {
+    serde_json::from_str(&format!(r#""{}""#, size)).unwrap()
+  }
+
+  fn update(book: &mut OrderBook, changes: &str) -> Result<(), OrderBookError> {
+    book.update(&serde_json::from_str(&format!(r#"{{
+      "product_id": "BTC-USD",
+      "changes": {}
+    }}"#, changes)).unwrap())
+  }
+
+  fn book() -> OrderBook {
+    OrderBook::from_snapshot(&serde_json::from_str(r#"{
+      "product_id": "BTC-USD",
+      "bids": [["99.0", "3.0"], ["100.0", "1.0"], ["98.5", "5.0"]],
+      "asks": [["101.5", "2.5"], ["101.0", "0.5"], ["103.0", "10.0"]]
+    }"#).unwrap())
+  }
+
+  #[test]
+  fn top_of_book() {
+    let book = book();
+    assert_eq!(book.best_bid(), Some((price("100.0"), size("1.0"))));
+    assert_eq!(book.best_ask(), Some((price("101.0"), size("0.5"))));
+    assert_eq!(book.mid_price(), Some(price("100.5")));
+    assert_eq!(book.spread(), Some(price("1.0")));
+  }
+
+  #[test]
+  fn empty() {
+    let book = OrderBook::from_snapshot(&serde_json::from_str(
+      r#"{"product_id": "BTC-USD", "bids": [], "asks": [["101.0", "1.0"]]}"#
+    ).unwrap());
+    assert_eq!(book.best_bid(), None);
+    assert_eq!(book.mid_price(), None);
+    assert_eq!(book.spread(), None);
+    assert_eq!(book.depth_within(Side::Buy, price("10.0")), size("0"));
+  }
+
+  #[test]
+  fn top() {
+    let book = book();
+    assert_eq!(book.top(Side::Buy, 2), vec![(price("100.0"), size("1.0")), (price("99.0"), size("3.0"))]);
+    assert_eq!(book.top(Side::Sell, 1), vec![(price("101.0"), size("0.5"))]);
+    assert_eq!(book.top(Side::Sell, 10).len(), 3);
+  }
+
+  #[test]
+  fn size_through() {
+    let book = book();
+    assert_eq!(book.size_through(Side::Buy, price("99.0")), size("4.0"));
+    assert_eq!(book.size_through(Side::Buy, price("100.5")), size("0"));
+    assert_eq!(book.size_through(Side::Sell, price("101.5")), size("3.0"));
+    assert_eq!(book.size_through(Side::Sell, price("1000.0")), size("13.0"));
+  }
+
+  #[test]
+  fn depth_within() {
+    let book = book();
+    assert_eq!(book.depth_within(Side::Buy, price("1.0")), size("4.0"));
+    assert_eq!(book.depth_within(Side::Buy, price("1000.0")), size("9.0"));
+    assert_eq!(book.depth_within(Side::Sell, price("0.5")), size("3.0"));
+  }
+
+  #[test]
+  fn zero_size_levels_removed() {
+    let mut book = OrderBook::from_snapshot(&serde_json::from_str(r#"{
+      "product_id": "BTC-USD",
+      "bids": [["100.0", "0.0"], ["99.0", "1.0"]],
+      "asks": [["101.0", "1.0"]]
+    }"#).unwrap());
+    assert_eq!(book.best_bid(), Some((price("99.0"), size("1.0"))));
+
+    update(&mut book, r#"[["buy", "99.0", "0.0"], ["sell", "101.0", "0"], ["sell", "102.0", "2.0"]]"#).unwrap();
+    assert_eq!(book.best_bid(), None);
+    assert_eq!(book.best_ask(), Some((price("102.0"), size("2.0"))));
+  }
+
+  #[test]
+  fn errors() {
+    let mut book = book();
+    assert_eq!(
+      update(&mut book, r#"[["buy", "99.5", "0.0"]]"#),
+      Err(OrderBookError::UnknownLevel{side: Side::Buy, price: price("99.5")}),
+    );
+    assert_eq!(
+      update(&mut book, r#"[["buy", "101.0", "1.0"]]"#),
+      Err(OrderBookError::Locked{price: price("101.0")}),
+    );
+    assert_eq!(
+      update(&mut book, r#"[["buy", "101.25", "1.0"]]"#),
+      Err(OrderBookError::Crossed{bid: price("101.25"), ask: price("101.0")}),
+    );
+    assert_eq!(book, self::book());
+  }
+
+  #[test]
+  fn failed_update_leaves_book_unchanged() {
+    let mut book = book();
+    assert_eq!(
+      update(&mut book, r#"[["buy", "100.0", "2.0"], ["sell", "101.0", "0"], ["buy", "99.5", "0"]]"#),
+      Err(OrderBookError::UnknownLevel{side: Side::Buy, price: price("99.5")}),
+    );
+    assert_eq!(book, self::book());
+
+    update(&mut book, r#"[["sell", "100.5", "1.0"], ["sell", "100.5", "0"]]"#).unwrap();
+    assert_eq!(book, self::book());
   }
 }
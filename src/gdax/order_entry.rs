@@ -0,0 +1,395 @@
+use futures::future;
+use futures::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use uuid::Uuid;
+
+use gdax::feed::message::{BaseCurrencyAmount, IncomingMessage, OrderType, Product, QuoteCurrencyPrice};
+use gdax::products::Products;
+use gdax::rest::{RestClient, RestError};
+use gdax::rest::message::{Fill, NewOrder, TimeInForce};
+use risk::RiskViolation;
+
+pub type OrderFuture<T> = Box<Future<Item=T, Error=OrderError>>;
+
+/// Why an order was rejected before being sent
+#[derive(Debug, PartialEq)]
+pub enum InvalidOrder {
+  UnknownProduct(Product),
+  SizeTooSmall{size: BaseCurrencyAmount, min: BaseCurrencyAmount},
+  SizeTooLarge{size: BaseCurrencyAmount, max: BaseCurrencyAmount},
+  /// A limit order without a price, or a market order with one
+  Price(OrderType),
+  /// The price isn't a whole number of the product's quote increment
+  Increment{price: QuoteCurrencyPrice, increment: QuoteCurrencyPrice},
+  /// Post-only and time in force only apply to limit orders
+  MarketOption,
+  /// A post-only order can't be immediate or cancel or fill or kill
+  PostOnly(TimeInForce),
+}
+
+impl Display for InvalidOrder {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      InvalidOrder::UnknownProduct(product)  => write!(f, "unknown product {}", product),
+      InvalidOrder::SizeTooSmall{size, min}  => write!(f, "size {} is below minimum {}", size, min),
+      InvalidOrder::SizeTooLarge{size, max}  => write!(f, "size {} is above maximum {}", size, max),
+      InvalidOrder::Price(OrderType::Limit)  => write!(f, "limit order without price"),
+      InvalidOrder::Price(OrderType::Market) => write!(f, "market order with price"),
+      InvalidOrder::Increment{price, increment}
+        => write!(f, "price {} is not a multiple of increment {}", price, increment),
+      InvalidOrder::MarketOption             => write!(f, "post-only or time in force on market order"),
+      InvalidOrder::PostOnly(time_in_force)  => write!(f, "post-only order with time in force {:?}", time_in_force),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum OrderError {
+  Invalid(InvalidOrder),
+  Rest(RestError),
+  /// The order or cancel was refused by a risk check
+  Risk(RiskViolation),
+  /// The order isn't open, or was never placed
+  UnknownOrder(Uuid),
+}
+
+impl Display for OrderError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      OrderError::Invalid(ref invalid) => write!(f, "invalid order: {}", invalid),
+      OrderError::Rest(ref error)      => write!(f, "REST error: {}", error),
+      OrderError::Risk(ref violation)  => write!(f, "risk check failed: {}", violation),
+      OrderError::UnknownOrder(id)     => write!(f, "unknown order {}", id),
+    }
+  }
+}
+
+impl Error for OrderError {
+  fn description(&self) -> &str {
+    match *self {
+      OrderError::Invalid(_)      => "invalid order",
+      OrderError::Rest(_)         => "REST error",
+      OrderError::Risk(_)         => "risk check failed",
+      OrderError::UnknownOrder(_) => "unknown order",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      OrderError::Rest(ref error) => Some(error),
+      OrderError::Risk(ref error) => Some(error),
+      OrderError::Invalid(_)      |
+      OrderError::UnknownOrder(_) => None,
+    }
+  }
+}
+
+/// An order accepted by an `OrderEntry`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PlacedOrder {
+  /// Identifier assigned by the exchange
+  pub id:         Uuid,
+  /// Identifier appearing in the `received` feed message for the order
+  pub client_oid: Uuid,
+}
+
+/// Something that happened to a placed order
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderEvent {
+  Fill(Fill),
+  /// The order is no longer open, because it filled or was canceled or
+  /// rejected
+  Done(Uuid),
+}
+
+/// Places and cancels orders, on the exchange or otherwise
+pub trait OrderEntry {
+  /// Place `order`, generating a `client_oid` if it doesn't have one
+  fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder>;
+
+  fn cancel(&mut self, id: Uuid) -> OrderFuture<()>;
+
+  /// Cancel open orders for `product`, or for all products, resolving to the
+  /// identifiers of the canceled orders
+  fn cancel_all(&mut self, product: Option<Product>) -> OrderFuture<Vec<Uuid>>;
+
+  /// Update from a feed message, e.g. to simulate fills against it
+  fn observe(&mut self, _message: &IncomingMessage) {}
+
+  /// Discard market state after feed messages may have been missed
+  fn discontinuity(&mut self) {}
+
+  /// Events of placed orders since the last call, for order entries that
+  /// don't report them on the feed
+  fn events(&mut self) -> Vec<OrderEvent> {
+    vec![]
+  }
+}
+
+impl<E: OrderEntry + ?Sized> OrderEntry for Box<E> {
+  fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder> {
+    (**self).place(order)
+  }
+
+  fn cancel(&mut self, id: Uuid) -> OrderFuture<()> {
+    (**self).cancel(id)
+  }
+
+  fn cancel_all(&mut self, product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+    (**self).cancel_all(product)
+  }
+
+  fn observe(&mut self, message: &IncomingMessage) {
+    (**self).observe(message)
+  }
+
+  fn discontinuity(&mut self) {
+    (**self).discontinuity()
+  }
+
+  fn events(&mut self) -> Vec<OrderEvent> {
+    (**self).events()
+  }
+}
+
+/// Check `order` against the size limits and price increment of its product
+pub fn validate(products: &Products, order: &NewOrder) -> Result<(), InvalidOrder> {
+  let info = products.get(order.product_id).ok_or(InvalidOrder::UnknownProduct(order.product_id))?;
+
+  if order.size < info.base_min_size {
+    return Err(InvalidOrder::SizeTooSmall{size: order.size, min: info.base_min_size});
+  }
+  if order.size > info.base_max_size {
+    return Err(InvalidOrder::SizeTooLarge{size: order.size, max: info.base_max_size});
+  }
+
+  match (order.order_type, order.price) {
+    (OrderType::Limit, Some(price)) => if !price.is_multiple_of(info.quote_increment) {
+      return Err(InvalidOrder::Increment{price, increment: info.quote_increment});
+    },
+    (OrderType::Market, None) => if order.post_only || order.time_in_force.is_some() {
+      return Err(InvalidOrder::MarketOption);
+    },
+    (order_type, _) => return Err(InvalidOrder::Price(order_type)),
+  }
+
+  match order.time_in_force {
+    Some(time_in_force @ TimeInForce::IOC) |
+    Some(time_in_force @ TimeInForce::FOK) if order.post_only => Err(InvalidOrder::PostOnly(time_in_force)),
+    _ => Ok(()),
+  }
+}
+
+/// Give `order` a fresh `client_oid` unless it already has one
+fn with_client_oid(mut order: NewOrder) -> (NewOrder, Uuid) {
+  let client_oid = *order.client_oid.get_or_insert_with(Uuid::new_v4);
+  (order, client_oid)
+}
+
+/// Validates orders and sends them to the exchange
+pub struct RestOrderEntry {
+  rest:     RestClient,
+  products: Products,
+}
+
+impl RestOrderEntry {
+  pub fn new(rest: RestClient, products: Products) -> RestOrderEntry {
+    RestOrderEntry{rest, products}
+  }
+}
+
+impl OrderEntry for RestOrderEntry {
+  fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder> {
+    if let Err(invalid) = validate(&self.products, &order) {
+      return Box::new(future::err(OrderError::Invalid(invalid)));
+    }
+    let (order, client_oid) = with_client_oid(order);
+    info!("placing {:?}", order);
+    Box::new(self.rest.place_order(&order)
+      .map(move |placed| PlacedOrder{id: placed.id, client_oid})
+      .map_err(OrderError::Rest))
+  }
+
+  fn cancel(&mut self, id: Uuid) -> OrderFuture<()> {
+    info!("canceling order {}", id);
+    Box::new(self.rest.cancel_order(id).map_err(OrderError::Rest))
+  }
+
+  fn cancel_all(&mut self, product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+    info!("canceling all orders{}", product.map(|product| format!(" for {}", product)).unwrap_or_default());
+    Box::new(self.rest.cancel_all(product).map_err(OrderError::Rest))
+  }
+}
+
+/// Validates and logs orders without sending them, keeping track of the
+/// orders it would have placed so that they can be canceled
+pub struct DryRunOrderEntry {
+  products: Products,
+  open:     BTreeMap<Uuid, NewOrder>,
+}
+
+impl DryRunOrderEntry {
+  pub fn new(products: Products) -> DryRunOrderEntry {
+    DryRunOrderEntry {
+      open: BTreeMap::new(),
+      products,
+    }
+  }
+
+  /// Orders that have been placed and not canceled
+  pub fn open_orders(&self) -> &BTreeMap<Uuid, NewOrder> {
+    &self.open
+  }
+}
+
+impl OrderEntry for DryRunOrderEntry {
+  fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder> {
+    if let Err(invalid) = validate(&self.products, &order) {
+      warn!("dry run: rejecting {:?}: {}", order, invalid);
+      return Box::new(future::err(OrderError::Invalid(invalid)));
+    }
+    let (order, client_oid) = with_client_oid(order);
+    let id = Uuid::new_v4();
+    info!("dry run: placing {:?} as order {}", order, id);
+    self.open.insert(id, order);
+    Box::new(future::ok(PlacedOrder{id, client_oid}))
+  }
+
+  /// Orders placed elsewhere are unknown to a dry run, so canceling them
+  /// succeeds as long as the identifier is well formed
+  fn cancel(&mut self, id: Uuid) -> OrderFuture<()> {
+    info!("dry run: canceling order {}", id);
+    self.open.remove(&id);
+    Box::new(future::ok(()))
+  }
+
+  fn cancel_all(&mut self, product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+    let canceled = self.open.iter()
+      .filter(|&(_, order)| product.map(|product| order.product_id == product).unwrap_or(true))
+      .map(|(id, _)| *id)
+      .collect::<Vec<Uuid>>();
+    for id in &canceled {
+      self.open.remove(id);
+    }
+    info!("dry run: canceling {} orders", canceled.len());
+    Box::new(future::ok(canceled))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use gdax::auth::Credentials;
+  use gdax::feed::message::Side;
+  use gdax::fixtures::products;
+  use gdax::rest::RestBuilder;
+  use gdax::rest::mock::{MockResponse, MockRest};
+  use serde_json;
+  use tokio_core::reactor::Core;
+
+  fn limit(price: &str, size: &str) -> NewOrder {
+    NewOrder::limit("BTC-USD".parse().unwrap(), Side::Buy, price.parse().unwrap(), size.parse().unwrap())
+  }
+
+  #[test]
+  fn validation() {
+    let products = products();
+    let market = NewOrder::market("BTC-USD".parse().unwrap(), Side::Sell, "1.0".parse().unwrap());
+
+    assert_eq!(validate(&products, &limit("100.01", "0.01")), Ok(()));
+    assert_eq!(validate(&products, &limit("100.01", "0.01").post_only()), Ok(()));
+    assert_eq!(validate(&products, &market), Ok(()));
+
+    assert_eq!(
+      validate(&products, &limit("100.001", "1.0")),
+      Err(InvalidOrder::Increment{price: "100.001".parse().unwrap(), increment: "0.01".parse().unwrap()}),
+    );
+    assert_eq!(
+      validate(&products, &limit("100.00", "0.001")),
+      Err(InvalidOrder::SizeTooSmall{size: "0.001".parse().unwrap(), min: "0.01".parse().unwrap()}),
+    );
+    assert_eq!(
+      validate(&products, &limit("100.00", "20000")),
+      Err(InvalidOrder::SizeTooLarge{size: "20000".parse().unwrap(), max: "10000".parse().unwrap()}),
+    );
+    assert_eq!(
+      validate(&products, &limit("100.00", "1.0").post_only().time_in_force(TimeInForce::IOC)),
+      Err(InvalidOrder::PostOnly(TimeInForce::IOC)),
+    );
+    assert_eq!(validate(&products, &market.clone().post_only()), Err(InvalidOrder::MarketOption));
+
+    let mut unknown = market;
+    unknown.product_id = "ETH-USD".parse().unwrap();
+    assert_eq!(validate(&products, &unknown), Err(InvalidOrder::UnknownProduct(unknown.product_id)));
+  }
+
+  #[test]
+  fn dry_run() {
+    let mut entry = DryRunOrderEntry::new(products());
+
+    let client_oid = Uuid::nil();
+    let first = entry.place(limit("100.00", "1.0").client_oid(client_oid)).wait().unwrap();
+    assert_eq!(first.client_oid, client_oid);
+    let second = entry.place(limit("100.00", "1.0")).wait().unwrap();
+    assert!(!second.client_oid.is_nil());
+    assert!(entry.place(limit("100.001", "1.0")).wait().is_err());
+    assert_eq!(entry.open_orders().len(), 2);
+
+    entry.cancel(first.id).wait().unwrap();
+    assert_eq!(entry.cancel_all(Some("ETH-USD".parse().unwrap())).wait().unwrap(), vec![]);
+    assert_eq!(entry.cancel_all(None).wait().unwrap(), vec![second.id]);
+    assert!(entry.open_orders().is_empty());
+  }
+
+  #[test]
+  fn rest() {
+    let server = MockRest::new()
+      .route("POST", "/orders", MockResponse::json(r#"{
+        "id":             "d50ec984-77a8-460a-b958-66f114b0de9b",
+        "product_id":     "BTC-USD",
+        "side":           "buy",
+        "type":           "limit",
+        "price":          "100.00",
+        "size":           "1.0",
+        "post_only":      true,
+        "created_at":     "2017-10-01T00:00:00Z",
+        "fill_fees":      "0.0",
+        "filled_size":    "0.0",
+        "executed_value": "0.0",
+        "status":         "pending",
+        "settled":        false
+      }"#))
+      .route("DELETE", "/orders", MockResponse::json(r#"["d50ec984-77a8-460a-b958-66f114b0de9b"]"#))
+      .bind()
+      .unwrap();
+    let mut core = Core::new().unwrap();
+    let client = RestBuilder::new()
+      .url(&server.url())
+      .credentials(Credentials::new("key", "c2VjcmV0", "passphrase").unwrap())
+      .build(&core.handle())
+      .unwrap();
+    let mut entry = RestOrderEntry::new(client, products());
+
+    match core.run(entry.place(limit("100.001", "1.0"))) {
+      Err(OrderError::Invalid(InvalidOrder::Increment{..})) => {}
+      other => panic!("expected invalid order: {:?}", other),
+    }
+    assert!(server.requests().is_empty());
+
+    let order = limit("100.00", "1.0").post_only().time_in_force(TimeInForce::GTC);
+    let placed = core.run(entry.place(order)).unwrap();
+    assert_eq!(placed.id.to_string(), "d50ec984-77a8-460a-b958-66f114b0de9b");
+
+    let canceled = core.run(entry.cancel_all(Some("BTC-USD".parse().unwrap()))).unwrap();
+    assert_eq!(canceled, vec![placed.id]);
+
+    let requests = server.requests();
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["client_oid"], placed.client_oid.to_string().as_str());
+    assert_eq!(body["post_only"], true);
+    assert_eq!(body["time_in_force"], "GTC");
+    assert_eq!(requests[1].path, "/orders?product_id=BTC-USD");
+  }
+}
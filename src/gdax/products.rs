@@ -0,0 +1,152 @@
+use serde_json;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use gdax::feed::message::{BaseCurrencyAmount, Currency, Product, QuoteCurrencyPrice};
+
+/// An entry in the exchange's products listing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProductInfo {
+  pub id:              Product,
+  pub base_currency:   Currency,
+  pub quote_currency:  Currency,
+  pub base_min_size:   BaseCurrencyAmount,
+  pub base_max_size:   BaseCurrencyAmount,
+  pub quote_increment: QuoteCurrencyPrice,
+}
+
+impl ProductInfo {
+  /// Smallest price increment of orders
+  pub fn tick_size(&self) -> QuoteCurrencyPrice {
+    self.quote_increment
+  }
+}
+
+#[derive(Debug)]
+pub enum ProductsError {
+  Deserialization(serde_json::Error),
+  Inconsistent(Product),
+  Io(io::Error),
+}
+
+impl Display for ProductsError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      ProductsError::Deserialization(ref error) => write!(f, "failed to deserialize products: {}", error),
+      ProductsError::Inconsistent(product)      => write!(f, "product {} has mismatched currencies", product),
+      ProductsError::Io(ref error)              => write!(f, "I/O error: {}", error),
+    }
+  }
+}
+
+impl Error for ProductsError {
+  fn description(&self) -> &str {
+    match *self {
+      ProductsError::Deserialization(_) => "failed to deserialize products",
+      ProductsError::Inconsistent(_)    => "product has mismatched currencies",
+      ProductsError::Io(_)              => "I/O error",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      ProductsError::Deserialization(ref error) => Some(error),
+      ProductsError::Io(ref error)              => Some(error),
+      ProductsError::Inconsistent(_)            => None,
+    }
+  }
+}
+
+/// Products available for trading, and their metadata
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Products {
+  products: BTreeMap<Product, ProductInfo>,
+}
+
+impl Products {
+  /// Parse a products listing, as returned by the exchange's `/products`
+  /// endpoint
+  pub fn from_listing(listing: &[u8]) -> Result<Products, ProductsError> {
+    Products::new(serde_json::from_slice(listing).map_err(ProductsError::Deserialization)?)
+  }
+
+  /// Index the entries of a products listing
+  pub fn new(infos: Vec<ProductInfo>) -> Result<Products, ProductsError> {
+    let mut products = BTreeMap::new();
+    for info in infos {
+      if info.id.base != info.base_currency || info.id.quote != info.quote_currency {
+        return Err(ProductsError::Inconsistent(info.id));
+      }
+      products.insert(info.id, info);
+    }
+
+    Ok(Products{products})
+  }
+
+  /// Load a products listing from the JSON file at `path`
+  pub fn load(path: &Path) -> Result<Products, ProductsError> {
+    let mut listing = Vec::new();
+    File::open(path)
+      .and_then(|mut file| file.read_to_end(&mut listing))
+      .map_err(ProductsError::Io)?;
+    Products::from_listing(&listing)
+  }
+
+  pub fn get(&self, product: Product) -> Option<&ProductInfo> {
+    self.products.get(&product)
+  }
+
+  /// Identifiers of all listed products, in order
+  pub fn ids(&self) -> Vec<Product> {
+    self.products.keys().cloned().collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LISTING: &'static str = r#"[
+    {
+      "id":              "BTC-USD",
+      "base_currency":   "BTC",
+      "quote_currency":  "USD",
+      "base_min_size":   "0.01",
+      "base_max_size":   "10000.00",
+      "quote_increment": "0.01",
+      "display_name":    "BTC/USD"
+    },
+    {
+      "id":              "ETH-BTC",
+      "base_currency":   "ETH",
+      "quote_currency":  "BTC",
+      "base_min_size":   "0.01",
+      "base_max_size":   "1000000.00",
+      "quote_increment": "0.00001"
+    }
+  ]"#;
+
+  #[test]
+  fn listing() {
+    let products = Products::from_listing(LISTING.as_bytes()).unwrap();
+    let eth_btc = "ETH-BTC".parse().unwrap();
+    assert_eq!(products.ids(), vec!["BTC-USD".parse().unwrap(), eth_btc]);
+    let info = products.get(eth_btc).unwrap();
+    assert_eq!(info.tick_size(), serde_json::from_str(r#""0.00001""#).unwrap());
+    assert_eq!(info.base_min_size, serde_json::from_str(r#""0.01""#).unwrap());
+    assert!(products.get("LTC-USD".parse().unwrap()).is_none());
+  }
+
+  #[test]
+  fn inconsistent() {
+    let listing = LISTING.replace(r#""base_currency":   "ETH""#, r#""base_currency":   "LTC""#);
+    match Products::from_listing(listing.as_bytes()) {
+      Err(ProductsError::Inconsistent(product)) => assert_eq!(product.to_string(), "ETH-BTC"),
+      other => panic!("expected inconsistent product: {:?}", other),
+    }
+  }
+}
@@ -0,0 +1,247 @@
+use serde::{de, Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Number, Value};
+use std::ops::Not;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use decimal::Decimal;
+use gdax::feed::message::{
+  BaseCurrencyAmount, Currency, DateTime, OrderType, Product, QuoteCurrencyAmount, QuoteCurrencyPrice,
+  Side,
+};
+
+/// Aggregation of a `book` request
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BookLevel {
+  /// Only the best bid and ask
+  Best,
+  /// The best 50 bids and asks
+  Top50,
+}
+
+impl BookLevel {
+  pub fn number(self) -> u8 {
+    match self {
+      BookLevel::Best  => 1,
+      BookLevel::Top50 => 2,
+    }
+  }
+}
+
+/// Price level of a level 1 or 2 order book, with the number of orders at it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregatedLevel(pub QuoteCurrencyPrice, pub BaseCurrencyAmount, pub u64);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregatedBook {
+  pub sequence: u64,
+  pub bids:     Vec<AggregatedLevel>,
+  pub asks:     Vec<AggregatedLevel>,
+}
+
+/// An order in a level 3 order book
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BookOrder(pub QuoteCurrencyPrice, pub BaseCurrencyAmount, pub Uuid);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FullBook {
+  pub sequence: u64,
+  pub bids:     Vec<BookOrder>,
+  pub asks:     Vec<BookOrder>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trade {
+  pub time:     DateTime,
+  pub trade_id: u64,
+  pub price:    QuoteCurrencyPrice,
+  pub size:     BaseCurrencyAmount,
+  /// Side of the maker
+  pub side:     Side,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candle {
+  /// Start of the candle's interval, in seconds since the epoch
+  pub time:   i64,
+  pub low:    QuoteCurrencyPrice,
+  pub high:   QuoteCurrencyPrice,
+  pub open:   QuoteCurrencyPrice,
+  pub close:  QuoteCurrencyPrice,
+  pub volume: BaseCurrencyAmount,
+}
+
+/// Candles are arrays of JSON numbers, which are converted to decimals
+/// through their shortest string representation
+impl<'de> Deserialize<'de> for Candle {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Candle, D::Error> {
+    fn decimal<E: de::Error, T: DeserializeOwned>(number: Number) -> Result<T, E> {
+      serde_json::from_value(Value::String(number.to_string())).map_err(E::custom)
+    }
+
+    let (time, low, high, open, close, volume) =
+      <(i64, Number, Number, Number, Number, Number)>::deserialize(deserializer)?;
+
+    Ok(Candle {
+      time,
+      low:    decimal(low)?,
+      high:   decimal(high)?,
+      open:   decimal(open)?,
+      close:  decimal(close)?,
+      volume: decimal(volume)?,
+    })
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Account {
+  pub id:         Uuid,
+  pub profile_id: Uuid,
+  pub currency:   Currency,
+  pub balance:    Decimal,
+  pub available:  Decimal,
+  pub hold:       Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+  Received,
+  Open,
+  Pending,
+  Active,
+  Done,
+  Rejected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Order {
+  pub id:             Uuid,
+  pub product_id:     Product,
+  pub side:           Side,
+  #[serde(rename = "type")]
+  pub order_type:     OrderType,
+  pub price:          Option<QuoteCurrencyPrice>,
+  pub size:           Option<BaseCurrencyAmount>,
+  pub funds:          Option<QuoteCurrencyAmount>,
+  #[serde(default)]
+  pub post_only:      bool,
+  pub created_at:     DateTime,
+  pub fill_fees:      QuoteCurrencyAmount,
+  pub filled_size:    BaseCurrencyAmount,
+  pub executed_value: QuoteCurrencyAmount,
+  pub status:         OrderStatus,
+  pub settled:        bool,
+}
+
+/// How long a limit order stays on the book
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimeInForce {
+  /// Good till canceled
+  GTC,
+  /// Immediate or cancel, any unfilled size is canceled
+  IOC,
+  /// Fill or kill, canceled unless it can be filled completely
+  FOK,
+}
+
+impl FromStr for TimeInForce {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<TimeInForce, String> {
+    match s {
+      "GTC" => Ok(TimeInForce::GTC),
+      "IOC" => Ok(TimeInForce::IOC),
+      "FOK" => Ok(TimeInForce::FOK),
+      _     => Err(format!("unknown time in force, expected GTC, IOC, or FOK: {}", s)),
+    }
+  }
+}
+
+/// An order to place
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NewOrder {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_oid:    Option<Uuid>,
+  pub product_id:    Product,
+  pub side:          Side,
+  #[serde(rename = "type")]
+  pub order_type:    OrderType,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub price:         Option<QuoteCurrencyPrice>,
+  pub size:          BaseCurrencyAmount,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub time_in_force: Option<TimeInForce>,
+  #[serde(skip_serializing_if = "Not::not")]
+  pub post_only:     bool,
+}
+
+impl NewOrder {
+  pub fn limit(product_id: Product, side: Side, price: QuoteCurrencyPrice, size: BaseCurrencyAmount) -> NewOrder {
+    NewOrder {
+      client_oid:    None,
+      order_type:    OrderType::Limit,
+      price:         Some(price),
+      time_in_force: None,
+      post_only:     false,
+      product_id, side, size,
+    }
+  }
+
+  pub fn market(product_id: Product, side: Side, size: BaseCurrencyAmount) -> NewOrder {
+    NewOrder {
+      client_oid:    None,
+      order_type:    OrderType::Market,
+      price:         None,
+      time_in_force: None,
+      post_only:     false,
+      product_id, side, size,
+    }
+  }
+
+  /// Identify the order in feed messages with `client_oid`
+  pub fn client_oid(mut self, client_oid: Uuid) -> NewOrder {
+    self.client_oid = Some(client_oid);
+    self
+  }
+
+  pub fn time_in_force(mut self, time_in_force: TimeInForce) -> NewOrder {
+    self.time_in_force = Some(time_in_force);
+    self
+  }
+
+  /// Reject the order instead of letting it take liquidity
+  pub fn post_only(mut self) -> NewOrder {
+    self.post_only = true;
+    self
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Liquidity {
+  #[serde(rename = "M")]
+  Maker,
+  #[serde(rename = "T")]
+  Taker,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fill {
+  pub trade_id:   u64,
+  pub product_id: Product,
+  pub order_id:   Uuid,
+  pub price:      QuoteCurrencyPrice,
+  pub size:       BaseCurrencyAmount,
+  pub side:       Side,
+  pub liquidity:  Liquidity,
+  pub fee:        QuoteCurrencyAmount,
+  pub created_at: DateTime,
+  pub settled:    bool,
+}
+
+/// Body of an error response
+#[derive(Deserialize, Debug)]
+pub struct ErrorMessage {
+  pub message: String,
+}
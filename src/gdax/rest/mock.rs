@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A request received by a `MockRest` server
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+  pub method:  String,
+  /// Path and query
+  pub path:    String,
+  pub headers: Vec<(String, String)>,
+  pub body:    String,
+}
+
+impl MockRequest {
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers.iter()
+      .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+      .map(|&(_, ref value)| value.as_str())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+  status:  u16,
+  headers: Vec<(String, String)>,
+  body:    String,
+}
+
+impl MockResponse {
+  /// A `200 OK` response with JSON `body`
+  pub fn json(body: &str) -> MockResponse {
+    MockResponse {
+      status:  200,
+      headers: vec![],
+      body:    body.to_string(),
+    }
+  }
+
+  pub fn status(mut self, status: u16) -> MockResponse {
+    self.status = status;
+    self
+  }
+
+  pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+}
+
+/// A local HTTP server that stands in for the GDAX REST API, answering each
+/// route with a sequence of canned responses
+pub struct MockRest {
+  routes: BTreeMap<(String, String), Vec<MockResponse>>,
+}
+
+impl MockRest {
+  pub fn new() -> MockRest {
+    MockRest {
+      routes: BTreeMap::new(),
+    }
+  }
+
+  /// Answer the next request for `path`, ignoring its query, with `response`.
+  /// The last response for a route is repeated once the others are used.
+  pub fn route(mut self, method: &str, path: &str, response: MockResponse) -> MockRest {
+    self.routes.entry((method.to_string(), path.to_string()))
+      .or_insert_with(Vec::new)
+      .push(response);
+    self
+  }
+
+  /// Listen on an unused local port, serving requests on a background thread
+  pub fn bind(self) -> io::Result<MockRestServer> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let requests = Arc::new(Mutex::new(vec![]));
+
+    let mut routes = self.routes;
+    let log = requests.clone();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let result = stream.and_then(|stream| serve(stream, &mut routes, &log));
+        if let Err(error) = result {
+          warn!("mock REST API: connection failed: {}", error);
+        }
+      }
+    });
+
+    Ok(MockRestServer{url, requests})
+  }
+}
+
+fn serve(
+  stream:   TcpStream,
+  routes:   &mut BTreeMap<(String, String), Vec<MockResponse>>,
+  requests: &Mutex<Vec<MockRequest>>,
+) -> io::Result<()> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+
+  let mut line = String::new();
+  reader.read_line(&mut line)?;
+  let mut words = line.split_whitespace();
+  let method = words.next().unwrap_or_default().to_string();
+  let path = words.next().unwrap_or_default().to_string();
+
+  let mut headers = vec![];
+  loop {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let line = line.trim_right();
+    if line.is_empty() {
+      break;
+    }
+    if let Some(colon) = line.find(':') {
+      headers.push((line[..colon].trim().to_string(), line[colon + 1..].trim().to_string()));
+    }
+  }
+
+  let mut request = MockRequest{method, path, headers, body: String::new()};
+  let length = request.header("Content-Length").and_then(|length| length.parse().ok()).unwrap_or(0);
+  reader.take(length).read_to_string(&mut request.body)?;
+
+  let route = (request.method.clone(), request.path.split('?').next().unwrap().to_string());
+  let response = match routes.get_mut(&route) {
+    Some(ref mut responses) if responses.len() > 1 => responses.remove(0),
+    Some(responses) => responses[0].clone(),
+    None => MockResponse::json(r#"{"message": "NotFound"}"#).status(404),
+  };
+  requests.lock().unwrap().push(request);
+
+  let mut stream = stream;
+  write!(stream, "HTTP/1.1 {} Mock\r\n", response.status)?;
+  for &(ref name, ref value) in &response.headers {
+    write!(stream, "{}: {}\r\n", name, value)?;
+  }
+  write!(stream, "Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+         response.body.len(), response.body)?;
+  stream.flush()
+}
+
+/// A bound `MockRest`
+pub struct MockRestServer {
+  url:      String,
+  requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockRestServer {
+  /// The `http://` base URL to send requests to
+  pub fn url(&self) -> String {
+    self.url.clone()
+  }
+
+  /// Requests received so far
+  pub fn requests(&self) -> Vec<MockRequest> {
+    self.requests.lock().unwrap().clone()
+  }
+}
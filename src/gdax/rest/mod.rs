@@ -0,0 +1,622 @@
+use chrono::Utc;
+use futures::future::{self, Loop};
+use futures::prelude::*;
+use futures::stream;
+use hyper::{self, Chunk, Client, Headers, Method, Request, StatusCode, Uri};
+use hyper::client::HttpConnector;
+use hyper::error::UriError;
+use hyper::header::{ContentLength, ContentType, UserAgent};
+use hyper_tls::{self, HttpsConnector};
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+use uuid::Uuid;
+
+#[cfg(test)]
+pub mod mock;
+pub mod message;
+
+use self::message::*;
+use gdax::auth::Credentials;
+use gdax::feed::message::{DateTime, Product};
+use gdax::products::ProductInfo;
+
+const LIVE_URL: &'static str = "https://api.gdax.com";
+const SANDBOX_URL: &'static str = "https://api-public.sandbox.gdax.com";
+
+const USER_AGENT: &'static str = concat!("whim/", env!("CARGO_PKG_VERSION"));
+
+pub type RestFuture<T> = Box<Future<Item=T, Error=RestError>>;
+
+#[derive(Debug)]
+pub enum RestError {
+  Api{status: StatusCode, message: String},
+  Deserialization(serde_json::Error),
+  Http(hyper::Error),
+  RateLimited,
+  Serialization(serde_json::Error),
+  Timer(io::Error),
+  Tls(hyper_tls::Error),
+  Unauthenticated,
+  Uri(UriError),
+}
+
+impl Display for RestError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      RestError::Api{status, ref message}   => write!(f, "API error: {}: {}", status, message),
+      RestError::Deserialization(ref error) => write!(f, "failed to deserialize response: {}", error),
+      RestError::Http(ref error)            => write!(f, "HTTP error: {}", error),
+      RestError::RateLimited                => write!(f, "rate limited, and out of retries"),
+      RestError::Serialization(ref error)   => write!(f, "failed to serialize request: {}", error),
+      RestError::Timer(ref error)           => write!(f, "timer error: {}", error),
+      RestError::Tls(ref error)             => write!(f, "TLS error: {}", error),
+      RestError::Unauthenticated            => write!(f, "request requires credentials"),
+      RestError::Uri(ref error)             => write!(f, "invalid URI: {}", error),
+    }
+  }
+}
+
+impl Error for RestError {
+  fn description(&self) -> &str {
+    match *self {
+      RestError::Api{..}            => "API error",
+      RestError::Deserialization(_) => "failed to deserialize response",
+      RestError::Http(_)            => "HTTP error",
+      RestError::RateLimited        => "rate limited, and out of retries",
+      RestError::Serialization(_)   => "failed to serialize request",
+      RestError::Timer(_)           => "timer error",
+      RestError::Tls(_)             => "TLS error",
+      RestError::Unauthenticated    => "request requires credentials",
+      RestError::Uri(_)             => "invalid URI",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      RestError::Deserialization(ref error) => Some(error),
+      RestError::Http(ref error)            => Some(error),
+      RestError::Serialization(ref error)   => Some(error),
+      RestError::Timer(ref error)           => Some(error),
+      RestError::Tls(ref error)             => Some(error),
+      RestError::Uri(ref error)             => Some(error),
+      RestError::Api{..}                    |
+      RestError::RateLimited                |
+      RestError::Unauthenticated            => None,
+    }
+  }
+}
+
+/// Position in a paginated listing, which is newest first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cursor {
+  before: Option<String>,
+  after:  Option<String>,
+  limit:  Option<u32>,
+}
+
+impl Cursor {
+  /// The newest page
+  pub fn new() -> Cursor {
+    Cursor::default()
+  }
+
+  /// Items older than `after`
+  pub fn after(mut self, after: String) -> Cursor {
+    self.after = Some(after);
+    self
+  }
+
+  /// Items newer than `before`
+  pub fn before(mut self, before: String) -> Cursor {
+    self.before = Some(before);
+    self
+  }
+
+  /// At most `limit` items per page
+  pub fn limit(mut self, limit: u32) -> Cursor {
+    self.limit = Some(limit);
+    self
+  }
+
+  fn query(&self) -> Vec<String> {
+    let mut query = vec![];
+    if let Some(ref before) = self.before {
+      query.push(format!("before={}", before));
+    }
+    if let Some(ref after) = self.after {
+      query.push(format!("after={}", after));
+    }
+    if let Some(limit) = self.limit {
+      query.push(format!("limit={}", limit));
+    }
+    query
+  }
+}
+
+/// A page of a listing, with the `CB-BEFORE` and `CB-AFTER` cursor headers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  before:    Option<String>,
+  after:     Option<String>,
+  limit:     Option<u32>,
+}
+
+impl<T> Page<T> {
+  /// Cursor for the page of older items, unless this page was the last
+  pub fn next(&self) -> Option<Cursor> {
+    if self.items.is_empty() {
+      return None;
+    }
+    self.after.clone().map(|after| Cursor{after: Some(after), before: None, limit: self.limit})
+  }
+
+  /// Cursor for the page of newer items
+  pub fn previous(&self) -> Option<Cursor> {
+    self.before.clone().map(|before| Cursor{before: Some(before), after: None, limit: self.limit})
+  }
+}
+
+/// Every item of a listing from `cursor` onwards, fetching older pages with
+/// `fetch` as needed
+pub fn paginate<T, F>(cursor: Cursor, mut fetch: F) -> Box<Stream<Item=T, Error=RestError>>
+  where T: 'static,
+        F: FnMut(Cursor) -> RestFuture<Page<T>> + 'static,
+{
+  let pages = stream::unfold(Some(cursor), move |cursor| {
+    cursor.map(|cursor| fetch(cursor).map(|page| {
+      let next = page.next();
+      (page.items, next)
+    }))
+  });
+  Box::new(pages.map(stream::iter_ok).flatten())
+}
+
+#[derive(Clone)]
+pub struct RestBuilder {
+  sandbox:     bool,
+  url:         Option<String>,
+  credentials: Option<Credentials>,
+  max_retries: u32,
+  retry_delay: Duration,
+}
+
+impl RestBuilder {
+  pub fn new() -> RestBuilder {
+    RestBuilder {
+      sandbox:     false,
+      url:         None,
+      credentials: None,
+      max_retries: 5,
+      retry_delay: Duration::from_millis(250),
+    }
+  }
+
+  pub fn sandbox(mut self, sandbox: bool) -> RestBuilder {
+    self.sandbox = sandbox;
+    self
+  }
+
+  /// Send requests to `url`, which may be `http` or `https`, instead of GDAX.
+  /// Overrides `sandbox`.
+  pub fn url(mut self, url: &str) -> RestBuilder {
+    self.url = Some(url.trim_right_matches('/').to_string());
+    self
+  }
+
+  /// Sign requests with `credentials`, which is required for private
+  /// endpoints
+  pub fn credentials(mut self, credentials: Credentials) -> RestBuilder {
+    self.credentials = Some(credentials);
+    self
+  }
+
+  /// Retry rate limited requests up to `max_retries` times, waiting
+  /// `retry_delay` before the first retry and doubling it after each
+  pub fn retries(mut self, max_retries: u32, retry_delay: Duration) -> RestBuilder {
+    self.max_retries = max_retries;
+    self.retry_delay = retry_delay;
+    self
+  }
+
+  pub fn build(self, handle: &Handle) -> Result<RestClient, RestError> {
+    let url = match self.url {
+      Some(url) => url,
+      None      => (if self.sandbox { SANDBOX_URL } else { LIVE_URL }).to_string(),
+    };
+
+    let connection = if url.starts_with("http:") {
+      Connection::Http(Client::new(handle))
+    } else {
+      let connector = HttpsConnector::new(1, handle).map_err(RestError::Tls)?;
+      Connection::Https(Client::configure().connector(connector).build(handle))
+    };
+
+    Ok(RestClient {
+      url,
+      connection,
+      credentials: self.credentials,
+      max_retries: self.max_retries,
+      retry_delay: self.retry_delay,
+      handle:      handle.clone(),
+    })
+  }
+}
+
+#[derive(Clone)]
+enum Connection {
+  Http(Client<HttpConnector>),
+  Https(Client<HttpsConnector<HttpConnector>>),
+}
+
+/// Client for the GDAX REST API. Clones share a connection pool.
+#[derive(Clone)]
+pub struct RestClient {
+  url:         String,
+  connection:  Connection,
+  credentials: Option<Credentials>,
+  max_retries: u32,
+  retry_delay: Duration,
+  handle:      Handle,
+}
+
+impl RestClient {
+  pub fn products(&self) -> RestFuture<Vec<ProductInfo>> {
+    self.get("/products".to_string(), false)
+  }
+
+  pub fn book(&self, product: Product, level: BookLevel) -> RestFuture<AggregatedBook> {
+    self.get(format!("/products/{}/book?level={}", product, level.number()), false)
+  }
+
+  /// The full, non-aggregated order book
+  pub fn full_book(&self, product: Product) -> RestFuture<FullBook> {
+    self.get(format!("/products/{}/book?level=3", product), false)
+  }
+
+  pub fn trades(&self, product: Product, cursor: &Cursor) -> RestFuture<Page<Trade>> {
+    self.get_page(format!("/products/{}/trades", product), vec![], cursor, false)
+  }
+
+  /// Candles between `start` and `end`, each covering `granularity`
+  pub fn candles(&self, product: Product, start: DateTime, end: DateTime, granularity: Duration)
+    -> RestFuture<Vec<Candle>>
+  {
+    self.get(format!(
+      "/products/{}/candles?start={}&end={}&granularity={}",
+      product,
+      start.format("%Y-%m-%dT%H:%M:%SZ"),
+      end.format("%Y-%m-%dT%H:%M:%SZ"),
+      granularity.as_secs(),
+    ), false)
+  }
+
+  pub fn accounts(&self) -> RestFuture<Vec<Account>> {
+    self.get("/accounts".to_string(), true)
+  }
+
+  /// Open orders
+  pub fn orders(&self, cursor: &Cursor) -> RestFuture<Page<Order>> {
+    self.get_page("/orders".to_string(), vec![], cursor, true)
+  }
+
+  pub fn order(&self, id: Uuid) -> RestFuture<Order> {
+    self.get(format!("/orders/{}", id), true)
+  }
+
+  pub fn place_order(&self, order: &NewOrder) -> RestFuture<Order> {
+    let body = match serde_json::to_string(order) {
+      Ok(body)   => body,
+      Err(error) => return Box::new(future::err(RestError::Serialization(error))),
+    };
+    Box::new(self.send(Method::Post, "/orders".to_string(), Some(body), true)
+      .and_then(|(_, body)| deserialize(&body)))
+  }
+
+  pub fn cancel_order(&self, id: Uuid) -> RestFuture<()> {
+    Box::new(self.send(Method::Delete, format!("/orders/{}", id), None, true).map(|_| ()))
+  }
+
+  /// Cancel all open orders, or only those for `product`, returning the ids
+  /// of the canceled orders
+  pub fn cancel_all(&self, product: Option<Product>) -> RestFuture<Vec<Uuid>> {
+    let path = match product {
+      Some(product) => format!("/orders?product_id={}", product),
+      None          => "/orders".to_string(),
+    };
+    Box::new(self.send(Method::Delete, path, None, true).and_then(|(_, body)| deserialize(&body)))
+  }
+
+  /// Fills of all orders, or only those for `product`
+  pub fn fills(&self, product: Option<Product>, cursor: &Cursor) -> RestFuture<Page<Fill>> {
+    let query = product.into_iter().map(|product| format!("product_id={}", product)).collect();
+    self.get_page("/fills".to_string(), query, cursor, true)
+  }
+
+  fn get<T: DeserializeOwned + 'static>(&self, path: String, authenticated: bool) -> RestFuture<T> {
+    Box::new(self.send(Method::Get, path, None, authenticated).and_then(|(_, body)| deserialize(&body)))
+  }
+
+  fn get_page<T: DeserializeOwned + 'static>(
+    &self,
+    path:          String,
+    mut query:     Vec<String>,
+    cursor:        &Cursor,
+    authenticated: bool,
+  ) -> RestFuture<Page<T>> {
+    query.extend(cursor.query());
+    let path = if query.is_empty() { path } else { format!("{}?{}", path, query.join("&")) };
+    let limit = cursor.limit;
+
+    Box::new(self.send(Method::Get, path, None, authenticated).and_then(move |(headers, body)| {
+      let header = |name| {
+        headers.get_raw(name)
+          .and_then(|raw| raw.one())
+          .and_then(|value| String::from_utf8(value.to_vec()).ok())
+      };
+      Ok(Page {
+        items:  deserialize(&body)?,
+        before: header("CB-BEFORE"),
+        after:  header("CB-AFTER"),
+        limit,
+      })
+    }))
+  }
+
+  /// Send a request, retrying if it is rate limited, and return the headers
+  /// and body of the successful response
+  fn send(&self, method: Method, path: String, body: Option<String>, authenticated: bool)
+    -> RestFuture<(Headers, Chunk)>
+  {
+    if authenticated && self.credentials.is_none() {
+      return Box::new(future::err(RestError::Unauthenticated));
+    }
+
+    let uri = match format!("{}{}", self.url, path).parse::<Uri>() {
+      Ok(uri)    => uri,
+      Err(error) => return Box::new(future::err(RestError::Uri(error))),
+    };
+
+    let client = self.clone();
+    Box::new(future::loop_fn(0, move |attempt| -> RestFuture<Loop<(Headers, Chunk), u32>> {
+      let request = client.request(method.clone(), uri.clone(), &path, body.as_ref(), authenticated);
+      let delay = client.retry_delay * 2u32.pow(attempt);
+      let retry = attempt < client.max_retries;
+      let handle = client.handle.clone();
+
+      Box::new(request.map_err(RestError::Http).and_then(move |response| -> RestFuture<_> {
+        let status = response.status();
+
+        if status == StatusCode::TooManyRequests {
+          if !retry {
+            return Box::new(future::err(RestError::RateLimited));
+          }
+          warn!("rate limited, retrying in {:?}", delay);
+          return Box::new(Timeout::new(delay, &handle).into_future().flatten()
+            .map(move |()| Loop::Continue(attempt + 1))
+            .map_err(RestError::Timer));
+        }
+
+        let headers = response.headers().clone();
+        Box::new(response.body().concat2().map_err(RestError::Http).and_then(move |body| {
+          if status.is_success() {
+            return Ok(Loop::Break((headers, body)));
+          }
+          let message = serde_json::from_slice::<ErrorMessage>(&body)
+            .map(|error| error.message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+          Err(RestError::Api{status, message})
+        }))
+      }))
+    }))
+  }
+
+  fn request(&self, method: Method, uri: Uri, path: &str, body: Option<&String>, authenticated: bool)
+    -> hyper::client::FutureResponse
+  {
+    let mut request = Request::new(method.clone(), uri);
+    request.headers_mut().set(UserAgent::new(USER_AGENT));
+
+    if let (true, Some(credentials)) = (authenticated, self.credentials.as_ref()) {
+      let timestamp = Utc::now().timestamp().to_string();
+      let signature = credentials.sign(&timestamp, method.as_ref(), path, body.map_or("", String::as_str));
+      let headers = request.headers_mut();
+      headers.set_raw("CB-ACCESS-KEY", credentials.key());
+      headers.set_raw("CB-ACCESS-SIGN", signature);
+      headers.set_raw("CB-ACCESS-TIMESTAMP", timestamp);
+      headers.set_raw("CB-ACCESS-PASSPHRASE", credentials.passphrase());
+    }
+
+    if let Some(body) = body {
+      request.headers_mut().set(ContentType::json());
+      request.headers_mut().set(ContentLength(body.len() as u64));
+      request.set_body(body.clone());
+    }
+
+    match self.connection {
+      Connection::Http(ref client)  => client.request(request),
+      Connection::Https(ref client) => client.request(request),
+    }
+  }
+}
+
+fn deserialize<T: DeserializeOwned>(body: &[u8]) -> Result<T, RestError> {
+  serde_json::from_slice(body).map_err(RestError::Deserialization)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use self::mock::{MockResponse, MockRest, MockRestServer};
+  use tokio_core::reactor::Core;
+
+  fn client(core: &Core, server: &MockRestServer) -> RestClient {
+    RestBuilder::new()
+      .url(&server.url())
+      .credentials(Credentials::new("key", "c2VjcmV0", "passphrase").unwrap())
+      .retries(2, Duration::from_millis(1))
+      .build(&core.handle())
+      .unwrap()
+  }
+
+  #[test]
+  fn books() {
+    let server = MockRest::new()
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(r#"{
+        "sequence": 3,
+        "bids": [["100.0", "1.5", 2]],
+        "asks": [["101.0", "0.5", 1]]
+      }"#))
+      .route("GET", "/products/BTC-USD/book", MockResponse::json(r#"{
+        "sequence": 3,
+        "bids": [["100.0", "1.5", "d50ec984-77a8-460a-b958-66f114b0de9b"]],
+        "asks": []
+      }"#))
+      .bind()
+      .unwrap();
+    let mut core = Core::new().unwrap();
+    let client = client(&core, &server);
+    let product = "BTC-USD".parse().unwrap();
+
+    let book = core.run(client.book(product, BookLevel::Top50)).unwrap();
+    assert_eq!(book.sequence, 3);
+    assert_eq!(book.bids[0].2, 2);
+
+    let book = core.run(client.full_book(product)).unwrap();
+    assert_eq!(book.bids[0].2.to_string(), "d50ec984-77a8-460a-b958-66f114b0de9b");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/products/BTC-USD/book?level=2");
+    assert_eq!(requests[1].path, "/products/BTC-USD/book?level=3");
+    assert_eq!(requests[0].header("CB-ACCESS-KEY"), None);
+  }
+
+  #[test]
+  fn candles() {
+    let candles: Vec<Candle> = serde_json::from_str("[[1500000000, 99.5, 101.25, 100, 101, 12.5]]").unwrap();
+    assert_eq!(candles[0].time, 1500000000);
+    assert_eq!(candles[0].high, serde_json::from_str(r#""101.25""#).unwrap());
+    assert_eq!(candles[0].volume, serde_json::from_str(r#""12.5""#).unwrap());
+  }
+
+  #[test]
+  fn signing() {
+    let server = MockRest::new()
+      .route("POST", "/orders", MockResponse::json(r#"{
+        "id":             "d50ec984-77a8-460a-b958-66f114b0de9b",
+        "product_id":     "BTC-USD",
+        "side":           "buy",
+        "type":           "limit",
+        "price":          "100.00",
+        "size":           "0.01",
+        "post_only":      false,
+        "created_at":     "2017-10-01T00:00:00Z",
+        "fill_fees":      "0.0",
+        "filled_size":    "0.0",
+        "executed_value": "0.0",
+        "status":         "pending",
+        "settled":        false
+      }"#))
+      .bind()
+      .unwrap();
+    let mut core = Core::new().unwrap();
+    let client = client(&core, &server);
+
+    let order = NewOrder::limit(
+      "BTC-USD".parse().unwrap(),
+      ::gdax::feed::message::Side::Buy,
+      serde_json::from_str(r#""100.00""#).unwrap(),
+      serde_json::from_str(r#""0.01""#).unwrap(),
+    );
+    let placed = core.run(client.place_order(&order)).unwrap();
+    assert_eq!(placed.status, OrderStatus::Pending);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.header("CB-ACCESS-KEY"), Some("key"));
+    assert_eq!(request.header("CB-ACCESS-PASSPHRASE"), Some("passphrase"));
+    let credentials = Credentials::new("key", "c2VjcmV0", "passphrase").unwrap();
+    assert!(credentials.verify(
+      request.header("CB-ACCESS-SIGN").unwrap(),
+      request.header("CB-ACCESS-TIMESTAMP").unwrap(),
+      "POST",
+      "/orders",
+      &request.body,
+    ));
+
+    let unauthenticated = RestBuilder::new().url(&server.url()).build(&core.handle()).unwrap();
+    match core.run(unauthenticated.accounts()) {
+      Err(RestError::Unauthenticated) => {}
+      other => panic!("expected unauthenticated error: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn pagination() {
+    let trade = |id| format!(
+      r#"{{"time": "2017-10-01T00:00:00Z", "trade_id": {}, "price": "100.0", "size": "1.0", "side": "buy"}}"#,
+      id,
+    );
+    let server = MockRest::new()
+      .route("GET", "/products/BTC-USD/trades", MockResponse::json(&format!("[{}, {}]", trade(5), trade(4)))
+        .header("CB-BEFORE", "5")
+        .header("CB-AFTER", "4"))
+      .route("GET", "/products/BTC-USD/trades", MockResponse::json(&format!("[{}]", trade(3)))
+        .header("CB-BEFORE", "3")
+        .header("CB-AFTER", "3"))
+      .route("GET", "/products/BTC-USD/trades", MockResponse::json("[]"))
+      .bind()
+      .unwrap();
+    let mut core = Core::new().unwrap();
+    let client = client(&core, &server);
+    let product = "BTC-USD".parse().unwrap();
+
+    let trades = core.run(paginate(Cursor::new().limit(2), move |cursor| client.trades(product, &cursor))
+      .collect()).unwrap();
+    assert_eq!(trades.iter().map(|trade| trade.trade_id).collect::<Vec<_>>(), vec![5, 4, 3]);
+
+    let paths = server.requests().into_iter().map(|request| request.path).collect::<Vec<_>>();
+    assert_eq!(paths, vec![
+      "/products/BTC-USD/trades?limit=2",
+      "/products/BTC-USD/trades?after=4&limit=2",
+      "/products/BTC-USD/trades?after=3&limit=2",
+    ]);
+  }
+
+  #[test]
+  fn rate_limit() {
+    let limited = MockResponse::json(r#"{"message": "Rate limit exceeded"}"#).status(429);
+    let server = MockRest::new()
+      .route("GET", "/products", limited.clone())
+      .route("GET", "/products", limited.clone())
+      .route("GET", "/products", MockResponse::json("[]"))
+      .route("GET", "/accounts", limited)
+      .bind()
+      .unwrap();
+    let mut core = Core::new().unwrap();
+    let client = client(&core, &server);
+
+    assert_eq!(core.run(client.products()).unwrap(), vec![]);
+    match core.run(client.accounts()) {
+      Err(RestError::RateLimited) => {}
+      other => panic!("expected rate limit error: {:?}", other),
+    }
+    assert_eq!(server.requests().len(), 6);
+  }
+
+  #[test]
+  fn api_error() {
+    let server = MockRest::new().bind().unwrap();
+    let mut core = Core::new().unwrap();
+    let client = client(&core, &server);
+
+    match core.run(client.order(Uuid::nil())) {
+      Err(RestError::Api{status: StatusCode::NotFound, ref message}) => assert_eq!(message, "NotFound"),
+      other => panic!("expected API error: {:?}", other),
+    }
+  }
+}
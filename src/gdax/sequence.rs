@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use gdax::feed::message::{IncomingMessage, Product};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SequenceEvent {
+  /// Messages between `expected` and `received` were missed
+  Gap{product_id: Product, expected: u64, received: u64},
+  /// A message with the same sequence number as the previous message was received
+  Duplicate{product_id: Product, sequence: u64},
+  /// A message with a lower sequence number than the previous message was received
+  OutOfOrder{product_id: Product, expected: u64, received: u64},
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SequenceCounts {
+  pub gaps:         u64,
+  pub missing:      u64,
+  pub duplicates:   u64,
+  pub out_of_order: u64,
+}
+
+impl SequenceCounts {
+  pub fn is_complete(&self) -> bool {
+    *self == SequenceCounts::default()
+  }
+}
+
+/// Checks the sequence numbers of full channel messages for each product
+pub struct SequenceTracker {
+  last:          BTreeMap<Product, u64>,
+  counts:        BTreeMap<Product, SequenceCounts>,
+  resync_on_gap: bool,
+}
+
+impl SequenceTracker {
+  pub fn new() -> SequenceTracker {
+    SequenceTracker {
+      last:          BTreeMap::new(),
+      counts:        BTreeMap::new(),
+      resync_on_gap: false,
+    }
+  }
+
+  /// Request a resync when a gap is detected
+  pub fn resync_on_gap(mut self, resync_on_gap: bool) -> SequenceTracker {
+    self.resync_on_gap = resync_on_gap;
+    self
+  }
+
+  /// Check the sequence number of `message`, returning an event if it isn't the
+  /// one that was expected
+  pub fn check(&mut self, message: &IncomingMessage) -> Option<SequenceEvent> {
+    use self::IncomingMessage::*;
+    let (product_id, received) = match *message {
+      Received{product_id, sequence, ..} |
+      Open{product_id, sequence, ..}     |
+      Done{product_id, sequence, ..}     |
+      Match{product_id, sequence, ..}    |
+      Change{product_id, sequence, ..}   => (product_id, sequence),
+      _                                  => return None,
+    };
+
+    let last = match self.last.insert(product_id, received) {
+      Some(last) => last,
+      None       => return None,
+    };
+
+    let counts = self.counts.entry(product_id).or_insert_with(SequenceCounts::default);
+
+    if received == last + 1 {
+      None
+    } else if received > last {
+      counts.gaps += 1;
+      counts.missing += received - last - 1;
+      Some(SequenceEvent::Gap{product_id, expected: last + 1, received})
+    } else {
+      self.last.insert(product_id, last);
+      if received == last {
+        counts.duplicates += 1;
+        Some(SequenceEvent::Duplicate{product_id, sequence: received})
+      } else {
+        counts.out_of_order += 1;
+        Some(SequenceEvent::OutOfOrder{product_id, expected: last + 1, received})
+      }
+    }
+  }
+
+  /// Whether the feed should be resynced after `event`
+  pub fn should_resync(&self, event: &SequenceEvent) -> bool {
+    match *event {
+      SequenceEvent::Gap{..} => self.resync_on_gap,
+      _                      => false,
+    }
+  }
+
+  /// Forget the last sequence number seen for every product, after a discontinuity
+  pub fn reset(&mut self) {
+    self.last.clear();
+  }
+
+  pub fn counts(&self) -> &BTreeMap<Product, SequenceCounts> {
+    &self.counts
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use gdax::fixtures::open_text;
+  use serde_json;
+
+  fn check(tracker: &mut SequenceTracker, product_id: &str, sequence: u64) -> Option<SequenceEvent> {
+    tracker.check(&serde_json::from_str(&open_text(product_id, sequence, 1, "sell", "200.2", "1.00")).unwrap())
+  }
+
+  #[test]
+  fn consecutive() {
+    let mut tracker = SequenceTracker::new();
+    for sequence in 10..20 {
+      assert_eq!(check(&mut tracker, "BTC-USD", sequence), None);
+    }
+    assert!(tracker.counts().values().all(SequenceCounts::is_complete));
+  }
+
+  #[test]
+  fn products_are_independent() {
+    let mut tracker = SequenceTracker::new();
+    assert_eq!(check(&mut tracker, "BTC-USD", 10), None);
+    assert_eq!(check(&mut tracker, "ETH-USD", 500), None);
+    assert_eq!(check(&mut tracker, "BTC-USD", 11), None);
+    assert_eq!(check(&mut tracker, "ETH-USD", 501), None);
+  }
+
+  #[test]
+  fn gap() {
+    let btc_usd = "BTC-USD".parse().unwrap();
+    let mut tracker = SequenceTracker::new();
+    check(&mut tracker, "BTC-USD", 10);
+    assert_eq!(
+      check(&mut tracker, "BTC-USD", 14),
+      Some(SequenceEvent::Gap{product_id: btc_usd, expected: 11, received: 14}),
+    );
+    assert_eq!(check(&mut tracker, "BTC-USD", 15), None);
+    assert_eq!(tracker.counts()[&btc_usd], SequenceCounts{gaps: 1, missing: 3, ..Default::default()});
+  }
+
+  #[test]
+  fn duplicate_and_out_of_order() {
+    let btc_usd = "BTC-USD".parse().unwrap();
+    let mut tracker = SequenceTracker::new();
+    check(&mut tracker, "BTC-USD", 10);
+    assert_eq!(
+      check(&mut tracker, "BTC-USD", 10),
+      Some(SequenceEvent::Duplicate{product_id: btc_usd, sequence: 10}),
+    );
+    assert_eq!(
+      check(&mut tracker, "BTC-USD", 8),
+      Some(SequenceEvent::OutOfOrder{product_id: btc_usd, expected: 11, received: 8}),
+    );
+    assert_eq!(check(&mut tracker, "BTC-USD", 11), None);
+    assert_eq!(
+      tracker.counts()[&btc_usd],
+      SequenceCounts{duplicates: 1, out_of_order: 1, ..Default::default()},
+    );
+  }
+
+  #[test]
+  fn reset() {
+    let mut tracker = SequenceTracker::new().resync_on_gap(true);
+    check(&mut tracker, "BTC-USD", 10);
+    let event = check(&mut tracker, "BTC-USD", 12).unwrap();
+    assert!(tracker.should_resync(&event));
+    tracker.reset();
+    assert_eq!(check(&mut tracker, "BTC-USD", 100), None);
+    assert_eq!(check(&mut tracker, "BTC-USD", 101), None);
+  }
+}
@@ -0,0 +1,590 @@
+use byteorder::{BigEndian, ByteOrder};
+use chrono::{Duration, TimeZone, Utc};
+use futures::future;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::usize;
+use uuid::Uuid;
+
+use decimal::{Decimal, Rounding};
+use gdax::fees::{FeeModel, FeeSchedule};
+use gdax::feed::message::*;
+use gdax::order_book::{OrderBook, OrderBookError};
+use gdax::order_entry::{self, OrderEntry, OrderError, OrderEvent, OrderFuture, PlacedOrder};
+use gdax::products::Products;
+use gdax::rest::message::{Fill, Liquidity, NewOrder, OrderStatus, TimeInForce};
+
+/// Where a simulated limit order joins the queue at its price, relative to
+/// the size displayed there when it opens
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QueueModel {
+  /// Behind all displayed size
+  Back,
+  /// Ahead of all displayed size
+  Front,
+  /// Behind the given fraction of displayed size
+  Fraction(Decimal),
+}
+
+impl QueueModel {
+  fn size_ahead(self, displayed: BaseCurrencyAmount) -> BaseCurrencyAmount {
+    match self {
+      QueueModel::Back               => displayed,
+      QueueModel::Front              => BaseCurrencyAmount::zero(),
+      QueueModel::Fraction(fraction) => displayed.scale(fraction, Rounding::HalfEven),
+    }
+  }
+}
+
+impl FromStr for QueueModel {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<QueueModel, String> {
+    match s {
+      "back"  => Ok(QueueModel::Back),
+      "front" => Ok(QueueModel::Front),
+      _       => match s.parse::<Decimal>() {
+        Ok(fraction) if fraction <= Decimal::from(1) => Ok(QueueModel::Fraction(fraction)),
+        _ => Err(format!("unknown queue model, expected back, front, or a fraction of displayed size: {}", s)),
+      },
+    }
+  }
+}
+
+/// An order placed with a `Simulator`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedOrder {
+  pub id:          Uuid,
+  pub client_oid:  Uuid,
+  pub order:       NewOrder,
+  /// `Pending` until it reaches the simulated exchange, `Rejected` if it was
+  /// post-only and would have taken liquidity
+  pub status:      OrderStatus,
+  pub filled_size: BaseCurrencyAmount,
+  pub fill_fees:   QuoteCurrencyAmount,
+  /// Size that must trade at the order's price before it fills
+  pub queue_ahead: BaseCurrencyAmount,
+  arrival:         Option<DateTime>,
+}
+
+impl SimulatedOrder {
+  pub fn remaining(&self) -> BaseCurrencyAmount {
+    self.order.size.checked_sub(self.filled_size).unwrap_or(BaseCurrencyAmount::zero())
+  }
+}
+
+/// A paper-trading exchange, which fills orders against the books and trades
+/// of the messages it's given, whether live or replayed.
+///
+/// Orders reach the exchange `latency` after they're placed, as measured by
+/// the timestamps of messages, or after the first timestamped message if
+/// they're placed before it. Cancels take effect immediately. Taker fills
+/// walk the level 2 book, and consume its liquidity until the next update of
+/// each level, without changing the book itself. Resting orders fill when
+/// trades at their price exhaust the size ahead of them in the queue, or
+/// when trades go through their price.
+pub struct Simulator {
+  products:   Products,
+  latency:    Duration,
+  queue:      QueueModel,
+  fees:       FeeModel,
+  books:      BTreeMap<Product, OrderBook>,
+  /// Size of each level taken by simulated orders since its last update
+  taken:      BTreeMap<(Product, Side, QuoteCurrencyPrice), BaseCurrencyAmount>,
+  orders:     BTreeMap<Uuid, SimulatedOrder>,
+  events:     Vec<OrderEvent>,
+  clock:      Option<DateTime>,
+  next_id:    u64,
+  next_trade: u64,
+}
+
+impl Simulator {
+  pub fn new(products: Products) -> Simulator {
+    Simulator {
+      latency:    Duration::zero(),
+      queue:      QueueModel::Back,
+      fees:       FeeModel::new(FeeSchedule::gdax()),
+      books:      BTreeMap::new(),
+      taken:      BTreeMap::new(),
+      orders:     BTreeMap::new(),
+      events:     vec![],
+      clock:      None,
+      next_id:    1,
+      next_trade: 1,
+      products,
+    }
+  }
+
+  /// Delay between placing an order and it reaching the exchange
+  pub fn latency(mut self, latency: Duration) -> Simulator {
+    self.latency = latency;
+    self
+  }
+
+  pub fn queue_model(mut self, queue: QueueModel) -> Simulator {
+    self.queue = queue;
+    self
+  }
+
+  /// Charge fills at the rates of `schedule` instead of the exchange's
+  pub fn fee_schedule(mut self, schedule: FeeSchedule) -> Simulator {
+    self.fees = FeeModel::new(schedule);
+    self
+  }
+
+  /// Time of the latest timestamped message
+  pub fn clock(&self) -> Option<DateTime> {
+    self.clock
+  }
+
+  pub fn book(&self, product: Product) -> Option<&OrderBook> {
+    self.books.get(&product)
+  }
+
+  pub fn orders(&self) -> &BTreeMap<Uuid, SimulatedOrder> {
+    &self.orders
+  }
+
+  /// Size on `side` at `price` that simulated orders can still take
+  pub fn available(&self, product: Product, side: Side, price: QuoteCurrencyPrice) -> BaseCurrencyAmount {
+    let displayed = self.books.get(&product)
+      .map(|book| book.size_at(side, price))
+      .unwrap_or(BaseCurrencyAmount::zero());
+    match self.taken.get(&(product, side, price)) {
+      Some(&taken) => displayed.checked_sub(taken).unwrap_or(BaseCurrencyAmount::zero()),
+      None         => displayed,
+    }
+  }
+
+  /// Update books and orders from a feed message. A book that an update
+  /// leaves invalid is discarded until the next snapshot.
+  pub fn apply(&mut self, message: &IncomingMessage) -> Result<(), OrderBookError> {
+    self.fees.observe(message);
+
+    if let Some(time) = message.time() {
+      if self.clock.is_none() {
+        let arrival = time + self.latency;
+        for order in self.orders.values_mut() {
+          if order.status == OrderStatus::Pending && order.arrival.is_none() {
+            order.arrival = Some(arrival);
+          }
+        }
+      }
+      if self.clock.map(|clock| time > clock).unwrap_or(true) {
+        self.clock = Some(time);
+      }
+    }
+
+    match *message {
+      IncomingMessage::Snapshot(ref snapshot) => {
+        self.books.insert(snapshot.product_id, OrderBook::from_snapshot(snapshot));
+        self.taken.retain(|&(product, _, _), _| product != snapshot.product_id);
+      }
+      IncomingMessage::L2update(ref update) => {
+        for &(side, price, _) in &update.changes {
+          self.taken.remove(&(update.product_id, side, price));
+        }
+        if let Some(mut book) = self.books.remove(&update.product_id) {
+          book.update(update)?;
+          self.books.insert(update.product_id, book);
+        }
+        for &(side, price, size) in &update.changes {
+          for order in self.orders.values_mut() {
+            if order.status == OrderStatus::Open && order.order.product_id == update.product_id
+              && order.order.side == side && order.order.price == Some(price) && size < order.queue_ahead
+            {
+              order.queue_ahead = size;
+            }
+          }
+        }
+      }
+      IncomingMessage::Match{product_id, price, side, size, ..} => self.trade(product_id, side, price, size),
+      _ => {}
+    }
+
+    let arrived = self.orders.values()
+      .filter(|order| order.status == OrderStatus::Pending && match (order.arrival, self.clock) {
+        (Some(arrival), Some(clock)) => arrival <= clock,
+        _                            => false,
+      })
+      .map(|order| order.id)
+      .collect::<Vec<Uuid>>();
+    for id in arrived {
+      self.arrive(id);
+    }
+
+    Ok(())
+  }
+
+  fn fill(&mut self, id: Uuid, price: QuoteCurrencyPrice, size: BaseCurrencyAmount, liquidity: Liquidity) {
+    let created_at = self.clock.unwrap_or_else(|| Utc.timestamp(0, 0));
+    let product_id = self.orders[&id].order.product_id;
+    let fee = self.fees.fee(created_at, product_id, liquidity, price, size);
+    self.fees.record(created_at, product_id, size);
+
+    let (side, remaining) = {
+      let order = self.orders.get_mut(&id).expect("filled unknown order");
+      order.filled_size = order.filled_size + size;
+      order.fill_fees = order.fill_fees + fee;
+      (order.order.side, order.remaining())
+    };
+
+    self.events.push(OrderEvent::Fill(Fill {
+      trade_id:   self.next_trade,
+      order_id:   id,
+      settled:    true,
+      created_at,
+      product_id, side, price, size, liquidity, fee,
+    }));
+    self.next_trade += 1;
+
+    if remaining.is_zero() {
+      self.finish(id, OrderStatus::Done);
+    }
+  }
+
+  fn finish(&mut self, id: Uuid, status: OrderStatus) {
+    self.orders.get_mut(&id).expect("finished unknown order").status = status;
+    self.events.push(OrderEvent::Done(id));
+  }
+
+  /// Fill resting orders against a trade in which the maker was on `side`,
+  /// best price first and then in the order they were placed, until the
+  /// trade's size is used up
+  fn trade(&mut self, product_id: Product, side: Side, price: QuoteCurrencyPrice, size: BaseCurrencyAmount) {
+    let mut resting = self.orders.values()
+      .filter(|order| order.status == OrderStatus::Open && order.order.product_id == product_id
+        && order.order.side == side)
+      .map(|order| (order.order.price.expect("open market order"), order.id))
+      .collect::<Vec<_>>();
+    resting.sort_by(|a, b| match side {
+      Side::Buy  => b.0.cmp(&a.0).then(a.1.cmp(&b.1)),
+      Side::Sell => a.cmp(b),
+    });
+
+    let mut remaining = size;
+    let mut fills = vec![];
+    for (limit, id) in resting {
+      let order = self.orders.get_mut(&id).unwrap();
+      let through = match side {
+        Side::Buy  => price < limit,
+        Side::Sell => price > limit,
+      };
+
+      let available = if through {
+        remaining
+      } else if price == limit {
+        let available = remaining.checked_sub(order.queue_ahead).unwrap_or(BaseCurrencyAmount::zero());
+        order.queue_ahead = order.queue_ahead.checked_sub(size).unwrap_or(BaseCurrencyAmount::zero());
+        available
+      } else {
+        continue;
+      };
+
+      let filled = available.min(order.remaining());
+      if !filled.is_zero() {
+        remaining = remaining.checked_sub(filled).unwrap();
+        fills.push((id, limit, filled));
+      }
+    }
+
+    for (id, price, size) in fills {
+      self.fill(id, price, size, Liquidity::Maker);
+    }
+  }
+
+  /// Execute or open an order that has reached the exchange
+  fn arrive(&mut self, id: Uuid) {
+    let order = self.orders[&id].order.clone();
+    let opposite = order.side.opposite();
+
+    let levels = self.books.get(&order.product_id)
+      .map(|book| book.top(opposite, usize::MAX))
+      .unwrap_or_default()
+      .into_iter()
+      .take_while(|&(price, _)| match (order.price, order.side) {
+        (None,        _)          => true,
+        (Some(limit), Side::Buy)  => price <= limit,
+        (Some(limit), Side::Sell) => price >= limit,
+      })
+      .map(|(price, _)| (price, self.available(order.product_id, opposite, price)))
+      .filter(|&(_, size)| !size.is_zero())
+      .collect::<Vec<_>>();
+    let available = levels.iter().fold(BaseCurrencyAmount::zero(), |total, &(_, size)| total + size);
+
+    if order.post_only && !available.is_zero() {
+      debug!("simulator: rejecting post-only order {} that would take liquidity", id);
+      self.finish(id, OrderStatus::Rejected);
+      return;
+    }
+
+    if order.time_in_force == Some(TimeInForce::FOK) && available < order.size {
+      debug!("simulator: killing fill or kill order {}", id);
+      self.finish(id, OrderStatus::Done);
+      return;
+    }
+
+    for (price, size) in levels {
+      let remaining = self.orders[&id].remaining();
+      if remaining.is_zero() {
+        break;
+      }
+      let filled = size.min(remaining);
+      self.fill(id, price, filled, Liquidity::Taker);
+      let taken = self.taken.entry((order.product_id, opposite, price)).or_insert(BaseCurrencyAmount::zero());
+      *taken = *taken + filled;
+    }
+
+    let queue_ahead = match (self.books.get(&order.product_id), order.price) {
+      (Some(book), Some(price)) => self.queue.size_ahead(book.size_at(order.side, price)),
+      _                         => BaseCurrencyAmount::zero(),
+    };
+    if self.orders[&id].status == OrderStatus::Done {
+      return;
+    }
+    match (order.order_type, order.time_in_force) {
+      (OrderType::Market, _) | (_, Some(TimeInForce::IOC)) => self.finish(id, OrderStatus::Done),
+      _ => {
+        let order = self.orders.get_mut(&id).unwrap();
+        order.status = OrderStatus::Open;
+        order.queue_ahead = queue_ahead;
+      }
+    }
+  }
+
+  /// Sequential identifiers, so that simulations are repeatable
+  fn next_id(&mut self) -> Uuid {
+    let mut bytes = [0; 16];
+    BigEndian::write_u64(&mut bytes[8..], self.next_id);
+    self.next_id += 1;
+    Uuid::from_bytes(&bytes).unwrap()
+  }
+}
+
+impl OrderEntry for Simulator {
+  fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder> {
+    if let Err(invalid) = order_entry::validate(&self.products, &order) {
+      return Box::new(future::err(OrderError::Invalid(invalid)));
+    }
+
+    let id = self.next_id();
+    let client_oid = order.client_oid.unwrap_or(id);
+    let arrival = self.clock.map(|clock| clock + self.latency);
+    self.orders.insert(id, SimulatedOrder {
+      status:      OrderStatus::Pending,
+      filled_size: BaseCurrencyAmount::zero(),
+      fill_fees:   QuoteCurrencyAmount::zero(),
+      queue_ahead: BaseCurrencyAmount::zero(),
+      id, client_oid, order, arrival,
+    });
+
+    if self.latency.is_zero() {
+      self.arrive(id);
+    }
+
+    Box::new(future::ok(PlacedOrder{id, client_oid}))
+  }
+
+  fn cancel(&mut self, id: Uuid) -> OrderFuture<()> {
+    match self.orders.get(&id).map(|order| order.status) {
+      Some(OrderStatus::Open) | Some(OrderStatus::Pending) => {
+        self.finish(id, OrderStatus::Done);
+        Box::new(future::ok(()))
+      }
+      _ => Box::new(future::err(OrderError::UnknownOrder(id))),
+    }
+  }
+
+  fn cancel_all(&mut self, product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+    let canceled = self.orders.values()
+      .filter(|order| (order.status == OrderStatus::Open || order.status == OrderStatus::Pending)
+        && product.map(|product| order.order.product_id == product).unwrap_or(true))
+      .map(|order| order.id)
+      .collect::<Vec<Uuid>>();
+    for &id in &canceled {
+      self.finish(id, OrderStatus::Done);
+    }
+    Box::new(future::ok(canceled))
+  }
+
+  fn observe(&mut self, message: &IncomingMessage) {
+    if let Err(error) = self.apply(message) {
+      warn!("simulator: discarding invalid order book: {}", error);
+    }
+  }
+
+  /// Discard books until the next snapshots
+  fn discontinuity(&mut self) {
+    self.books.clear();
+    self.taken.clear();
+  }
+
+  fn events(&mut self) -> Vec<OrderEvent> {
+    self.events.split_off(0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use futures::Future;
+  use gdax::fixtures::{products, snapshot, trade};
+  use serde_json;
+
+  fn product() -> Product {
+    "BTC-USD".parse().unwrap()
+  }
+
+  fn message(json: &str) -> IncomingMessage {
+    serde_json::from_str(json).unwrap()
+  }
+
+  fn take_fills(simulator: &mut Simulator) -> Vec<Fill> {
+    simulator.events().into_iter()
+      .filter_map(|event| match event {
+        OrderEvent::Fill(fill) => Some(fill),
+        OrderEvent::Done(_)    => None,
+      })
+      .collect()
+  }
+
+  fn limit(side: Side, price: &str, size: &str) -> NewOrder {
+    NewOrder::limit(product(), side, price.parse().unwrap(), size.parse().unwrap())
+  }
+
+  #[test]
+  fn taker() {
+    let mut simulator = Simulator::new(products()).fee_schedule(FeeSchedule::flat(Decimal::zero(), "0.0025".parse().unwrap()));
+    simulator.apply(&snapshot()).unwrap();
+
+    let market = NewOrder::market(product(), Side::Buy, "2.0".parse().unwrap());
+    let placed = simulator.place(market).wait().unwrap();
+    let fills = take_fills(&mut simulator);
+    assert_eq!(
+      fills.iter().map(|fill| (fill.price.to_string(), fill.size.to_string(), fill.liquidity)).collect::<Vec<_>>(),
+      vec![("101.0".to_string(), "1.0".to_string(), Liquidity::Taker), ("102.0".to_string(), "1.0".to_string(), Liquidity::Taker)],
+    );
+    assert_eq!(fills[0].fee, "0.2525".parse().unwrap());
+    assert_eq!(simulator.orders()[&placed.id].status, OrderStatus::Done);
+    assert_eq!(simulator.book(product()).unwrap().best_ask(), Some(("101.00".parse().unwrap(), "1.0".parse().unwrap())));
+    assert!(simulator.available(product(), Side::Sell, "101.00".parse().unwrap()).is_zero());
+    assert_eq!(simulator.available(product(), Side::Sell, "102.00".parse().unwrap()), "3.0".parse().unwrap());
+
+    let post_only = simulator.place(limit(Side::Buy, "102.00", "1.0").post_only()).wait().unwrap();
+    assert_eq!(simulator.orders()[&post_only.id].status, OrderStatus::Rejected);
+
+    let fok = simulator.place(limit(Side::Buy, "102.00", "5.0").time_in_force(TimeInForce::FOK)).wait().unwrap();
+    assert_eq!(simulator.orders()[&fok.id].status, OrderStatus::Done);
+    assert!(take_fills(&mut simulator).is_empty());
+
+    let ioc = simulator.place(limit(Side::Sell, "99.50", "3.0").time_in_force(TimeInForce::IOC)).wait().unwrap();
+    assert_eq!(simulator.orders()[&ioc.id].filled_size, "2.0".parse().unwrap());
+    assert_eq!(simulator.orders()[&ioc.id].status, OrderStatus::Done);
+  }
+
+  #[test]
+  fn removal_after_sweep() {
+    let mut simulator = Simulator::new(products());
+    simulator.apply(&snapshot()).unwrap();
+    simulator.place(NewOrder::market(product(), Side::Buy, "1.0".parse().unwrap())).wait().unwrap();
+
+    simulator.apply(&message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["sell", "101.00", "0"]]}"#))
+      .unwrap();
+    simulator.apply(&message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["sell", "101.00", "2.0"]]}"#))
+      .unwrap();
+    assert_eq!(simulator.available(product(), Side::Sell, "101.00".parse().unwrap()), "2.0".parse().unwrap());
+  }
+
+  #[test]
+  fn queue_position() {
+    let mut simulator = Simulator::new(products()).fee_schedule(FeeSchedule::flat("0.001".parse().unwrap(), Decimal::zero()));
+    simulator.apply(&snapshot()).unwrap();
+
+    let back = simulator.place(limit(Side::Buy, "100.00", "1.0")).wait().unwrap();
+    assert_eq!(simulator.orders()[&back.id].queue_ahead, "2.0".parse().unwrap());
+
+    simulator.apply(&message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["buy", "100.00", "1.5"]]}"#))
+      .unwrap();
+    assert_eq!(simulator.orders()[&back.id].queue_ahead, "1.5".parse().unwrap());
+
+    simulator.apply(&trade(1, "buy", "100.00", "1.0")).unwrap();
+    assert!(take_fills(&mut simulator).is_empty());
+    simulator.apply(&trade(2, "buy", "100.00", "1.0")).unwrap();
+    let fills = take_fills(&mut simulator);
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].size, "0.5".parse().unwrap());
+    assert_eq!(fills[0].liquidity, Liquidity::Maker);
+    assert_eq!(fills[0].fee, "0.05".parse().unwrap());
+
+    simulator.apply(&trade(3, "buy", "99.00", "2.0")).unwrap();
+    assert_eq!(take_fills(&mut simulator)[0].size, "0.5".parse().unwrap());
+    assert_eq!(simulator.orders()[&back.id].status, OrderStatus::Done);
+
+    let mut front = Simulator::new(products()).queue_model(QueueModel::Front);
+    front.apply(&snapshot()).unwrap();
+    front.place(limit(Side::Sell, "101.00", "1.0")).wait().unwrap();
+    front.apply(&trade(1, "sell", "101.00", "0.25")).unwrap();
+    assert_eq!(take_fills(&mut front)[0].size, "0.25".parse().unwrap());
+  }
+
+  #[test]
+  fn fills_share_trade_size() {
+    let mut simulator = Simulator::new(products()).queue_model(QueueModel::Front);
+    simulator.apply(&snapshot()).unwrap();
+    let first = simulator.place(limit(Side::Sell, "101.00", "1.0")).wait().unwrap();
+    let better = simulator.place(limit(Side::Sell, "100.50", "0.5")).wait().unwrap();
+    let last = simulator.place(limit(Side::Sell, "101.00", "1.0")).wait().unwrap();
+
+    simulator.apply(&trade(1, "sell", "101.00", "1.0")).unwrap();
+    let fills = take_fills(&mut simulator);
+    assert_eq!(
+      fills.iter().map(|fill| (fill.order_id, fill.size.to_string())).collect::<Vec<_>>(),
+      vec![(better.id, "0.5".to_string()), (first.id, "0.5".to_string())],
+    );
+    assert!(simulator.orders()[&last.id].filled_size.is_zero());
+  }
+
+  #[test]
+  fn latency() {
+    let mut simulator = Simulator::new(products()).latency(Duration::seconds(2));
+    simulator.apply(&snapshot()).unwrap();
+    simulator.apply(&trade(0, "sell", "101.00", "0.1")).unwrap();
+
+    let placed = simulator.place(limit(Side::Buy, "101.00", "1.0")).wait().unwrap();
+    assert_eq!(simulator.orders()[&placed.id].status, OrderStatus::Pending);
+
+    simulator.apply(&trade(1, "sell", "101.00", "0.1")).unwrap();
+    assert_eq!(simulator.orders()[&placed.id].status, OrderStatus::Pending);
+
+    simulator.apply(&message(r#"{"type": "l2update", "product_id": "BTC-USD", "changes": [["sell", "101.00", "0.5"]]}"#))
+      .unwrap();
+    simulator.apply(&trade(2, "sell", "102.00", "0.1")).unwrap();
+    assert_eq!(simulator.orders()[&placed.id].status, OrderStatus::Open);
+    assert_eq!(simulator.orders()[&placed.id].filled_size, "0.5".parse().unwrap());
+    assert!(simulator.orders()[&placed.id].queue_ahead.is_zero());
+
+    simulator.events();
+    assert_eq!(simulator.cancel_all(None).wait().unwrap(), vec![placed.id]);
+    assert!(simulator.cancel(placed.id).wait().is_err());
+    match simulator.events().as_slice() {
+      &[OrderEvent::Done(id)] => assert_eq!(id, placed.id),
+      events => panic!("expected done event: {:?}", events),
+    }
+  }
+
+  #[test]
+  fn latency_before_first_timestamp() {
+    let mut simulator = Simulator::new(products()).latency(Duration::seconds(2));
+    simulator.apply(&snapshot()).unwrap();
+
+    let placed = simulator.place(limit(Side::Buy, "101.00", "1.0")).wait().unwrap();
+    simulator.apply(&snapshot()).unwrap();
+    assert_eq!(simulator.orders()[&placed.id].status, OrderStatus::Pending);
+
+    simulator.apply(&trade(0, "sell", "102.00", "0.1")).unwrap();
+    assert_eq!(simulator.orders()[&placed.id].status, OrderStatus::Pending);
+
+    simulator.apply(&trade(2, "sell", "102.00", "0.1")).unwrap();
+    assert_eq!(simulator.orders()[&placed.id].filled_size, "1.0".parse().unwrap());
+  }
+}
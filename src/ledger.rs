@@ -0,0 +1,363 @@
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+use decimal::{Decimal, Rounding, SignedDecimal};
+use gdax::fees::{FeeModel, FeeSchedule};
+use gdax::feed::message::{Currency, IncomingMessage, Product, QuoteCurrencyAmount, QuoteCurrencyPrice, Side};
+use gdax::rest::message::{Fill, Liquidity};
+
+/// Holdings of one product
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Position {
+  /// Net size, positive if long
+  pub size:          SignedDecimal,
+  /// Average entry price of the open size, `None` when flat
+  pub average_price: Option<QuoteCurrencyPrice>,
+  /// Profit or loss on size that has been closed, before fees
+  pub realized_pnl:  SignedDecimal,
+  pub fees:          QuoteCurrencyAmount,
+}
+
+impl Position {
+  /// No holdings
+  pub fn flat() -> Position {
+    Position {
+      size:          SignedDecimal::zero(),
+      average_price: None,
+      realized_pnl:  SignedDecimal::zero(),
+      fees:          QuoteCurrencyAmount::zero(),
+    }
+  }
+
+  fn trade(&mut self, side: Side, price: QuoteCurrencyPrice, size: Decimal) {
+    let signed = match side {
+      Side::Buy  => SignedDecimal::from(size),
+      Side::Sell => -SignedDecimal::from(size),
+    };
+    let open = self.size.abs();
+    let average = self.average_price.map(Decimal::from).unwrap_or_else(Decimal::zero);
+
+    if self.size.is_zero() || self.size.is_negative() == signed.is_negative() {
+      let total = open + size;
+      let cost = open * average + size * Decimal::from(price);
+      self.average_price = Some(cost.div_rounded(total, Rounding::HalfEven).into());
+    } else {
+      let closed = SignedDecimal::from(cmp::min(open, size));
+      let gain = SignedDecimal::from(Decimal::from(price)) - SignedDecimal::from(average);
+      let gain = if self.size.is_negative() { -gain } else { gain };
+      self.realized_pnl = self.realized_pnl + closed * gain;
+
+      if size > open {
+        self.average_price = Some(price);
+      } else if size == open {
+        self.average_price = None;
+      }
+    }
+
+    self.size = self.size + signed;
+  }
+
+  /// Profit or loss if the open size were closed at `mid`
+  pub fn unrealized_pnl(&self, mid: QuoteCurrencyPrice) -> SignedDecimal {
+    match self.average_price {
+      Some(average) =>
+        self.size * (SignedDecimal::from(Decimal::from(mid)) - SignedDecimal::from(Decimal::from(average))),
+      None => SignedDecimal::zero(),
+    }
+  }
+
+  /// Realized and unrealized profit or loss, net of fees. Open size is
+  /// valued at its entry price if there is no mid.
+  pub fn pnl(&self, mid: Option<QuoteCurrencyPrice>) -> SignedDecimal {
+    let unrealized = mid.map(|mid| self.unrealized_pnl(mid)).unwrap_or_else(SignedDecimal::zero);
+    self.realized_pnl + unrealized - SignedDecimal::from(Decimal::from(self.fees))
+  }
+}
+
+/// Balances and positions resulting from fills of our orders, fed either
+/// from the feed or from REST fills. A fill seen from both is only counted
+/// once.
+///
+/// Balances are exact, so they reconcile with exchange account balances.
+/// Average entry prices are rounded.
+#[derive(Debug, Clone)]
+pub struct Ledger {
+  balances:  BTreeMap<Currency, SignedDecimal>,
+  positions: BTreeMap<Product, Position>,
+  orders:    BTreeSet<Uuid>,
+  /// Client identifiers of orders sent but not yet seen or acknowledged
+  expected:  BTreeSet<Uuid>,
+  /// Fee and whether it was settled by the exchange, of each fill recorded
+  fills:     BTreeMap<(Product, u64, Uuid), (QuoteCurrencyAmount, bool)>,
+  fees:      FeeModel,
+}
+
+impl Ledger {
+  pub fn new() -> Ledger {
+    Ledger {
+      balances:  BTreeMap::new(),
+      positions: BTreeMap::new(),
+      orders:    BTreeSet::new(),
+      expected:  BTreeSet::new(),
+      fills:     BTreeMap::new(),
+      fees:      FeeModel::new(FeeSchedule::gdax()),
+    }
+  }
+
+  /// Start with `amount` of `currency`
+  pub fn deposit(mut self, currency: Currency, amount: Decimal) -> Ledger {
+    let balance = self.balance(currency) + SignedDecimal::from(amount);
+    self.balances.insert(currency, balance);
+    self
+  }
+
+  /// Charge fills from the feed, which don't include fees, at the rates of
+  /// `schedule` instead of the exchange's
+  pub fn fee_schedule(mut self, schedule: FeeSchedule) -> Ledger {
+    self.fees = FeeModel::new(schedule);
+    self
+  }
+
+  pub fn balance(&self, currency: Currency) -> SignedDecimal {
+    self.balances.get(&currency).cloned().unwrap_or_else(SignedDecimal::zero)
+  }
+
+  pub fn balances(&self) -> &BTreeMap<Currency, SignedDecimal> {
+    &self.balances
+  }
+
+  pub fn position(&self, product: Product) -> Option<&Position> {
+    self.positions.get(&product)
+  }
+
+  pub fn positions(&self) -> &BTreeMap<Product, Position> {
+    &self.positions
+  }
+
+  /// Count feed matches of order `id` as our fills, until it's done
+  pub fn track(&mut self, id: Uuid) {
+    self.orders.insert(id);
+  }
+
+  /// Track the order about to be sent with `client_oid` from its `received`
+  /// message, so that matches arriving before it's acknowledged are counted
+  pub fn expect(&mut self, client_oid: Uuid) {
+    self.expected.insert(client_oid);
+  }
+
+  /// Stop expecting `client_oid`, once its order is acknowledged or rejected
+  pub fn forget(&mut self, client_oid: Uuid) {
+    self.expected.remove(&client_oid);
+  }
+
+  /// Record a fill, returning false if it was already recorded. A settled
+  /// fill repeating one seen on the feed replaces its estimated fee with the
+  /// fee the exchange charged.
+  pub fn fill(&mut self, fill: &Fill) -> bool {
+    let key = (fill.product_id, fill.trade_id, fill.order_id);
+    if let Some(&(fee, settled)) = self.fills.get(&key) {
+      if fill.settled && !settled {
+        self.settle(fill, fee);
+      }
+      return false;
+    }
+    self.fills.insert(key, (fill.fee, fill.settled));
+
+    let base = SignedDecimal::from(Decimal::from(fill.size));
+    let notional = SignedDecimal::from(Decimal::from(fill.price.notional(fill.size)));
+    let fee = SignedDecimal::from(Decimal::from(fill.fee));
+    let (base, quote) = match fill.side {
+      Side::Buy  => (base, -notional - fee),
+      Side::Sell => (-base, notional - fee),
+    };
+
+    let product = fill.product_id;
+    self.fees.record(fill.created_at, product, fill.size);
+
+    let balance = self.balance(product.base) + base;
+    self.balances.insert(product.base, balance);
+    let balance = self.balance(product.quote) + quote;
+    self.balances.insert(product.quote, balance);
+
+    let position = self.positions.entry(product).or_insert_with(Position::flat);
+    position.trade(fill.side, fill.price, Decimal::from(fill.size));
+    position.fees = position.fees + fill.fee;
+    true
+  }
+
+  /// Charge the fee of settled `fill` in place of the `estimated` fee it was
+  /// recorded with
+  fn settle(&mut self, fill: &Fill, estimated: QuoteCurrencyAmount) {
+    let product = fill.product_id;
+    self.fills.insert((product, fill.trade_id, fill.order_id), (fill.fee, true));
+
+    let difference = SignedDecimal::from(Decimal::from(fill.fee)) - SignedDecimal::from(Decimal::from(estimated));
+    let balance = self.balance(product.quote) - difference;
+    self.balances.insert(product.quote, balance);
+
+    if let Some(position) = self.positions.get_mut(&product) {
+      position.fees = (Decimal::from(position.fees) - Decimal::from(estimated) + Decimal::from(fill.fee)).into();
+    }
+  }
+
+  /// Record matches of tracked orders, returning the new fills, track
+  /// expected orders once they're received, stop tracking orders that are
+  /// done, and follow market volume for fee tiers
+  pub fn apply(&mut self, message: &IncomingMessage) -> Vec<Fill> {
+    self.fees.observe(message);
+
+    let mut fills = vec![];
+    match *message {
+      IncomingMessage::Received{client_oid: Some(client_oid), order_id, ..} => {
+        if self.expected.remove(&client_oid) {
+          self.orders.insert(order_id);
+        }
+      }
+      IncomingMessage::Match{product_id, maker_order_id, taker_order_id, price, side, size, time, trade_id, ..} => {
+        for &(order_id, liquidity) in &[(maker_order_id, Liquidity::Maker), (taker_order_id, Liquidity::Taker)] {
+          if !self.orders.contains(&order_id) {
+            continue;
+          }
+          let side = match liquidity {
+            Liquidity::Maker => side,
+            Liquidity::Taker => side.opposite(),
+          };
+          let fee = self.fees.fee(time, product_id, liquidity, price, size);
+          let fill = Fill {
+            created_at: time,
+            settled:    false,
+            trade_id, product_id, order_id, price, size, side, liquidity, fee,
+          };
+          if self.fill(&fill) {
+            fills.push(fill);
+          }
+        }
+      }
+      IncomingMessage::Done{order_id, ..} => {
+        self.orders.remove(&order_id);
+      }
+      _ => {}
+    }
+    fills
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{TimeZone, Utc};
+  use gdax::fixtures::{id, match_text};
+  use serde_json;
+
+  fn fill(trade_id: u64, side: Side, price: &str, size: &str, fee: &str) -> Fill {
+    Fill {
+      trade_id,
+      product_id: "BTC-USD".parse().unwrap(),
+      order_id:   Uuid::nil(),
+      price:      price.parse().unwrap(),
+      size:       size.parse().unwrap(),
+      liquidity:  Liquidity::Maker,
+      fee:        fee.parse().unwrap(),
+      created_at: Utc.timestamp(0, 0),
+      settled:    true,
+      side,
+    }
+  }
+
+  #[test]
+  fn average_and_realized() {
+    let product = "BTC-USD".parse().unwrap();
+    let mut ledger = Ledger::new();
+
+    ledger.fill(&fill(1, Side::Buy, "100.00", "1.0", "0"));
+    ledger.fill(&fill(2, Side::Buy, "102.00", "1.0", "0"));
+    assert_eq!(ledger.position(product).unwrap().average_price, Some("101".parse().unwrap()));
+
+    ledger.fill(&fill(3, Side::Sell, "105.00", "3.0", "0.5"));
+    {
+      let position = ledger.position(product).unwrap();
+      assert_eq!(position.size, "-1".parse().unwrap());
+      assert_eq!(position.average_price, Some("105".parse().unwrap()));
+      assert_eq!(position.realized_pnl, "8".parse().unwrap());
+      assert_eq!(position.unrealized_pnl("104".parse().unwrap()), "1".parse().unwrap());
+      assert_eq!(position.pnl(Some("104".parse().unwrap())), "8.5".parse().unwrap());
+    }
+
+    ledger.fill(&fill(4, Side::Buy, "100.00", "1.0", "0"));
+    let position = ledger.position(product).unwrap();
+    assert!(position.size.is_zero());
+    assert_eq!(position.average_price, None);
+    assert_eq!(position.realized_pnl, "13".parse().unwrap());
+    assert_eq!(position.fees, "0.5".parse().unwrap());
+
+    assert!(ledger.balance("BTC".parse().unwrap()).is_zero());
+    assert_eq!(ledger.balance("USD".parse().unwrap()), "12.5".parse().unwrap());
+  }
+
+  #[test]
+  fn feed() {
+    let mut ledger = Ledger::new()
+      .deposit("USD".parse().unwrap(), "1000".parse().unwrap())
+      .fee_schedule(FeeSchedule::flat("0.001".parse().unwrap(), "0.003".parse().unwrap()));
+    ledger.track(id(1));
+
+    let message = |text: &str| serde_json::from_str::<IncomingMessage>(text).unwrap();
+    let trade = |trade_id| message(&match_text(trade_id, id(2), id(1), "sell", "100.00", "2.0"));
+
+    assert_eq!(ledger.apply(&trade(1)).len(), 1);
+    assert_eq!(ledger.balance("BTC".parse().unwrap()), "2".parse().unwrap());
+    assert_eq!(ledger.balance("USD".parse().unwrap()), "799.4".parse().unwrap());
+
+    let mut rest = fill(1, Side::Buy, "100.00", "2.0", "0.6");
+    rest.order_id = id(1);
+    assert!(!ledger.fill(&rest));
+
+    ledger.apply(&message(r#"{"type": "done", "product_id": "BTC-USD", "sequence": 2,
+      "order_id": "00000000-0000-0000-0000-000000000001", "side": "buy", "reason": "filled",
+      "time": "2017-10-01T00:00:00Z"}"#));
+    assert!(ledger.apply(&trade(2)).is_empty());
+    assert_eq!(ledger.balance("BTC".parse().unwrap()), "2".parse().unwrap());
+  }
+
+  #[test]
+  fn settled_fee() {
+    let product = "BTC-USD".parse().unwrap();
+    let mut ledger = Ledger::new()
+      .deposit("USD".parse().unwrap(), "1000".parse().unwrap())
+      .fee_schedule(FeeSchedule::flat("0.001".parse().unwrap(), "0.003".parse().unwrap()));
+    ledger.track(id(1));
+
+    let trade = match_text(1, id(1), id(2), "buy", "100.00", "2.0");
+    assert_eq!(ledger.apply(&serde_json::from_str(&trade).unwrap()).len(), 1);
+    assert_eq!(ledger.balance("USD".parse().unwrap()), "799.8".parse().unwrap());
+    assert_eq!(ledger.position(product).unwrap().fees, "0.2".parse().unwrap());
+
+    let mut rest = fill(1, Side::Buy, "100.00", "2.0", "0.05");
+    rest.order_id = id(1);
+    assert!(!ledger.fill(&rest));
+    assert_eq!(ledger.balance("USD".parse().unwrap()), "799.95".parse().unwrap());
+    assert_eq!(ledger.position(product).unwrap().fees, "0.05".parse().unwrap());
+
+    rest.fee = "0.5".parse().unwrap();
+    assert!(!ledger.fill(&rest));
+    assert_eq!(ledger.balance("USD".parse().unwrap()), "799.95".parse().unwrap());
+  }
+
+  #[test]
+  fn expected() {
+    let client_oid = "00000000-0000-0000-0000-0000000000c1".parse().unwrap();
+    let mut ledger = Ledger::new();
+    ledger.expect(client_oid);
+
+    let message = |text: &str| serde_json::from_str::<IncomingMessage>(text).unwrap();
+    ledger.apply(&message(r#"{"type": "received", "product_id": "BTC-USD", "sequence": 1,
+      "client_oid": "00000000-0000-0000-0000-0000000000c1", "order_id": "00000000-0000-0000-0000-000000000001",
+      "order_type": "limit", "side": "buy", "price": "100.00", "size": "1.0", "time": "2017-10-01T00:00:00Z"}"#));
+    let fills = ledger.apply(&message(&match_text(2, id(1), id(2), "buy", "100.00", "0.5")));
+
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].order_id, id(1));
+    assert_eq!(ledger.balance("BTC".parse().unwrap()), "0.5".parse().unwrap());
+  }
+}
@@ -1,17 +1,24 @@
 #![recursion_limit = "1024"]
 
+#[macro_use] extern crate diesel;
 #[macro_use] extern crate error_chain;
 #[macro_use] extern crate log;
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate futures;
+extern crate base64;
+extern crate byteorder;
 extern crate chrono;
 extern crate extprim;
+extern crate hmac;
+extern crate hyper;
+extern crate hyper_tls;
 extern crate clap;
 extern crate exit_code;
 extern crate num;
 extern crate pretty_env_logger;
 extern crate serde;
 extern crate serde_json;
+extern crate sha2;
 extern crate tokio_core;
 extern crate uuid;
 extern crate websocket;
@@ -25,13 +32,27 @@ use std::io::prelude::*;
 mod errors;
 mod cli;
 mod record;
+mod replay;
+mod mock_feed;
+mod order;
+mod backtest;
 mod decimal;
+mod database;
+mod engine;
 mod gdax;
+mod ledger;
+mod report;
+mod risk;
+mod strategy;
 
 use errors::*;
 
 use cli::Arguments;
 use record::record;
+use replay::replay;
+use mock_feed::mock_feed;
+use order::{cancel, place};
+use backtest::backtest;
 
 fn run<I, T>(arguments: I, current_dir: Result<PathBuf, io::Error>) -> Result<(), Error>
   where I: IntoIterator<Item = T>,
@@ -47,7 +68,18 @@ fn run<I, T>(arguments: I, current_dir: Result<PathBuf, io::Error>) -> Result<()
 
   use cli::Command::*;
   match arguments.command {
-    Record{sandbox} => record(sandbox),
+    Record{sandbox, feed_url, rest_url, database, capture, resync_on_gap, products, products_file, channels, credentials}
+      => record(sandbox, feed_url, rest_url, database, capture, resync_on_gap, products, products_file, channels, credentials),
+    Replay{capture, database, products, start, end, speed}
+      => replay(capture, database, products, start, end, speed),
+    MockFeed{port, script, capture, interval, credentials}
+      => mock_feed(port, script, capture, interval, credentials),
+    Place{sandbox, rest_url, credentials, products_file, risk_limits, dry_run, order}
+      => place(sandbox, rest_url, credentials, products_file, risk_limits, dry_run, order),
+    Cancel{sandbox, rest_url, credentials, dry_run, order_id, product}
+      => cancel(sandbox, rest_url, credentials, dry_run, order_id, product),
+    Backtest{capture, products_file, product, size, interval, latency, queue, fee_schedule, fee_tier, risk_limits, output}
+      => backtest(capture, products_file, product, size, interval, latency, queue, fee_schedule, fee_tier, risk_limits, output),
   }
 }
 
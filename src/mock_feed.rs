@@ -0,0 +1,48 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+
+use gdax::auth::Credentials;
+use gdax::feed::mock::{read_capture, read_script, MockFeed};
+
+use errors::*;
+
+pub fn mock_feed(
+  port:        u16,
+  script:      Option<PathBuf>,
+  capture:     Option<PathBuf>,
+  interval:    Option<Duration>,
+  credentials: Option<PathBuf>,
+) -> Result<(), Error> {
+  info!("command: mock-feed");
+  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
+
+  let events = match (script, capture) {
+    (Some(path), _) => read_script(&path)
+      .chain_err(|| format!("failed to read script file: {}", path.display()))?,
+    (None, Some(path)) => read_capture(&path)
+      .chain_err(|| format!("failed to read capture file: {}", path.display()))?,
+    (None, None) => vec![],
+  };
+
+  let mut mock = MockFeed::new().script(events);
+  if let Some(interval) = interval {
+    mock = mock.interval(interval);
+  }
+
+  if let Some(path) = credentials {
+    let credentials = Credentials::load(&path)
+      .chain_err(|| format!("failed to load credentials file: {}", path.display()))?;
+    mock = mock.credentials(credentials);
+  }
+
+  let server = mock.bind(&SocketAddr::from(([127, 0, 0, 1], port)), &core.handle())
+    .chain_err(|| format!("failed to listen on port {}", port))?;
+
+  println!("Serving mock feed at {}", server.url());
+
+  core.run(server).chain_err(|| "mock feed server failed")?;
+
+  Ok(())
+}
@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+use tokio_core::reactor::{Core, Handle};
+use uuid::Uuid;
+use websocket::url::Url;
+
+use gdax::auth::Credentials;
+use gdax::feed::message::{IncomingMessage, OrderBookSnapshot, Product};
+use gdax::order_entry::{DryRunOrderEntry, OrderEntry, RestOrderEntry};
+use gdax::products::Products;
+use gdax::rest::{RestBuilder, RestClient};
+use gdax::rest::message::{AggregatedLevel, BookLevel, NewOrder};
+use risk::{CheckedOrderEntry, RiskLimits};
+
+use errors::*;
+
+fn rest_client(
+  handle:      &Handle,
+  sandbox:     bool,
+  rest_url:    Option<Url>,
+  credentials: Option<PathBuf>,
+  dry_run:     bool,
+) -> Result<RestClient, Error> {
+  let mut builder = RestBuilder::new().sandbox(sandbox);
+  if let Some(url) = rest_url {
+    builder = builder.url(url.as_str());
+  }
+
+  let credentials = match credentials {
+    Some(path) => Some(Credentials::load(&path)
+      .chain_err(|| format!("failed to load credentials file: {}", path.display()))?),
+    None => Credentials::from_env()?,
+  };
+
+  match credentials {
+    Some(credentials) => builder = builder.credentials(credentials),
+    None => if !dry_run {
+      return Err("sending orders requires credentials".into());
+    },
+  }
+
+  Ok(builder.build(handle)?)
+}
+
+fn order_entry(rest: RestClient, products: Products, dry_run: bool) -> Box<OrderEntry> {
+  if dry_run {
+    Box::new(DryRunOrderEntry::new(products))
+  } else {
+    Box::new(RestOrderEntry::new(rest, products))
+  }
+}
+
+pub fn place(
+  sandbox:       bool,
+  rest_url:      Option<Url>,
+  credentials:   Option<PathBuf>,
+  products_file: Option<PathBuf>,
+  risk_limits:   Option<PathBuf>,
+  dry_run:       bool,
+  order:         NewOrder,
+) -> Result<(), Error> {
+  info!("command: place");
+  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
+
+  let rest = rest_client(&core.handle(), sandbox, rest_url, credentials, dry_run)?;
+
+  let products = match products_file {
+    Some(path) => Products::load(&path)
+      .chain_err(|| format!("failed to load products file: {}", path.display()))?,
+    None => {
+      let listing = core.run(rest.products()).chain_err(|| "failed to fetch products listing")?;
+      Products::new(listing)?
+    }
+  };
+
+  let placed = match risk_limits {
+    Some(path) => {
+      let limits = RiskLimits::load(&path)
+        .chain_err(|| format!("failed to load risk limits file: {}", path.display()))?;
+      let product_id = order.product_id;
+      let book = core.run(rest.book(product_id, BookLevel::Best))
+        .chain_err(|| format!("failed to fetch {} book", product_id))?;
+      let levels = |levels: Vec<AggregatedLevel>| levels.into_iter()
+        .map(|AggregatedLevel(price, size, _)| (price, size))
+        .collect();
+      let snapshot = OrderBookSnapshot{product_id, bids: levels(book.bids), asks: levels(book.asks)};
+
+      let mut entry = CheckedOrderEntry::new(order_entry(rest, products, dry_run), limits);
+      entry.observe(&IncomingMessage::Snapshot(snapshot));
+      core.run(entry.place(order))?
+    }
+    None => core.run(order_entry(rest, products, dry_run).place(order))?,
+  };
+
+  println!("{}order {} client_oid {}", if dry_run { "dry run: " } else { "" }, placed.id, placed.client_oid);
+
+  Ok(())
+}
+
+pub fn cancel(
+  sandbox:     bool,
+  rest_url:    Option<Url>,
+  credentials: Option<PathBuf>,
+  dry_run:     bool,
+  order_id:    Option<Uuid>,
+  product:     Option<Product>,
+) -> Result<(), Error> {
+  info!("command: cancel");
+  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
+
+  let rest = rest_client(&core.handle(), sandbox, rest_url, credentials, dry_run)?;
+
+  // Cancellation doesn't validate against products, so none are loaded
+  let mut entry = order_entry(rest, Products::default(), dry_run);
+
+  let canceled = match order_id {
+    Some(id) => {
+      core.run(entry.cancel(id))?;
+      vec![id]
+    }
+    None => core.run(entry.cancel_all(product))?,
+  };
+
+  for id in canceled {
+    println!("{}canceled {}", if dry_run { "dry run: " } else { "" }, id);
+  }
+
+  Ok(())
+}
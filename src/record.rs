@@ -1,56 +1,255 @@
 use futures::prelude::*;
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 use tokio_core::reactor::Core;
+use websocket::url::Url;
 
 use gdax::feed::message::*;
 use gdax::order_book::OrderBook;
+use gdax::products::Products;
+use gdax::rest::RestBuilder;
+use gdax::sequence::{SequenceCounts, SequenceTracker};
 
+use gdax::auth::Credentials;
 use gdax::feed::FeedBuilder;
+use gdax::feed::reconnect::{FeedEvent, ReconnectingFeed, ResyncHandle};
+use gdax::feed::capture::CaptureWriter;
+
+use database::Database;
 
 use errors::*;
 
-pub fn record(sandbox: bool) -> Result<(), Error> {
-  info!("command: record");
-  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
-  let mut order_books = BTreeMap::new();
+/// Maintains order books and checks sequence numbers, whether live or
+/// replayed
+pub struct Recorder {
+  order_books: BTreeMap<Product, OrderBook>,
+  sequences:   SequenceTracker,
+  resync:      Option<ResyncHandle>,
+}
 
-  let feed_future = FeedBuilder::new()
-    .sandbox(sandbox)
-    .subscribe_to_all()
-    .connect(&core.handle());
+impl Recorder {
+  pub fn new() -> Recorder {
+    Recorder {
+      order_books: BTreeMap::new(),
+      sequences:   SequenceTracker::new(),
+      resync:      None,
+    }
+  }
 
-  let feed = core.run(feed_future.unwrap()).unwrap();
+  /// Resync the feed with `resync` when an order book becomes invalid, which
+  /// is done whether or not `resync_on_gap` is set
+  pub fn resync(mut self, resync: ResyncHandle) -> Recorder {
+    self.resync = Some(resync);
+    self
+  }
 
-  let (sink, stream) = feed.split();
+  /// Also resync the feed when a sequence gap is detected
+  pub fn resync_on_gap(mut self, resync_on_gap: bool) -> Recorder {
+    self.sequences = self.sequences.resync_on_gap(resync_on_gap);
+    self
+  }
+
+  pub fn sequence_counts(&self) -> &BTreeMap<Product, SequenceCounts> {
+    self.sequences.counts()
+  }
+
+  /// Discard order books after messages may have been missed, since they will
+  /// be rebuilt from fresh snapshots
+  pub fn discontinuity(&mut self) {
+    warn!("discarding order books after feed discontinuity");
+    self.order_books.clear();
+    self.sequences.reset();
+  }
+
+  pub fn event(&mut self, event: FeedEvent) -> Result<(), Error> {
+    match event {
+      FeedEvent::Message(message) => self.record(message),
+      FeedEvent::Discontinuity    => Ok(self.discontinuity()),
+    }
+  }
 
-  let recorder = stream.filter_map(|message| match message {
-    IncomingMessage::Snapshot(snapshot) => {
-      info!("order book snapshot from GDAX: {:?}", snapshot);
-      order_books.insert(snapshot.product_id, OrderBook::from_snapshot(&snapshot));
-      None
+  pub fn record(&mut self, message: IncomingMessage) -> Result<(), Error> {
+    if let Some(event) = self.sequences.check(&message) {
+      warn!("sequence error: {:?}", event);
+      if self.sequences.should_resync(&event) {
+        if let Some(ref resync) = self.resync {
+          resync.resync();
+        }
+      }
     }
-    IncomingMessage::L2update(update) => {
-      info!("order book update from GDAX: {:?}", update);
-      order_books
-        .get_mut(&update.product_id)
-        .ok_or_else(|| format!("No orderbook for {:?} but had no order book", update.product_id))
-        // todo: deal with this
-        .expect("Got update for missing orderbook")
-        .update(&update);
-      None
+
+    match message {
+      IncomingMessage::Snapshot(snapshot) => {
+        info!("order book snapshot from GDAX: {:?}", snapshot);
+        self.order_books.insert(snapshot.product_id, OrderBook::from_snapshot(&snapshot));
+      }
+      IncomingMessage::L2update(update) => {
+        info!("order book update from GDAX: {:?}", update);
+        let result = match self.order_books.get_mut(&update.product_id) {
+          Some(order_book) => order_book.update(&update),
+          None => {
+            warn!("ignoring update for {} without order book", update.product_id);
+            Ok(())
+          }
+        };
+        if let Err(error) = result {
+          error!("discarding invalid {} order book: {}", update.product_id, error);
+          self.order_books.remove(&update.product_id);
+          if let Some(ref resync) = self.resync {
+            resync.resync();
+          }
+        }
+      }
+      IncomingMessage::Error{message} => {
+        error!("error message from GDAX: {}", message);
+      }
+      other => {
+        info!("message from GDAX: {:?}", other);
+      }
     }
-    IncomingMessage::Error{message} => {
-      error!("error message from GDAX: {}", message);
-      None
+
+    Ok(())
+  }
+}
+
+pub fn record(
+  sandbox:       bool,
+  feed_url:      Option<Url>,
+  rest_url:      Option<Url>,
+  database:      Option<PathBuf>,
+  capture:       Option<PathBuf>,
+  resync_on_gap: bool,
+  products:      Vec<Product>,
+  products_file: Option<PathBuf>,
+  channels:      Vec<Channel>,
+  credentials:   Option<PathBuf>,
+) -> Result<(), Error> {
+  info!("command: record");
+  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
+
+  let mut recorder = Recorder::new();
+
+  let products = if !products.is_empty() {
+    products
+  } else if let Some(path) = products_file {
+    Products::load(&path)
+      .chain_err(|| format!("failed to load products file: {}", path.display()))?
+      .ids()
+  } else {
+    let mut rest = RestBuilder::new().sandbox(sandbox);
+    match rest_url {
+      Some(url) => rest = rest.url(url.as_str()),
+      None => if feed_url.is_some() {
+        return Err("a custom feed URL requires --product, --products-file, or --rest-url, \
+                    instead of fetching the products listing from GDAX".into());
+      },
     }
-    other => {
-      info!("message from GDAX: {:?}", other);
-      None
+    let rest = rest.build(&core.handle())?;
+    let listing = core.run(rest.products()).chain_err(|| "failed to fetch products listing")?;
+    Products::new(listing)?.ids()
+  };
+  let channels = if channels.is_empty() { Channel::all() } else { channels };
+
+  let mut builder = FeedBuilder::new().sandbox(sandbox);
+  for &channel in &channels {
+    for &product in &products {
+      builder = builder.subscribe(channel, product);
     }
-  }).forward(sink);
+  }
+
+  let credentials = match credentials {
+    Some(path) => Some(Credentials::load(&path)
+      .chain_err(|| format!("failed to load credentials file: {}", path.display()))?),
+    None => Credentials::from_env()?,
+  };
+
+  match credentials {
+    Some(credentials) => builder = builder.credentials(credentials),
+    None => if channels.contains(&Channel::User) {
+      return Err("subscribing to the user channel requires credentials".into());
+    },
+  }
+
+  if let Some(url) = feed_url {
+    builder = builder.url(url);
+  }
+
+  if let Some(path) = capture {
+    let writer = CaptureWriter::open(&path)
+      .chain_err(|| format!("failed to open capture file: {}", path.display()))?;
+    builder = builder.capture(writer);
+  }
+
+  if let Some(path) = database {
+    builder = builder.frame_sink(Database::open(&path)?);
+  }
+
+  let feed = ReconnectingFeed::new(builder, &core.handle())
+    .chain_err(|| "failed to connect to feed")?;
+
+  recorder = recorder
+    .resync(feed.resync_handle())
+    .resync_on_gap(resync_on_gap);
+
+  let (sink, stream) = feed.split();
+
+  let recording = stream
+    .map_err(Error::from)
+    .and_then(|event| recorder.event(event))
+    .filter_map(|()| None)
+    .forward(sink.sink_map_err(Error::from));
 
-  // todo: deal with this
-  core.run(recorder).unwrap();
+  core.run(recording.map(|_| ()))?;
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use gdax::feed::mock::{MockEvent, MockFeed};
+  use gdax::fixtures::open_event;
+  use std::path::Path;
+
+  #[test]
+  fn resync_on_gap() {
+    let mut core = Core::new().unwrap();
+
+    let server = MockFeed::new()
+      .script(vec![
+        MockEvent::Text(r#"{"type": "snapshot", "product_id": "BTC-USD",
+          "bids": [["100.0", "1.0"]], "asks": [["101.0", "1.0"]]}"#.to_string()),
+        MockEvent::Text(r#"{"type": "l2update", "product_id": "BTC-USD",
+          "changes": [["buy", "100.5", "2.0"]]}"#.to_string()),
+        MockEvent::Ping,
+        open_event(1),
+        MockEvent::Gap{count: 1},
+        open_event(2),
+        open_event(3),
+      ])
+      .script(vec![open_event(10), open_event(11)])
+      .bind(&"127.0.0.1:0".parse().unwrap(), &core.handle())
+      .unwrap();
+    let url = server.url();
+    let received = server.received();
+    core.handle().spawn(server.map_err(|error| panic!("mock feed failed: {}", error)));
+
+    let builder = FeedBuilder::new()
+      .url(url)
+      .subscribe_to_all(&["BTC-USD".parse().unwrap()])
+      .frame_sink(Database::open(Path::new(":memory:")).unwrap());
+    let feed = ReconnectingFeed::new(builder, &core.handle()).unwrap();
+    let mut recorder = Recorder::new()
+      .resync(feed.resync_handle())
+      .resync_on_gap(true);
+
+    // subscriptions, snapshot, update, 1, 3, discontinuity, subscriptions, 10, 11
+    core.run(feed.take(9).map_err(Error::from).for_each(|event| recorder.event(event))).unwrap();
+
+    assert_eq!(
+      recorder.sequence_counts()[&"BTC-USD".parse().unwrap()],
+      SequenceCounts{gaps: 1, missing: 1, ..Default::default()},
+    );
+    assert_eq!(received.borrow().len(), 2);
+  }
+}
@@ -0,0 +1,64 @@
+use futures::prelude::*;
+use std::path::PathBuf;
+use tokio_core::reactor::Core;
+
+use gdax::feed::message::{DateTime, Product};
+use gdax::feed::replay::ReplayBuilder;
+
+use database::Database;
+use record::Recorder;
+
+use errors::*;
+
+pub fn replay(
+  capture:  PathBuf,
+  database: Option<PathBuf>,
+  products: Vec<Product>,
+  start:    Option<DateTime>,
+  end:      Option<DateTime>,
+  speed:    Option<f64>,
+) -> Result<(), Error> {
+  info!("command: replay");
+  let mut core = Core::new().chain_err(|| "failed to create tokio Core")?;
+
+  let mut recorder = Recorder::new();
+
+  let mut builder = ReplayBuilder::new();
+  for product in products {
+    builder = builder.product(product);
+  }
+  if let Some(start) = start {
+    builder = builder.start(start);
+  }
+  if let Some(end) = end {
+    builder = builder.end(end);
+  }
+  if let Some(speed) = speed {
+    builder = builder.speed(speed);
+  }
+  if let Some(path) = database {
+    builder = builder.frame_sink(Database::open(&path)?);
+  }
+
+  let replay = builder.open(&capture, &core.handle())
+    .chain_err(|| format!("failed to open capture file: {}", capture.display()))?;
+
+  let replaying = replay
+    .map_err(Error::from)
+    .for_each(|message| recorder.record(message));
+
+  core.run(replaying)?;
+
+  for (product, counts) in recorder.sequence_counts() {
+    if counts.is_complete() {
+      println!("{}: complete", product);
+    } else {
+      println!(
+        "{}: {} gaps ({} missing messages), {} duplicates, {} out of order",
+        product, counts.gaps, counts.missing, counts.duplicates, counts.out_of_order,
+      );
+    }
+  }
+
+  Ok(())
+}
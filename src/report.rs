@@ -0,0 +1,239 @@
+use std::io::{self, Write};
+use serde_json;
+
+use decimal::{Decimal, SignedDecimal};
+use engine::Context;
+use ledger::Position;
+use gdax::feed::message::{DateTime, Product, QuoteCurrencyAmount, QuoteCurrencyPrice, Side, Ticker};
+use gdax::order_book::OrderBook;
+use gdax::rest::message::{Fill, Liquidity, Trade};
+use strategy::{Intent, Strategy};
+
+/// Position and P&L of a strategy at a point in time
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Sample {
+  pub time:      DateTime,
+  /// Mid price the position is marked to, if the book has both sides
+  pub mid:       Option<QuoteCurrencyPrice>,
+  pub inventory: SignedDecimal,
+  pub cash:      SignedDecimal,
+  pub pnl:       SignedDecimal,
+}
+
+/// Results of running a strategy over one product
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+  pub product:      Product,
+  pub fills:        Vec<Fill>,
+  pub samples:      Vec<Sample>,
+  pub fees:         QuoteCurrencyAmount,
+  /// Total notional value traded
+  pub turnover:     QuoteCurrencyAmount,
+  pub inventory:    SignedDecimal,
+  /// Final position
+  pub position:     Position,
+  pub pnl:          SignedDecimal,
+  /// Largest fall in P&L from a previous peak
+  pub max_drawdown: Decimal,
+  /// Mean over standard deviation of the change in P&L between samples,
+  /// not annualized
+  pub sharpe:       Option<f64>,
+}
+
+impl Report {
+  pub fn write_json<W: Write>(&self, writer: W) -> io::Result<()> {
+    serde_json::to_writer_pretty(writer, self).map_err(io::Error::from)
+  }
+
+  pub fn write_pnl_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "time,mid,inventory,cash,pnl")?;
+    for sample in &self.samples {
+      writeln!(
+        writer, "{},{},{},{},{}",
+        sample.time.to_rfc3339(),
+        sample.mid.map(|mid| mid.to_string()).unwrap_or_default(),
+        sample.inventory, sample.cash, sample.pnl,
+      )?;
+    }
+    Ok(())
+  }
+
+  pub fn write_fills_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    writeln!(writer, "time,trade_id,order_id,side,price,size,liquidity,fee")?;
+    for fill in &self.fills {
+      let side = match fill.side {
+        Side::Buy  => "buy",
+        Side::Sell => "sell",
+      };
+      let liquidity = match fill.liquidity {
+        Liquidity::Maker => "maker",
+        Liquidity::Taker => "taker",
+      };
+      writeln!(
+        writer, "{},{},{},{},{},{},{},{}",
+        fill.created_at.to_rfc3339(), fill.trade_id, fill.order_id, side, fill.price, fill.size, liquidity,
+        fill.fee,
+      )?;
+    }
+    Ok(())
+  }
+}
+
+/// Wraps a strategy, tracking the fills it produces in one product and
+/// sampling its position in the engine's ledger on each timer tick.
+pub struct Reporter<S> {
+  strategy: S,
+  product:  Product,
+  fills:    Vec<Fill>,
+  samples:  Vec<Sample>,
+  mid:      Option<QuoteCurrencyPrice>,
+  turnover: QuoteCurrencyAmount,
+}
+
+impl<S: Strategy> Reporter<S> {
+  pub fn new(strategy: S, product: Product) -> Reporter<S> {
+    Reporter {
+      fills:    vec![],
+      samples:  vec![],
+      mid:      None,
+      turnover: QuoteCurrencyAmount::zero(),
+      strategy, product,
+    }
+  }
+
+  /// Position at `time`, marked to the latest mid price
+  fn mark(&self, context: &Context, time: DateTime) -> Sample {
+    let mid = context.book(self.product).and_then(OrderBook::mid_price).or(self.mid);
+    let position = self.position(context);
+
+    Sample {
+      inventory: position.size,
+      cash:      context.ledger().balance(self.product.quote),
+      pnl:       position.pnl(mid),
+      time, mid,
+    }
+  }
+
+  fn position(&self, context: &Context) -> Position {
+    context.ledger().position(self.product).cloned().unwrap_or_else(Position::flat)
+  }
+
+  /// Summarize the run, with a final sample at the current time that
+  /// includes any fills since the last one
+  pub fn report(&self, context: &Context) -> Report {
+    let mut samples = self.samples.clone();
+    if let Some(now) = context.now() {
+      if samples.last().map(|sample| sample.time == now).unwrap_or(false) {
+        samples.pop();
+      }
+      samples.push(self.mark(context, now));
+    }
+
+    let mut peak = None;
+    let mut max_drawdown = Decimal::zero();
+    for sample in &samples {
+      let peak = peak.get_or_insert(sample.pnl);
+      if sample.pnl > *peak {
+        *peak = sample.pnl;
+      }
+      if let Some(drawdown) = (*peak - sample.pnl).to_unsigned() {
+        if drawdown > max_drawdown {
+          max_drawdown = drawdown;
+        }
+      }
+    }
+
+    let position = self.position(context);
+    let pnl = samples.last()
+      .map(|sample| sample.pnl)
+      .unwrap_or_else(|| position.pnl(self.mid));
+
+    Report {
+      product:   self.product,
+      fills:     self.fills.clone(),
+      fees:      position.fees,
+      turnover:  self.turnover,
+      inventory: position.size,
+      sharpe:    sharpe(&samples),
+      position, pnl, max_drawdown, samples,
+    }
+  }
+}
+
+fn sharpe(samples: &[Sample]) -> Option<f64> {
+  let pnl = samples.iter()
+    .map(|sample| sample.pnl.to_string().parse::<f64>().ok())
+    .collect::<Option<Vec<f64>>>()?;
+
+  let returns = pnl.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<f64>>();
+  if returns.len() < 2 {
+    return None;
+  }
+
+  let n = returns.len() as f64;
+  let mean = returns.iter().sum::<f64>() / n;
+  let variance = returns.iter().map(|r| (r - mean) * (r - mean)).sum::<f64>() / n;
+  if variance == 0.0 {
+    return None;
+  }
+  Some(mean / variance.sqrt())
+}
+
+impl<S: Strategy> Strategy for Reporter<S> {
+  fn on_book(&mut self, context: &Context, product: Product, book: &OrderBook) -> Vec<Intent> {
+    self.strategy.on_book(context, product, book)
+  }
+
+  fn on_trade(&mut self, context: &Context, product: Product, trade: &Trade) -> Vec<Intent> {
+    self.strategy.on_trade(context, product, trade)
+  }
+
+  fn on_ticker(&mut self, context: &Context, ticker: &Ticker) -> Vec<Intent> {
+    self.strategy.on_ticker(context, ticker)
+  }
+
+  fn on_timer(&mut self, context: &Context, now: DateTime) -> Vec<Intent> {
+    let sample = self.mark(context, now);
+    self.mid = sample.mid;
+    self.samples.push(sample);
+    self.strategy.on_timer(context, now)
+  }
+
+  fn on_fill(&mut self, context: &Context, fill: &Fill) -> Vec<Intent> {
+    if fill.product_id == self.product {
+      self.turnover = self.turnover + fill.price.notional(fill.size);
+      self.fills.push(fill.clone());
+    }
+    self.strategy.on_fill(context, fill)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{TimeZone, Utc};
+
+  fn sample(seconds: i64, pnl: &str) -> Sample {
+    Sample {
+      time:      Utc.timestamp(seconds, 0),
+      mid:       None,
+      inventory: SignedDecimal::zero(),
+      cash:      pnl.parse().unwrap(),
+      pnl:       pnl.parse().unwrap(),
+    }
+  }
+
+  #[test]
+  fn drawdown_and_sharpe() {
+    struct Idle;
+    impl Strategy for Idle {}
+
+    let mut reporter = Reporter::new(Idle, "BTC-USD".parse().unwrap());
+    reporter.samples = vec![sample(0, "0"), sample(1, "2"), sample(2, "-1"), sample(3, "1"), sample(4, "3")];
+    let report = reporter.report(&Context::default());
+
+    assert_eq!(report.max_drawdown, "3".parse().unwrap());
+    assert_eq!(report.pnl, "3".parse().unwrap());
+    assert_eq!(report.sharpe, Some(0.75 / 4.6875f64.sqrt()));
+  }
+}
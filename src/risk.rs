@@ -0,0 +1,541 @@
+use chrono::{Duration, Utc};
+use futures::future;
+use futures::prelude::*;
+use serde_json;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::rc::Rc;
+use uuid::Uuid;
+
+use decimal::{Decimal, Rounding, SignedDecimal};
+use engine::Context;
+use gdax::feed::message::*;
+use gdax::order_entry::{OrderEntry, OrderError, OrderEvent, OrderFuture, PlacedOrder};
+use gdax::rest::message::NewOrder;
+
+/// Pre-trade limits on the orders a strategy may send. Absent limits aren't
+/// checked.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskLimits {
+  /// Largest size of a single order, per product
+  pub max_order_size:          BTreeMap<Product, BaseCurrencyAmount>,
+  /// Largest value of a single order, at its limit price or the mid price
+  pub max_notional:            Option<QuoteCurrencyAmount>,
+  pub max_open_orders:         Option<usize>,
+  /// Largest long or short position, per product, if every open order on
+  /// the same side filled
+  pub max_position:            BTreeMap<Product, BaseCurrencyAmount>,
+  /// Largest distance of a limit price from the mid price, as a fraction
+  /// of the mid, e.g. `0.05` for 5%
+  pub price_band:              Option<Decimal>,
+  /// Most places and cancels sent in any one second
+  pub max_messages_per_second: Option<usize>,
+  /// Longest gap between timestamped feed messages
+  pub stale_after_seconds:     Option<u32>,
+}
+
+impl RiskLimits {
+  /// Load limits from the JSON file at `path`
+  pub fn load(path: &Path) -> Result<RiskLimits, RiskLimitsError> {
+    let mut json = Vec::new();
+    File::open(path)
+      .and_then(|mut file| file.read_to_end(&mut json))
+      .map_err(RiskLimitsError::Io)?;
+    serde_json::from_slice(&json).map_err(RiskLimitsError::Deserialization)
+  }
+}
+
+#[derive(Debug)]
+pub enum RiskLimitsError {
+  Deserialization(serde_json::Error),
+  Io(io::Error),
+}
+
+impl Display for RiskLimitsError {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      RiskLimitsError::Deserialization(ref error) => write!(f, "failed to deserialize risk limits: {}", error),
+      RiskLimitsError::Io(ref error)              => write!(f, "I/O error: {}", error),
+    }
+  }
+}
+
+impl Error for RiskLimitsError {
+  fn description(&self) -> &str {
+    match *self {
+      RiskLimitsError::Deserialization(_) => "failed to deserialize risk limits",
+      RiskLimitsError::Io(_)              => "I/O error",
+    }
+  }
+
+  fn cause(&self) -> Option<&Error> {
+    match *self {
+      RiskLimitsError::Deserialization(ref error) => Some(error),
+      RiskLimitsError::Io(ref error)              => Some(error),
+    }
+  }
+}
+
+/// A breached risk limit
+#[derive(Debug, Clone, PartialEq)]
+pub enum RiskViolation {
+  OrderSize{product: Product, size: BaseCurrencyAmount, max: BaseCurrencyAmount},
+  Notional{notional: QuoteCurrencyAmount, max: QuoteCurrencyAmount},
+  /// There was no mid price to value or check an order against
+  NoMidPrice(Product),
+  OpenOrders{max: usize},
+  Position{product: Product, position: SignedDecimal, max: BaseCurrencyAmount},
+  PriceBand{price: QuoteCurrencyPrice, mid: QuoteCurrencyPrice},
+  MessageRate{max: usize},
+  StaleFeed{seconds: i64},
+}
+
+impl Display for RiskViolation {
+  fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      RiskViolation::OrderSize{product, size, max} =>
+        write!(f, "order size {} exceeds {} limit of {}", size, product, max),
+      RiskViolation::Notional{notional, max} =>
+        write!(f, "order notional {} exceeds limit of {}", notional, max),
+      RiskViolation::NoMidPrice(product) =>
+        write!(f, "no {} mid price to check order against", product),
+      RiskViolation::OpenOrders{max} =>
+        write!(f, "order would exceed limit of {} open orders", max),
+      RiskViolation::Position{product, position, max} =>
+        write!(f, "potential {} position {} exceeds limit of {}", product, position, max),
+      RiskViolation::PriceBand{price, mid} =>
+        write!(f, "order price {} is outside price band around mid {}", price, mid),
+      RiskViolation::MessageRate{max} =>
+        write!(f, "order would exceed limit of {} messages per second", max),
+      RiskViolation::StaleFeed{seconds} =>
+        write!(f, "feed was silent for {} seconds", seconds),
+    }
+  }
+}
+
+impl Error for RiskViolation {
+  fn description(&self) -> &str {
+    match *self {
+      RiskViolation::OrderSize{..}   => "order size limit exceeded",
+      RiskViolation::Notional{..}    => "order notional limit exceeded",
+      RiskViolation::NoMidPrice(_)   => "no mid price to check order against",
+      RiskViolation::OpenOrders{..}  => "open orders limit exceeded",
+      RiskViolation::Position{..}    => "position limit exceeded",
+      RiskViolation::PriceBand{..}   => "order price outside price band",
+      RiskViolation::MessageRate{..} => "message rate limit exceeded",
+      RiskViolation::StaleFeed{..}   => "stale feed",
+    }
+  }
+}
+
+/// Checks orders against `RiskLimits`, tracking the message rate they
+/// depend on. The first violation trips the kill switch, after
+/// which `breach` returns it.
+///
+/// Times come from the feed, so checks behave the same in replays, and from
+/// the wall clock before the first timestamped message, which level 2 only
+/// sessions and the `place` command may never see. A feed that stops entirely is only detected as stale when it resumes, unless
+/// the silence is also checked against the wall clock with `check_silence`.
+#[derive(Debug)]
+pub struct RiskManager {
+  limits:   RiskLimits,
+  messages: VecDeque<DateTime>,
+  breach:   Option<RiskViolation>,
+}
+
+impl RiskManager {
+  pub fn new(limits: RiskLimits) -> RiskManager {
+    RiskManager {
+      messages: VecDeque::new(),
+      breach:   None,
+      limits,
+    }
+  }
+
+  /// The violation that tripped the kill switch, if any
+  pub fn breach(&self) -> Option<&RiskViolation> {
+    self.breach.as_ref()
+  }
+
+  fn trip<T>(&mut self, violation: RiskViolation) -> Result<T, RiskViolation> {
+    if self.breach.is_none() {
+      self.breach = Some(violation.clone());
+    }
+    Err(violation)
+  }
+
+  /// Longest allowed gap between feed messages, if limited
+  pub fn stale_after(&self) -> Option<Duration> {
+    self.limits.stale_after_seconds.map(|seconds| Duration::seconds(seconds.into()))
+  }
+
+  /// Check the gap between the latest feed message, at `previous`, and one
+  /// at `time`
+  pub fn check_feed(&mut self, previous: Option<DateTime>, time: DateTime) -> Result<(), RiskViolation> {
+    match previous {
+      Some(previous) => self.check_silence(time.signed_duration_since(previous)),
+      None           => Ok(()),
+    }
+  }
+
+  /// Check that the feed hasn't been silent for too long
+  pub fn check_silence(&mut self, silent: Duration) -> Result<(), RiskViolation> {
+    match self.stale_after() {
+      Some(stale_after) if silent > stale_after => self.trip(RiskViolation::StaleFeed{seconds: silent.num_seconds()}),
+      _                                         => Ok(()),
+    }
+  }
+
+  /// Count a message sent at `now`, or at the wall clock time if there's no
+  /// feed time yet, against the rate limit
+  fn check_rate(&mut self, now: Option<DateTime>) -> Result<(), RiskViolation> {
+    if let Some(max) = self.limits.max_messages_per_second {
+      let now = now.unwrap_or_else(Utc::now);
+      // wall clock times counted before the first feed time may be later than it
+      self.messages.retain(|&time| time <= now && now.signed_duration_since(time) < Duration::seconds(1));
+      if self.messages.len() >= max {
+        return self.trip(RiskViolation::MessageRate{max});
+      }
+      self.messages.push_back(now);
+    }
+    Ok(())
+  }
+
+  /// Check a cancel, which passes unchecked after the kill switch trips
+  pub fn check_cancel(&mut self, context: &Context) -> Result<(), RiskViolation> {
+    if self.breach.is_some() {
+      return Ok(());
+    }
+    self.check_rate(context.now())
+  }
+
+  /// Check an order, which fails with the breach after the kill switch trips
+  pub fn check_place(&mut self, context: &Context, order: &NewOrder) -> Result<(), RiskViolation> {
+    if let Some(ref breach) = self.breach {
+      return Err(breach.clone());
+    }
+
+    let product = order.product_id;
+
+    if let Some(&max) = self.limits.max_order_size.get(&product) {
+      if order.size > max {
+        return self.trip(RiskViolation::OrderSize{product, size: order.size, max});
+      }
+    }
+
+    let mid = context.book(product).and_then(|book| book.mid_price());
+
+    if let Some(max) = self.limits.max_notional {
+      let notional = match order.price.or(mid) {
+        Some(price) => price.notional(order.size),
+        None        => return self.trip(RiskViolation::NoMidPrice(product)),
+      };
+      if notional > max {
+        return self.trip(RiskViolation::Notional{notional, max});
+      }
+    }
+
+    if let Some(band) = self.limits.price_band {
+      if let Some(price) = order.price {
+        let mid = match mid {
+          Some(mid) => mid,
+          None      => return self.trip(RiskViolation::NoMidPrice(product)),
+        };
+        let distance = price.checked_sub(mid).or_else(|| mid.checked_sub(price)).unwrap();
+        let outside = Decimal::from(distance) > Decimal::from(mid).mul_rounded(band, Rounding::Floor);
+        if outside {
+          return self.trip(RiskViolation::PriceBand{price, mid});
+        }
+      }
+    }
+
+    if let Some(max) = self.limits.max_open_orders {
+      if context.open_orders().len() >= max {
+        return self.trip(RiskViolation::OpenOrders{max});
+      }
+    }
+
+    if let Some(&max) = self.limits.max_position.get(&product) {
+      let signed = |order: &NewOrder| {
+        let size = SignedDecimal::from(Decimal::from(order.size));
+        match order.side {
+          Side::Buy  => size,
+          Side::Sell => -size,
+        }
+      };
+      let filled = context.ledger().position(product)
+        .map(|position| position.size)
+        .unwrap_or_else(SignedDecimal::zero);
+      let position = context.open_orders().values()
+        .filter(|open| open.product_id == product && open.side == order.side)
+        .fold(filled + signed(order), |position, open| position + signed(open));
+      if position.abs() > Decimal::from(max) {
+        return self.trip(RiskViolation::Position{product, position, max});
+      }
+    }
+
+    self.check_rate(context.now())
+  }
+}
+
+/// Checks orders against risk limits before passing them to another
+/// `OrderEntry`, refusing them with `OrderError::Risk`. After the kill
+/// switch trips, every order is refused, and cancels pass unchecked.
+///
+/// Orders placed through the `OrderEntry` methods are checked against
+/// market and order state from the messages it observes, and count as open
+/// until they're canceled or a `done` message or event for them. Those
+/// placed with `place_in` are checked against the given context instead.
+pub struct CheckedOrderEntry<E> {
+  inner:    E,
+  risk:     RiskManager,
+  context:  Context,
+  placed:   Rc<RefCell<Vec<(Uuid, NewOrder)>>>,
+  canceled: Rc<RefCell<Vec<Uuid>>>,
+}
+
+impl<E: OrderEntry> CheckedOrderEntry<E> {
+  pub fn new(inner: E, limits: RiskLimits) -> CheckedOrderEntry<E> {
+    CheckedOrderEntry {
+      risk:     RiskManager::new(limits),
+      context:  Context::default(),
+      placed:   Rc::new(RefCell::new(vec![])),
+      canceled: Rc::new(RefCell::new(vec![])),
+      inner,
+    }
+  }
+
+  pub fn inner(&self) -> &E {
+    &self.inner
+  }
+
+  pub fn inner_mut(&mut self) -> &mut E {
+    &mut self.inner
+  }
+
+  pub fn into_inner(self) -> E {
+    self.inner
+  }
+
+  pub fn risk(&self) -> &RiskManager {
+    &self.risk
+  }
+
+  pub fn risk_mut(&mut self) -> &mut RiskManager {
+    &mut self.risk
+  }
+
+  /// Market and order state that orders placed through the `OrderEntry`
+  /// methods are checked against
+  pub fn context(&self) -> &Context {
+    &self.context
+  }
+
+  /// Place `order` if it passes the checks against `context`
+  pub fn place_in(&mut self, context: &Context, order: NewOrder) -> OrderFuture<PlacedOrder> {
+    match self.risk.check_place(context, &order) {
+      Ok(())         => self.inner.place(order),
+      Err(violation) => Box::new(future::err(OrderError::Risk(violation))),
+    }
+  }
+
+  /// Cancel order `id` if it passes the checks against `context`
+  pub fn cancel_in(&mut self, context: &Context, id: Uuid) -> OrderFuture<()> {
+    match self.risk.check_cancel(context) {
+      Ok(())         => self.inner.cancel(id),
+      Err(violation) => Box::new(future::err(OrderError::Risk(violation))),
+    }
+  }
+
+  /// Cancel open orders if it passes the checks against `context`
+  pub fn cancel_all_in(&mut self, context: &Context, product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+    match self.risk.check_cancel(context) {
+      Ok(())         => self.inner.cancel_all(product),
+      Err(violation) => Box::new(future::err(OrderError::Risk(violation))),
+    }
+  }
+
+  /// Count orders placed through the `OrderEntry` methods as open, and
+  /// those canceled through them as finished
+  fn sync(&mut self) {
+    for (id, order) in self.placed.borrow_mut().drain(..) {
+      self.context.placed(id, order);
+    }
+    for id in self.canceled.borrow_mut().drain(..) {
+      self.context.finished(id);
+    }
+  }
+}
+
+impl<E: OrderEntry> OrderEntry for CheckedOrderEntry<E> {
+  fn place(&mut self, order: NewOrder) -> OrderFuture<PlacedOrder> {
+    self.sync();
+    if let Err(violation) = self.risk.check_place(&self.context, &order) {
+      return Box::new(future::err(OrderError::Risk(violation)));
+    }
+    let placed = self.placed.clone();
+    Box::new(self.inner.place(order.clone()).map(move |acknowledged| {
+      placed.borrow_mut().push((acknowledged.id, order));
+      acknowledged
+    }))
+  }
+
+  fn cancel(&mut self, id: Uuid) -> OrderFuture<()> {
+    self.sync();
+    if let Err(violation) = self.risk.check_cancel(&self.context) {
+      return Box::new(future::err(OrderError::Risk(violation)));
+    }
+    let canceled = self.canceled.clone();
+    Box::new(self.inner.cancel(id).map(move |()| canceled.borrow_mut().push(id)))
+  }
+
+  fn cancel_all(&mut self, product: Option<Product>) -> OrderFuture<Vec<Uuid>> {
+    self.sync();
+    if let Err(violation) = self.risk.check_cancel(&self.context) {
+      return Box::new(future::err(OrderError::Risk(violation)));
+    }
+    let canceled = self.canceled.clone();
+    Box::new(self.inner.cancel_all(product).map(move |ids| {
+      canceled.borrow_mut().extend(ids.iter().cloned());
+      ids
+    }))
+  }
+
+  fn observe(&mut self, message: &IncomingMessage) {
+    self.sync();
+    self.context.observe(message);
+    self.inner.observe(message);
+  }
+
+  fn discontinuity(&mut self) {
+    self.context.discontinuity();
+    self.inner.discontinuity();
+  }
+
+  fn events(&mut self) -> Vec<OrderEvent> {
+    self.sync();
+    let events = self.inner.events();
+    for event in &events {
+      match *event {
+        OrderEvent::Fill(ref fill) => {
+          self.context.fill(fill);
+        }
+        OrderEvent::Done(id) => self.context.finished(id),
+      }
+    }
+    events
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{TimeZone, Utc};
+  use gdax::order_entry::DryRunOrderEntry;
+  use gdax::fixtures::{products, snapshot};
+  use gdax::rest::message::{Fill, Liquidity};
+  use serde_json;
+
+  fn limits(json: &str) -> RiskManager {
+    RiskManager::new(serde_json::from_str(json).unwrap())
+  }
+
+  fn order(side: Side, size: &str) -> NewOrder {
+    NewOrder::limit("BTC-USD".parse().unwrap(), side, "100.00".parse().unwrap(), size.parse().unwrap())
+  }
+
+  #[test]
+  fn order_size() {
+    let mut risk = limits(r#"{"max_order_size": {"BTC-USD": "1.0"}}"#);
+    let context = Context::default();
+    assert_eq!(risk.check_place(&context, &order(Side::Buy, "1.0")), Ok(()));
+    assert!(risk.breach().is_none());
+    assert_eq!(
+      risk.check_place(&context, &order(Side::Buy, "1.5")),
+      Err(RiskViolation::OrderSize{
+        product: "BTC-USD".parse().unwrap(),
+        size:    "1.5".parse().unwrap(),
+        max:     "1.0".parse().unwrap(),
+      }),
+    );
+    assert!(risk.breach().is_some());
+  }
+
+  #[test]
+  fn position() {
+    let mut risk = limits(r#"{"max_position": {"BTC-USD": "2.0"}}"#);
+    let mut context = Context::default();
+    context.fill(&Fill {
+      trade_id:   1,
+      product_id: "BTC-USD".parse().unwrap(),
+      order_id:   Uuid::nil(),
+      price:      "100.00".parse().unwrap(),
+      size:       "1.5".parse().unwrap(),
+      side:       Side::Sell,
+      liquidity:  Liquidity::Maker,
+      fee:        QuoteCurrencyAmount::zero(),
+      created_at: Utc.timestamp(0, 0),
+      settled:    true,
+    });
+    assert_eq!(risk.check_place(&context, &order(Side::Buy, "3.5")), Ok(()));
+    assert!(risk.check_place(&context, &order(Side::Sell, "1.0")).is_err());
+  }
+
+  #[test]
+  fn market_order_without_mid() {
+    let mut risk = limits(r#"{"max_notional": "1000"}"#);
+    let order = NewOrder::market("BTC-USD".parse().unwrap(), Side::Buy, "1.0".parse().unwrap());
+    assert_eq!(
+      risk.check_place(&Context::default(), &order),
+      Err(RiskViolation::NoMidPrice("BTC-USD".parse().unwrap())),
+    );
+  }
+
+  #[test]
+  fn message_rate_without_feed_time() {
+    let mut risk = limits(r#"{"max_messages_per_second": 1}"#);
+    let context = Context::default();
+    assert_eq!(risk.check_cancel(&context), Ok(()));
+    assert_eq!(risk.check_place(&context, &order(Side::Buy, "1.0")), Err(RiskViolation::MessageRate{max: 1}));
+  }
+
+  #[test]
+  fn stale_feed() {
+    let mut risk = limits(r#"{"stale_after_seconds": 5}"#);
+    assert_eq!(risk.check_feed(None, Utc.timestamp(0, 0)), Ok(()));
+    assert_eq!(risk.check_feed(Some(Utc.timestamp(0, 0)), Utc.timestamp(5, 0)), Ok(()));
+    assert_eq!(
+      risk.check_feed(Some(Utc.timestamp(5, 0)), Utc.timestamp(11, 0)),
+      Err(RiskViolation::StaleFeed{seconds: 6}),
+    );
+  }
+
+  #[test]
+  fn checked_order_entry() {
+    let limits = serde_json::from_str(r#"{"price_band": "0.05", "max_open_orders": 1}"#).unwrap();
+    let mut entry = CheckedOrderEntry::new(DryRunOrderEntry::new(products()), limits);
+
+    entry.observe(&snapshot());
+    entry.place(order(Side::Buy, "1.0")).wait().unwrap();
+    assert_eq!(entry.cancel_all(None).wait().unwrap().len(), 1);
+    entry.place(order(Side::Buy, "1.0")).wait().unwrap();
+
+    match entry.place(order(Side::Sell, "1.0")).wait() {
+      Err(OrderError::Risk(RiskViolation::OpenOrders{max: 1})) => {}
+      other => panic!("expected open orders violation: {:?}", other),
+    }
+    assert!(entry.place(order(Side::Sell, "1.0")).wait().is_err());
+    assert_eq!(entry.cancel_all(None).wait().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn unknown_limit() {
+    assert!(serde_json::from_str::<RiskLimits>(r#"{"max_size": "1.0"}"#).is_err());
+  }
+}
@@ -0,0 +1,53 @@
+use engine::Context;
+use gdax::feed::message::{BaseCurrencyAmount, Product, Side};
+use gdax::order_book::OrderBook;
+use gdax::rest::message::NewOrder;
+use strategy::{Intent, Strategy};
+
+/// Quotes a post-only bid and ask of a fixed size at the top of the book,
+/// moving them when the best prices change. Quotes are only replaced once
+/// their placement is acknowledged, so it's meant for order entries that
+/// acknowledge orders promptly, like the simulator.
+pub struct MarketMaker {
+  product: Product,
+  size:    BaseCurrencyAmount,
+}
+
+impl MarketMaker {
+  pub fn new(product: Product, size: BaseCurrencyAmount) -> MarketMaker {
+    MarketMaker{product, size}
+  }
+}
+
+impl Strategy for MarketMaker {
+  fn on_book(&mut self, context: &Context, product: Product, book: &OrderBook) -> Vec<Intent> {
+    if product != self.product {
+      return vec![];
+    }
+
+    let mut intents = vec![];
+    for &(side, best) in &[(Side::Buy, book.best_bid()), (Side::Sell, book.best_ask())] {
+      let best = match best {
+        Some((price, _)) => price,
+        None             => continue,
+      };
+
+      let mut quoted = false;
+      for (id, order) in context.open_orders() {
+        if order.product_id != product || order.side != side {
+          continue;
+        }
+        if order.price == Some(best) && !quoted {
+          quoted = true;
+        } else {
+          intents.push(Intent::Cancel(*id));
+        }
+      }
+
+      if !quoted {
+        intents.push(Intent::Place(NewOrder::limit(product, side, best, self.size).post_only()));
+      }
+    }
+    intents
+  }
+}
@@ -0,0 +1,47 @@
+use uuid::Uuid;
+
+use engine::Context;
+use gdax::feed::message::{DateTime, Product, Ticker};
+use gdax::order_book::OrderBook;
+use gdax::rest::message::{Fill, NewOrder, Trade};
+
+mod market_maker;
+
+pub use self::market_maker::MarketMaker;
+
+/// An order entry request made by a strategy
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intent {
+  Place(NewOrder),
+  Cancel(Uuid),
+  /// Cancel open orders for a product, or for all products
+  CancelAll(Option<Product>),
+}
+
+/// A trading strategy, called by an `Engine` as market events arrive. Each
+/// hook returns the orders to place or cancel in response.
+pub trait Strategy {
+  /// The level 2 book for `product` changed
+  fn on_book(&mut self, _context: &Context, _product: Product, _book: &OrderBook) -> Vec<Intent> {
+    vec![]
+  }
+
+  /// A trade of `product` took place
+  fn on_trade(&mut self, _context: &Context, _product: Product, _trade: &Trade) -> Vec<Intent> {
+    vec![]
+  }
+
+  fn on_ticker(&mut self, _context: &Context, _ticker: &Ticker) -> Vec<Intent> {
+    vec![]
+  }
+
+  /// The engine's timer interval elapsed
+  fn on_timer(&mut self, _context: &Context, _now: DateTime) -> Vec<Intent> {
+    vec![]
+  }
+
+  /// One of the strategy's orders filled
+  fn on_fill(&mut self, _context: &Context, _fill: &Fill) -> Vec<Intent> {
+    vec![]
+  }
+}